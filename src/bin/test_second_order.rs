@@ -15,6 +15,8 @@ fn main() {
     println!("Initializing signal chain...");
     let mut graph = DeviceGraph::new();
     let wavf = WavReader::open("wav/delta.wav").unwrap();
+    let sample_rate = wavf.get_sample_rate();
+    graph.set_sample_rate(sample_rate);
     let wav_in = graph.add_node(wavf);
     let cutoff = 1000.0;
 
@@ -44,6 +46,6 @@ fn main() {
 
     // Write files
     println!("Writing second order filters to wav files...");
-    graph.tick_n_times(44100);
+    graph.tick_n_times(sample_rate as u64);
     println!("Done!");
 }
@@ -25,34 +25,92 @@
 //! );
 //! chain.tick_forever();
 //! ```
+//!
+//! # Changing a chain
+//!
+//! Devices can be inserted, removed or replaced at any position once a chain
+//! is built. These methods return an Err instead of panicking when the
+//! channels of neighbouring devices don't match. With
+//! `DeviceChain::set_adapt_channels`, mismatched devices are instead joined
+//! by a `ChannelAdapter`, and `DeviceChain::set_adapter` sets a custom mixing
+//! matrix between two devices.
+//!
+//! # Nesting chains
+//!
+//! A chain is itself an `AudioDevice`, taking the inputs of its first device
+//! and returning the outputs of its last. This lets a series of effects be
+//! built once, then added as a single device to another chain or a graph. The
+//! chain can also be wrapped in `Messaged`, to set the parameters of its
+//! devices from another thread.
+//!
+//! Since both `AudioDevice` and `Tick` define a `tick` method, code that has
+//! both traits in scope must call `Tick::tick(&mut chain)` to process a block.
+
 
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use types::{AudioDevice, Sample, Time};
+use error::{Error, Result};
+use mixers::ChannelAdapter;
+use parameters::Parameters;
+use transport::{Transport, TransportHandle};
+use types::{SAMPLE_RATE, AnyDevice, AudioDevice, MessageReceiver, Sample,
+            SampleFloat, Time};
 pub use tick::Tick;
 
 
+/// The id of the next chain created.
+static NEXT_CHAIN_ID: AtomicUsize = AtomicUsize::new(0);
+
 /// A container for a series of audio devices.
-pub struct DeviceChain {
-    bus: Vec<Sample>,
-    devices: Vec<AudioNode>,
+///
+/// Each tick of the chain processes one block of audio. By default a block is
+/// a single frame; a larger block size can be set using `set_block_size`, so
+/// that each device processes many frames at once.
+///
+/// The chain also holds the sample rate for all its devices. Every device
+/// added to the chain is prepared with the chain's sample rate and block size.
+///
+/// Every device shares the chain's transport, which advances after each tick
+/// while it is playing.
+///
+/// When the chain is used as an `AudioDevice` inside another container, it
+/// processes whatever block its container passes it, and shares the
+/// container's transport instead of advancing its own.
+///
+/// All devices in a chain process samples of the same precision, `S`.
+pub struct DeviceChain<S: SampleFloat = Sample> {
+    input: Vec<S>,
+    devices: Vec<AudioNode<S>>,
+    block_size: usize,
+    sample_rate: u32,
+    transport: TransportHandle,
+    adapt_channels: bool,
+    id: usize, // unique among every chain, and stored in its handles
+    next_id: usize, // the id of the next device added
     time: Time
 }
 
-impl DeviceChain {
+impl<S: SampleFloat> DeviceChain<S> {
     /// Creates a new chain that starts from the provided device. This device
     /// will receive no inputs unless they are manually supplied using
     /// DeviceChain::get_input.
-    pub fn from<D>(device: D) -> Self where D: 'static+AudioDevice {
-        let mut chain = DeviceChain {
-            bus: Vec::new(),
-            devices: Vec::new(),
+    pub fn from<D>(mut device: D) -> Self where D: 'static+AudioDevice<S> {
+        let transport = Transport::new_handle(SAMPLE_RATE);
+        device.prepare(SAMPLE_RATE, 1);
+        device.set_transport(transport.clone());
+        DeviceChain {
+            input: vec![S::zero(); device.num_inputs()],
+            devices: vec![AudioNode::new(device, 0, 1)],
+            block_size: 1,
+            sample_rate: SAMPLE_RATE,
+            transport: transport,
+            adapt_channels: false,
+            id: NEXT_CHAIN_ID.fetch_add(1, Ordering::Relaxed),
+            next_id: 1,
             time: 0
-        };
-        for _ in 0..device.num_inputs() {
-            chain.bus.push(0.0);
         }
-        chain.devices.push(AudioNode::new(device, &mut chain.bus));
-        chain
     }
 
     /// Appends the provided device to the end of the chain. This device will be
@@ -62,82 +120,598 @@ impl DeviceChain {
     /// # Panics
     ///
     /// Panics if the provided device does not have as many inputs as the
-    /// previous device has outputs.
-    pub fn into<D>(mut self, device: D) -> Self where D: 'static+AudioDevice {
-        if self.devices[self.devices.len()-1].device.num_outputs() !=
-                device.num_inputs() {
+    /// previous device has outputs, unless the chain adapts channels.
+    pub fn into<D>(mut self, device: D) -> Self
+            where D: 'static+AudioDevice<S> {
+        if self.push(device).is_err() {
             panic!("DeviceChain: number of outputs must match number of inputs");
         }
-        self.devices.push(AudioNode::new(device, &mut self.bus));
         self
     }
 
+    /// Sets whether the chain adapts channels, then returns the same chain.
+    ///
+    /// See `set_adapt_channels`.
+    pub fn adapt_channels(mut self, adapt: bool) -> Self {
+        self.adapt_channels = adapt;
+        self
+    }
+
+    /// Returns true if the chain adapts channels.
+    pub fn get_adapt_channels(&self) -> bool {
+        self.adapt_channels
+    }
+
+    /// Sets whether the chain adapts channels between devices.
+    ///
+    /// By default, each device must have as many inputs as the previous device
+    /// has outputs. When channels are adapted, devices may be connected with
+    /// different numbers of channels, and a `ChannelAdapter` is placed between
+    /// them: mono signals are duplicated, and several channels are averaged
+    /// into mono.
+    ///
+    /// This only applies to devices connected after the change.
+    pub fn set_adapt_channels(&mut self, adapt: bool) {
+        self.adapt_channels = adapt;
+    }
+
+    /// Returns the number of devices in the chain.
+    pub fn num_devices(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// Appends a device to the end of the chain, like `into`. Returns an Err
+    /// if the device's inputs don't match the outputs of the last device.
+    pub fn push<D>(&mut self, device: D) -> Result<()>
+            where D: 'static+AudioDevice<S> {
+        let i = self.devices.len();
+        self.insert(i, device)
+    }
+
+    /// Inserts a device at position `i` in the chain, moving the devices after
+    /// it back by one.
+    ///
+    /// The device's inputs must match the outputs of the device before it, and
+    /// its outputs must match the inputs of the device after it, unless the
+    /// chain adapts channels. Otherwise, or if `i` is past the end of the
+    /// chain, an Err is returned and the chain is not changed.
+    pub fn insert<D>(&mut self, i: usize, mut device: D) -> Result<()>
+            where D: 'static+AudioDevice<S> {
+        if i > self.devices.len() {
+            return Err(Error::OutOfRange("device"));
+        }
+        let adapter = if i > 0 {
+            let channels = self.devices[i-1].device.num_outputs();
+            try!(self.fit(channels, device.num_inputs(), None))
+        } else {
+            None
+        };
+        let next_adapter = match self.devices.get(i) {
+            Some(next) => Some(try!(self.fit(device.num_outputs(),
+                                             next.device.num_inputs(),
+                                             next.adapter.as_ref()))),
+            None => None
+        };
+
+        device.prepare(self.sample_rate, self.block_size);
+        device.set_transport(self.transport.clone());
+        let mut node = AudioNode::new(device, self.next_id, self.block_size);
+        self.next_id += 1;
+        node.set_adapter(adapter, self.block_size);
+        if let Some(adapter) = next_adapter {
+            self.devices[i].set_adapter(adapter, self.block_size);
+        }
+        self.devices.insert(i, node);
+        if i == 0 {
+            self.resize_input();
+        }
+        Ok(())
+    }
+
+    /// Removes the device at position `i` from the chain, moving the devices
+    /// after it forward by one. Returns the device.
+    ///
+    /// The devices on either side must then connect, unless the chain adapts
+    /// channels. Otherwise, if `i` is past the end of the chain, or if the
+    /// device is the only one in the chain, an Err is returned and the chain
+    /// is not changed.
+    pub fn remove(&mut self, i: usize) -> Result<Box<AnyDevice<S>>> {
+        if i >= self.devices.len() || self.devices.len() == 1 {
+            return Err(Error::OutOfRange("device"));
+        }
+        let next_adapter = match (i.checked_sub(1), self.devices.get(i+1)) {
+            (Some(prev), Some(next)) =>
+                Some(try!(self.fit(self.devices[prev].device.num_outputs(),
+                                   next.device.num_inputs(),
+                                   next.adapter.as_ref()))),
+            _ => None
+        };
+
+        if let Some(adapter) = next_adapter {
+            self.devices[i+1].set_adapter(adapter, self.block_size);
+        }
+        let node = self.devices.remove(i);
+        if i == 0 {
+            self.resize_input();
+        }
+        Ok(node.device)
+    }
+
+    /// Replaces the device at position `i` in the chain. Returns the old
+    /// device.
+    ///
+    /// The new device must connect to the devices on either side, unless the
+    /// chain adapts channels. Otherwise, or if `i` is past the end of the
+    /// chain, an Err is returned and the chain is not changed.
+    pub fn replace<D>(&mut self, i: usize, mut device: D)
+            -> Result<Box<AnyDevice<S>>> where D: 'static+AudioDevice<S> {
+        if i >= self.devices.len() {
+            return Err(Error::OutOfRange("device"));
+        }
+        let adapter = if i > 0 {
+            let channels = self.devices[i-1].device.num_outputs();
+            try!(self.fit(channels, device.num_inputs(),
+                          self.devices[i].adapter.as_ref()))
+        } else {
+            None
+        };
+        let next_adapter = match self.devices.get(i+1) {
+            Some(next) => Some(try!(self.fit(device.num_outputs(),
+                                             next.device.num_inputs(),
+                                             next.adapter.as_ref()))),
+            None => None
+        };
+
+        device.prepare(self.sample_rate, self.block_size);
+        device.set_transport(self.transport.clone());
+        let mut node = AudioNode::new(device, self.next_id, self.block_size);
+        self.next_id += 1;
+        node.set_adapter(adapter, self.block_size);
+        if let Some(adapter) = next_adapter {
+            self.devices[i+1].set_adapter(adapter, self.block_size);
+        }
+        let old = ::std::mem::replace(&mut self.devices[i], node);
+        if i == 0 {
+            self.resize_input();
+        }
+        Ok(old.device)
+    }
+
+    /// Sets the adapter that feeds the outputs of the previous device into the
+    /// inputs of the device at position `i`, or removes it with `None`.
+    ///
+    /// The adapter must have as many inputs as the previous device has
+    /// outputs, and as many outputs as the device has inputs. Otherwise, or if
+    /// `i` is past the end of the chain, an Err is returned. An adapter for the
+    /// first device sets the number of inputs of the chain.
+    pub fn set_adapter(&mut self, i: usize, adapter: Option<ChannelAdapter<S>>)
+            -> Result<()> {
+        if i >= self.devices.len() {
+            return Err(Error::OutOfRange("device"));
+        }
+        let num_in = self.devices[i].device.num_inputs();
+        let (inputs, outputs) = match adapter {
+            Some(ref adapter) => (adapter.num_inputs(), adapter.num_outputs()),
+            None => (num_in, num_in)
+        };
+        if outputs != num_in ||
+                (i > 0 && inputs != self.devices[i-1].device.num_outputs()) {
+            return Err(Error::OutOfRange("channel"));
+        }
+        self.devices[i].set_adapter(adapter, self.block_size);
+        if i == 0 {
+            self.resize_input();
+        }
+        Ok(())
+    }
+
+    /// Returns the number of frames processed by each tick.
+    pub fn get_block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Sets the number of frames processed by each tick, and prepares every
+    /// device in the chain for the new block size.
+    ///
+    /// The input and output buffers are reallocated, and will be zeroed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frames` is zero.
+    pub fn set_block_size(&mut self, frames: usize) {
+        let sample_rate = self.sample_rate;
+        self.prepare(sample_rate, frames);
+    }
+
+    /// Returns the sample rate of the chain, in Hz.
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Sets the sample rate of the chain, in Hz, and prepares every device in
+    /// the chain for the new rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        let block_size = self.block_size;
+        self.prepare(sample_rate, block_size);
+    }
+
+    /// Sets both the sample rate and block size of the chain, then prepares
+    /// every device in the chain for them.
+    ///
+    /// The input and output buffers are reallocated, and will be zeroed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is zero.
+    pub fn prepare(&mut self, sample_rate: u32, block_size: usize) {
+        if block_size == 0 {
+            panic!("DeviceChain: block size must be at least one frame");
+        }
+        self.sample_rate = sample_rate;
+        self.block_size = block_size;
+        self.transport.borrow_mut().set_sample_rate(sample_rate);
+        self.resize_input();
+        for node in self.devices.iter_mut() {
+            node.device.prepare(sample_rate, block_size);
+            node.resize(block_size);
+        }
+    }
+
+    /// Resets every device in the chain, and zeroes the input and output
+    /// buffers.
+    pub fn reset(&mut self) {
+        for s in self.input.iter_mut() {
+            *s = S::zero();
+        }
+        for node in self.devices.iter_mut() {
+            node.device.reset();
+            for s in node.outputs.iter_mut() {
+                *s = S::zero();
+            }
+        }
+    }
+
+    /// Returns a handle to the transport shared by every device in the chain.
+    pub fn get_transport(&self) -> TransportHandle {
+        self.transport.clone()
+    }
+
+    /// Returns the total latency of the chain, in samples.
+    ///
+    /// This is the sum of the latencies reported by each device.
+    pub fn latency(&self) -> usize {
+        self.devices.iter().map(|node| node.device.latency()).sum()
+    }
+
+    /// Returns the parameters of the `i`th device in the chain, or `None` if
+    /// the device has no parameters or doesn't exist.
+    pub fn get_parameters(&self, i: usize) -> Option<&Parameters> {
+        self.devices.get(i).and_then(|node| node.device.parameters())
+    }
+
+    /// Returns the parameters of the `i`th device in the chain mutably, or
+    /// `None` if the device has no parameters or doesn't exist.
+    pub fn get_parameters_mut(&mut self, i: usize) -> Option<&mut Parameters> {
+        self.devices.get_mut(i).and_then(|node| node.device.parameters_mut())
+    }
+
+    /// Returns a handle to the `i`th device in the chain, or `None` if the
+    /// device doesn't exist or doesn't have type `D`.
+    pub fn get_handle<D>(&self, i: usize) -> Option<DeviceHandle<D>>
+            where D: 'static+AudioDevice<S> {
+        self.devices.get(i)
+            .and_then(|node| (*node.device).as_any().downcast_ref::<D>()
+                      .map(|_| DeviceHandle { chain: self.id, id: node.id,
+                                              device: PhantomData }))
+    }
+
+    /// Returns the current position of a device in the chain, or `None` if
+    /// the device has been removed or replaced, or the handle is from another
+    /// chain.
+    pub fn get_index<D>(&self, handle: DeviceHandle<D>) -> Option<usize> {
+        if handle.chain != self.id {
+            return None;
+        }
+        self.devices.iter().position(|node| node.id == handle.id)
+    }
+
+    /// Returns a device in the chain, or `None` if the device has been removed
+    /// or replaced.
+    pub fn get_device<D>(&self, handle: DeviceHandle<D>) -> Option<&D>
+            where D: 'static+AudioDevice<S> {
+        self.get_index(handle)
+            .and_then(|i| (*self.devices[i].device).as_any().downcast_ref())
+    }
+
+    /// Returns a device in the chain mutably, or `None` if the device has been
+    /// removed or replaced.
+    pub fn get_device_mut<D>(&mut self, handle: DeviceHandle<D>)
+            -> Option<&mut D> where D: 'static+AudioDevice<S> {
+        match self.get_index(handle) {
+            Some(i) => (*self.devices[i].device).as_any_mut().downcast_mut(),
+            None => None
+        }
+    }
+
+    /// Passes a message straight to a device in the chain. Returns an Err if
+    /// the device has been removed or replaced.
+    ///
+    /// The message is handled immediately, so this must be called from the
+    /// thread that ticks the chain.
+    pub fn send_message<D>(&mut self, handle: DeviceHandle<D>, msg: D::Msg)
+            -> Result<()> where D: 'static+AudioDevice<S>+MessageReceiver {
+        match self.get_device_mut(handle) {
+            Some(device) => {
+                device.handle_message(msg);
+                Ok(())
+            },
+            None => Err(Error::OutOfRange("device"))
+        }
+    }
+
     /// Return a mutable slice to the input of the first device in the chain.
     ///
-    /// These inputs never get overwritten, so if you are supplying input you
-    /// must manually zero the buffer again.
-    pub fn get_input(&mut self) -> &mut[Sample] {
-        &mut self.bus[..self.devices[0].device.num_inputs()]
+    /// The input holds one block of interleaved frames. These inputs never get
+    /// overwritten, so if you are supplying input you must manually zero the
+    /// buffer again.
+    pub fn get_input(&mut self) -> &mut[S] {
+        &mut self.input
     }
 
     /// Returns a slice to the output of the last device in the chain.
-    pub fn get_output(&self) -> &[Sample] {
-        let outputs = self.devices[self.devices.len()-1].device.num_outputs();
-        &self.bus[self.bus.len()-outputs..]
+    ///
+    /// The output holds the last block of interleaved frames.
+    pub fn get_output(&self) -> &[S] {
+        &self.devices[self.devices.len()-1].outputs
     }
 }
 
-impl Tick for DeviceChain {
+impl<S: SampleFloat> DeviceChain<S> {
+    /// Returns the adapter for a device with `inputs` inputs, that is fed
+    /// `channels` channels. The current adapter is kept if it still fits.
+    /// Returns an Err if the channels don't match and can't be adapted.
+    fn fit(&self, channels: usize, inputs: usize,
+           adapter: Option<&ChannelAdapter<S>>)
+            -> Result<Option<ChannelAdapter<S>>> {
+        match adapter {
+            Some(adapter) if adapter.num_inputs() == channels &&
+                    adapter.num_outputs() == inputs =>
+                Ok(Some(adapter.clone())),
+            _ if channels == inputs => Ok(None),
+            _ if self.adapt_channels =>
+                Ok(Some(ChannelAdapter::new(channels, inputs))),
+            _ => Err(Error::OutOfRange("channel"))
+        }
+    }
+
+    /// Reallocates the input buffer to hold a block for the first device.
+    fn resize_input(&mut self) {
+        let num_in = self.devices[0].num_inputs();
+        self.input = vec![S::zero(); self.block_size*num_in];
+    }
+
+    /// Processes the first `frames` frames of a block, starting at time `t`.
+    fn process(&mut self, t: Time, frames: usize) {
+        let num_in = self.devices[0].num_inputs();
+        let mut inputs = &self.input[..frames*num_in];
+        for node in self.devices.iter_mut() {
+            if let Some(ref mut adapter) = node.adapter {
+                let n = node.device.num_inputs();
+                adapter.tick_block(t, frames, inputs,
+                                   &mut node.adapted[..frames*n]);
+                inputs = &node.adapted[..frames*n];
+            }
+            let num_out = node.device.num_outputs();
+            node.device.tick_block(t, frames, inputs,
+                                   &mut node.outputs[..frames*num_out]);
+            inputs = &node.outputs[..frames*num_out];
+        }
+    }
+}
+
+impl<S: SampleFloat> Tick for DeviceChain<S> {
     fn tick(&mut self) {
-        for device in self.devices.iter_mut() {
-            device.tick(self.time, &mut self.bus);
+        let (time, block_size) = (self.time, self.block_size);
+        self.process(time, block_size);
+        self.transport.borrow_mut().advance(self.block_size);
+        self.time += self.block_size as Time;
+    }
+}
+
+impl<S: SampleFloat> AudioDevice<S> for DeviceChain<S> {
+    fn num_inputs(&self) -> usize {
+        self.devices[0].num_inputs()
+    }
+
+    fn num_outputs(&self) -> usize {
+        self.devices[self.devices.len()-1].device.num_outputs()
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        DeviceChain::set_sample_rate(self, sample_rate);
+    }
+
+    fn prepare(&mut self, sample_rate: u32, block_size: usize) {
+        DeviceChain::prepare(self, sample_rate, block_size);
+    }
+
+    fn reset(&mut self) {
+        DeviceChain::reset(self);
+    }
+
+    fn latency(&self) -> usize {
+        DeviceChain::latency(self)
+    }
+
+    fn set_transport(&mut self, transport: TransportHandle) {
+        for node in self.devices.iter_mut() {
+            node.device.set_transport(transport.clone());
         }
-        self.time += 1;
+        self.transport = transport;
+    }
+
+    fn tick(&mut self, t: Time, inputs: &[S], outputs: &mut[S]) {
+        self.tick_block(t, 1, inputs, outputs);
+    }
+
+    fn tick_block(&mut self, t: Time, frames: usize, inputs: &[S],
+                  outputs: &mut[S]) {
+        let num_in = AudioDevice::<S>::num_inputs(self);
+        let num_out = AudioDevice::<S>::num_outputs(self);
+        self.input[..frames*num_in].copy_from_slice(&inputs[..frames*num_in]);
+        self.process(t, frames);
+        outputs[..frames*num_out].copy_from_slice(
+            &self.get_output()[..frames*num_out]);
     }
 }
 
 
-/// Wrap an audio device behind a pointer, and stores an index into the bus.
-struct AudioNode {
-    device: Box<AudioDevice>,
-    bus_start: usize,
-    bus_end: usize,
-    split_point: usize
+/// Defines the messages that a DeviceChain supports.
+#[derive(Clone, Copy, Debug)]
+pub enum Message {
+    /// Sets a parameter of a device, by the device's position in the chain,
+    /// parameter index and value. Messages for missing devices or parameters
+    /// are ignored.
+    SetParameter(usize, usize, f32),
 }
+pub use self::Message::*;
 
-impl AudioNode {
-    /// Wraps the provided audio device in a new node and allocate space for it
-    /// in the bus.
-    fn new<D>(device: D, bus: &mut Vec<Sample>) -> AudioNode
-            where D: 'static+AudioDevice {
-        let inputs = device.num_inputs();
-        let outputs = device.num_outputs();
-        let bus_start = bus.len() - inputs;
-        for _ in 0..outputs {
-            bus.push(0.0);
+impl<S: SampleFloat> MessageReceiver for DeviceChain<S> {
+    type Msg = Message;
+    fn handle_message(&mut self, msg: Message) {
+        match msg {
+            SetParameter(device, i, value) => {
+                if let Some(params) = self.get_parameters_mut(device) {
+                    if i < params.num_parameters() {
+                        params.set_parameter(i, value);
+                    }
+                }
+            }
         }
+    }
+}
+
+
+/// A handle to a device in a chain, that remembers the device's type.
+///
+/// Handles are returned by `DeviceChain::get_handle`, and give typed access to
+/// the device through `DeviceChain::get_device_mut`.
+///
+/// A handle follows its device as other devices are inserted or removed
+/// around it. Once its own device is removed or replaced, the handle no
+/// longer refers to any device; `DeviceChain::get_index` returns the device's
+/// current position. A handle never refers to a device in any other chain.
+pub struct DeviceHandle<D> {
+    chain: usize, // the id of the chain that returned the handle
+    id: usize, // the id given to the device when it was added
+    device: PhantomData<fn() -> D>
+}
+
+impl<D> Clone for DeviceHandle<D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D> Copy for DeviceHandle<D> {}
+
+impl<D> fmt::Debug for DeviceHandle<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DeviceHandle({}, {})", self.chain, self.id)
+    }
+}
+
+impl<D> PartialEq for DeviceHandle<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.chain == other.chain && self.id == other.id
+    }
+}
+
+
+/// Wrap an audio device behind a pointer, along with its output buffer.
+struct AudioNode<S: SampleFloat> {
+    device: Box<AnyDevice<S>>,
+    id: usize, // unique among every device added to the chain
+    adapter: Option<ChannelAdapter<S>>, // adapts the inputs to the device
+    adapted: Vec<S>, // the adapted inputs
+    outputs: Vec<S>
+}
+
+impl<S: SampleFloat> AudioNode<S> {
+    /// Wraps the provided audio device in a new node with the given id, and
+    /// allocates an output buffer for `frames` frames.
+    fn new<D>(device: D, id: usize, frames: usize) -> AudioNode<S>
+            where D: 'static+AudioDevice<S> {
+        let outputs = vec![S::zero(); frames*device.num_outputs()];
         AudioNode {
             device: Box::new(device),
-            bus_start: bus_start,
-            bus_end: bus_start + inputs + outputs,
-            split_point: inputs,
+            id: id,
+            adapter: None,
+            adapted: Vec::new(),
+            outputs: outputs
+        }
+    }
+
+    /// Returns the number of channels fed into the node.
+    fn num_inputs(&self) -> usize {
+        match self.adapter {
+            Some(ref adapter) => adapter.num_inputs(),
+            None => self.device.num_inputs()
         }
     }
 
-    /// Ticks the device one time step.
-    fn tick(&mut self, t: Time, bus: &mut [Sample]) {
-        let bus_slice = &mut bus[self.bus_start .. self.bus_end];
-        let (inputs, outputs) = bus_slice.split_at_mut(self.split_point);
-        self.device.tick(t, inputs, outputs);
+    /// Sets the adapter of the node, and reallocates the buffers to hold
+    /// `frames` frames.
+    fn set_adapter(&mut self, adapter: Option<ChannelAdapter<S>>,
+                   frames: usize) {
+        self.adapter = adapter;
+        self.resize(frames);
+    }
+
+    /// Reallocates the buffers to hold `frames` frames.
+    fn resize(&mut self, frames: usize) {
+        self.outputs = vec![S::zero(); frames*self.device.num_outputs()];
+        self.adapted = match self.adapter {
+            Some(_) => vec![S::zero(); frames*self.device.num_inputs()],
+            None => Vec::new()
+        };
     }
 }
 
 
 #[cfg(test)]
 mod test {
+    use error::Error;
+    use filters::second_order::{Filter, LowPass};
+    use mixers::{gain, ChannelAdapter, Gain};
+    use oscillator::{self, Oscillator, Sine};
+    use parameters::Parameters;
     use testing::MockAudioDevice;
+    use transport::TransportHandle;
+    use types::{self, Time};
     use super::{DeviceChain, Tick};
 
+    /// Outputs the position of the transport at each frame.
+    struct Position(Option<TransportHandle>);
+
+    impl types::AudioDevice for Position {
+        fn num_inputs(&self) -> usize { 0 }
+        fn num_outputs(&self) -> usize { 1 }
+        fn set_transport(&mut self, transport: TransportHandle) {
+            self.0 = Some(transport);
+        }
+        fn tick(&mut self, _: Time, _: &[f32], _: &mut[f32]) {
+            panic!("Position is only ticked in blocks");
+        }
+        fn tick_block(&mut self, _: Time, frames: usize, _: &[f32],
+                      outputs: &mut[f32]) {
+            let transport = self.0.as_ref().unwrap().borrow();
+            for (f, s) in outputs[..frames].iter_mut().enumerate() {
+                *s = transport.position_after(f) as f32;
+            }
+        }
+    }
+
     #[test]
     fn test_success() {
         let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
@@ -170,6 +744,185 @@ mod test {
         assert_eq!(chain.get_output(), [1.0]);
     }
 
+    #[test]
+    fn test_block() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 2);
+        mock1.will_tick(&[], &[1.0]).will_tick(&[], &[2.0]);
+        mock2.will_tick(&[1.0], &[3.0, 4.0]).will_tick(&[2.0], &[5.0, 6.0]);
+
+        let mut chain = DeviceChain::from(mock1).into(mock2);
+        chain.set_block_size(2);
+        chain.tick();
+        assert_eq!(chain.get_output(), [3.0, 4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut mock = MockAudioDevice::new("mock1", 0, 1);
+        mock.will_tick(&[], &[1.0]);
+
+        let mut chain = DeviceChain::from(mock);
+        chain.tick();
+        chain.reset();
+        assert_eq!(chain.get_output(), [0.0]);
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 1);
+        mock1.will_report_latency(3);
+        mock2.will_report_latency(4);
+
+        let chain = DeviceChain::from(mock1).into(mock2);
+        assert_eq!(chain.latency(), 7);
+    }
+
+    #[test]
+    fn test_transport() {
+        let mut chain = DeviceChain::from(Position(None));
+        chain.set_block_size(2);
+        let transport = chain.get_transport();
+        transport.borrow_mut().set_loop(1, 4);
+
+        chain.tick();
+        assert_eq!(chain.get_output(), [0.0, 0.0]);
+
+        transport.borrow_mut().play();
+        chain.tick();
+        assert_eq!(chain.get_output(), [0.0, 1.0]);
+        chain.tick();
+        assert_eq!(chain.get_output(), [2.0, 3.0]);
+        chain.tick();
+        assert_eq!(chain.get_output(), [1.0, 2.0]);
+        assert_eq!(transport.borrow().get_position(), 3);
+    }
+
+    #[test]
+    fn test_nested() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 1);
+        mock1.will_tick(&[], &[1.0]).will_tick(&[], &[2.0]);
+        mock2.will_tick(&[1.0], &[3.0]).will_tick(&[2.0], &[4.0]);
+
+        let inner = DeviceChain::from(mock1).into(mock2);
+        let mut chain = DeviceChain::from(inner);
+        chain.set_block_size(2);
+        chain.tick();
+        assert_eq!(chain.get_output(), [3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_handles() {
+        let mut chain = DeviceChain::from(Oscillator::new(Sine).freq(220.0))
+            .into(Gain::new(0.0, 1));
+        assert!(chain.get_handle::<Gain>(0).is_none());
+        assert!(chain.get_handle::<Gain>(2).is_none());
+        let osc = chain.get_handle::<Oscillator>(0).unwrap();
+        let gain = chain.get_handle::<Gain>(1).unwrap();
+        assert_eq!(chain.get_index(gain), Some(1));
+
+        chain.send_message(osc, oscillator::SetFreq(440.0)).unwrap();
+        chain.get_device_mut(gain).unwrap().set_parameter(0, -6.0);
+        assert_eq!(chain.get_device(osc).unwrap().get_parameter(0), 440.0);
+        assert_eq!(chain.get_parameters(1).unwrap().get_parameter(0), -6.0);
+
+        // Handles follow their device, and go stale once it is gone
+        chain.insert(1, Gain::new(0.0, 1)).unwrap();
+        assert_eq!(chain.get_index(gain), Some(2));
+        assert_eq!(chain.get_device(gain).unwrap().get_parameter(0), -6.0);
+        chain.remove(2).unwrap();
+        assert_eq!(chain.get_index(gain), None);
+        assert!(chain.get_device_mut(gain).is_none());
+        assert!(chain.send_message(gain, gain::SetGain(0.0)).is_err());
+        let inserted = chain.get_handle::<Gain>(1).unwrap();
+        chain.replace(1, Gain::new(0.0, 1)).unwrap();
+        assert!(chain.get_device(inserted).is_none());
+
+        // Handles from another chain are never valid
+        let other = DeviceChain::from(Oscillator::new(Sine));
+        let other_osc = other.get_handle::<Oscillator>(0).unwrap();
+        assert_eq!(chain.get_index(other_osc), None);
+        match chain.send_message(other_osc, oscillator::SetFreq(440.0)) {
+            Err(Error::OutOfRange("device")) => (),
+            _ => panic!("device from another chain was accepted")
+        }
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 1);
+        let mut mock3 = MockAudioDevice::new("mock3", 1, 1);
+        mock1.will_tick(&[], &[1.0]).will_tick(&[], &[2.0]);
+        mock2.will_tick(&[1.0], &[3.0]);
+        mock3.will_tick(&[3.0], &[4.0]).will_tick(&[2.0], &[5.0]);
+
+        let mut chain = DeviceChain::from(mock1).into(mock3);
+        assert!(chain.insert(3, Gain::new(0.0, 1)).is_err());
+        assert!(chain.insert(1, Gain::new(0.0, 2)).is_err());
+        chain.insert(1, mock2).unwrap();
+        assert_eq!(chain.num_devices(), 3);
+        chain.tick();
+        assert_eq!(chain.get_output(), [4.0]);
+
+        chain.remove(1).unwrap();
+        chain.tick();
+        assert_eq!(chain.get_output(), [5.0]);
+        assert!(chain.remove(2).is_err());
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 1);
+        mock1.will_tick(&[], &[1.0]);
+        mock2.will_tick(&[1.0], &[2.0]);
+
+        let mut chain = DeviceChain::from(Gain::new(0.0, 1)).into(mock2);
+        assert!(chain.replace(0, Gain::new(0.0, 2)).is_err());
+        let old = chain.replace(0, mock1).unwrap();
+        assert!((*old).as_any().is::<Gain>());
+        assert_eq!(types::AudioDevice::num_inputs(&chain), 0);
+        assert!(chain.replace(1, Gain::new(0.0, 2)).is_err());
+        chain.tick();
+        assert_eq!(chain.get_output(), [2.0]);
+    }
+
+    #[test]
+    fn test_adapt_channels() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 2, 2);
+        let mut mock3 = MockAudioDevice::new("mock3", 1, 2);
+        mock1.will_tick(&[], &[1.0]).will_tick(&[], &[2.0]);
+        mock2.will_tick(&[1.0, 1.0], &[1.0, 3.0]);
+        mock3.will_tick(&[2.0], &[0.0, 0.0]).will_tick(&[4.0], &[0.0, 0.0]);
+
+        // Mono is duplicated into stereo, and stereo averaged into mono
+        let mut chain = DeviceChain::from(mock1).adapt_channels(true)
+            .into(mock2).into(mock3);
+        chain.tick();
+
+        // Once the stereo device is removed, the channels match again
+        chain.set_adapt_channels(false);
+        assert!(chain.remove(1).is_ok());
+        let matrix = ChannelAdapter::from_matrix(1, &[vec![2.0]]);
+        chain.set_adapter(1, Some(matrix)).unwrap();
+        assert!(chain.set_adapter(1, Some(ChannelAdapter::new(2, 1))).is_err());
+        chain.tick();
+    }
+
+    #[test]
+    fn test_f64() {
+        let mut chain: DeviceChain<f64> =
+            DeviceChain::from(Filter::new_generic(LowPass(20.0), 1));
+        chain.get_input()[0] = 1.0;
+        chain.tick();
+        assert!(chain.get_output()[0] > 0.0);
+        assert!(chain.get_output()[0] < 1e-4);
+    }
+
     #[test]
     #[should_panic]
     fn test_wrong_number_inputs() {
@@ -177,4 +930,11 @@ mod test {
         let mock2 = MockAudioDevice::new("mock2", 2, 1);
         DeviceChain::from(mock1).into(mock2);
     }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_block_size() {
+        let mock = MockAudioDevice::new("mock", 0, 1);
+        DeviceChain::from(mock).set_block_size(0);
+    }
 }
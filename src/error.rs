@@ -29,6 +29,16 @@ pub enum Error {
     OutOfRange(&'static str),
     /// The requested operation would create a graph cycle.
     CreatesCycle,
+    /// The operation was cancelled before it finished.
+    Cancelled,
+    /// Text could not be parsed.
+    ///
+    /// The string argument describes what was expected.
+    Parse(&'static str),
+    /// An error occurred on a line of a patch.
+    ///
+    /// The arguments are the line number, counting from one, and the error.
+    Patch(usize, Box<Error>),
     /// A feature isn't supported.
     ///
     /// The string argument describes what feature.
@@ -83,6 +93,9 @@ impl error::Error for Error {
             &InvalidFile => "The provided was not valid",
             &OutOfRange(_) => "Index was out of range",
             &CreatesCycle => "The requested action creates a graph cycle",
+            &Cancelled => "The operation was cancelled",
+            &Parse(_) => "Text could not be parsed",
+            &Patch(_, _) => "Invalid patch",
             &Unsupported(_) => "Unsupported feature",
             &Io(_) => "std::io error",
             &PortAudio(_) => "PortAudio error",
@@ -95,6 +108,7 @@ impl error::Error for Error {
             &Io(ref e) => Some(e),
             &PortAudio(ref e) => Some(e),
             &PortMidi(ref e) => Some(e),
+            &Patch(_, ref e) => Some(&**e),
             _ => None,
         }
     }
@@ -5,14 +5,20 @@
 //!
 //! A `LowShelf` or `HighShelf` filter will provide a shelf starting at the
 //! cutoff frequency, and will provide the specified gain in the shelf region.
+//!
+//! Changes to the cutoff and gain are smoothed to avoid clicks, with the
+//! coefficients recomputed every sample while they ramp. By default they ramp
+//! over `DEFAULT_RAMP_TIME`; the type of filter changes instantly.
 
 #![allow(non_snake_case)]
 
-use std::f32::consts::PI;
-use num::traits::Float;
+use std::f64::consts::PI;
 
-use types::{SAMPLE_RATE, AudioDevice, MessageReceiver, Sample, Time};
+use parameters::{Decibels, Hertz, Logarithmic, ParameterInfo, Parameters};
+use types::{SAMPLE_RATE, AudioDevice, MessageReceiver, Sample, SampleFloat,
+            Time};
 use utils::helpers::decibel_to_ratio;
+use utils::smoothed::{DEFAULT_RAMP_TIME, Exponential, Linear, Smoothed};
 
 
 /// Defines the messages that the Filter supports.
@@ -20,6 +26,8 @@ use utils::helpers::decibel_to_ratio;
 pub enum Message {
     /// Sets the filter's mode of operation.
     SetMode(FilterMode),
+    /// Sets the time taken to ramp to a new cutoff or gain, in seconds.
+    SetRampTime(f32),
 }
 pub use self::Message::*;
 
@@ -40,61 +48,162 @@ pub enum FilterMode {
 }
 pub use self::FilterMode::*;
 
+/// The names of each filter mode, in the order of the mode parameter.
+static MODE_NAMES: [&str; 4] =
+    ["low pass", "high pass", "low shelf", "high shelf"];
+
+/// Splits a mode into its (index, cutoff, gain). Modes without a gain use 0.
+fn mode_to_parts(mode: FilterMode) -> (usize, f32, f32) {
+    match mode {
+        LowPass(cutoff) => (0, cutoff, 0.0),
+        HighPass(cutoff) => (1, cutoff, 0.0),
+        LowShelf(cutoff, gain) => (2, cutoff, gain),
+        HighShelf(cutoff, gain) => (3, cutoff, gain)
+    }
+}
+
+/// Builds a mode from its (index, cutoff, gain).
+fn mode_from_parts(i: usize, cutoff: f32, gain: f32) -> FilterMode {
+    match i {
+        0 => LowPass(cutoff),
+        1 => HighPass(cutoff),
+        2 => LowShelf(cutoff, gain),
+        _ => HighShelf(cutoff, gain)
+    }
+}
+
 
 /// A single pole filter.
-pub struct Filter {
+pub struct Filter<S: SampleFloat = Sample> {
+    sample_rate: u32,
     num_channels: usize,
-    x_last: Vec<Sample>,
-    y1_last: Vec<Sample>,
-    mode: FilterMode,
-    alpha: f32,
-    H0: f32
+    x_last: Vec<S>,
+    y1_last: Vec<S>,
+    mode: FilterMode, // the mode being ramped towards
+    cutoff: Smoothed<f32>,
+    gain: Smoothed<f32>,
+    alpha: S,
+    H0: S
 }
 
 impl Filter {
     /// Creates a new first order filter with the provided mode. Each channel is
     /// filtered independently.
     pub fn new(mode: FilterMode, num_channels: usize) -> Self {
-        // Compute the parameter values. H0 is ignored for Pass filters
-        let (alpha, H0) = compute_parameters(mode);
+        Filter::new_generic(mode, num_channels)
+    }
+}
 
-        Filter {
+impl<S: SampleFloat> Filter<S> {
+    /// Returns a filter processing any sample type. `new` returns the same
+    /// device for the default `Sample` type.
+    pub fn new_generic(mode: FilterMode, num_channels: usize) -> Self {
+        let (_, cutoff, gain) = mode_to_parts(mode);
+        let mut filter = Filter {
+            sample_rate: SAMPLE_RATE,
             num_channels: num_channels,
-            x_last: vec![0.0; num_channels],
-            y1_last: vec![0.0; num_channels],
+            x_last: vec![S::zero(); num_channels],
+            y1_last: vec![S::zero(); num_channels],
             mode: mode,
-            alpha: alpha,
-            H0: H0
-        }
+            cutoff: Smoothed::new(cutoff, Exponential)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
+            gain: Smoothed::new(gain, Linear)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
+            alpha: S::zero(),
+            H0: S::zero()
+        };
+        filter.update_coefficients();
+        filter
+    }
+
+    /// Recomputes the coefficients from the current cutoff and gain.
+    fn update_coefficients(&mut self) {
+        let (i, _, _) = mode_to_parts(self.mode);
+        let mode = mode_from_parts(i, self.cutoff.get_value(),
+                                   self.gain.get_value());
+
+        // H0 is ignored for Pass filters
+        let (alpha, H0) = compute_parameters(mode, self.sample_rate);
+        self.alpha = S::from_f64(alpha);
+        self.H0 = S::from_f64(H0);
     }
 }
 
-impl MessageReceiver for Filter {
+impl<S: SampleFloat> MessageReceiver for Filter<S> {
     type Msg = Message;
     fn handle_message(&mut self, msg: Message) {
-        let SetMode(mode) = msg;
-        let (alpha, H0) = compute_parameters(mode);
-        self.mode = mode;
-        self.alpha = alpha;
-        self.H0 = H0;
+        match msg {
+            SetMode(mode) => {
+                let (_, cutoff, gain) = mode_to_parts(mode);
+                self.mode = mode;
+                self.cutoff.set_target(cutoff);
+                self.gain.set_target(gain);
+                self.update_coefficients();
+            },
+            SetRampTime(seconds) => {
+                self.cutoff.set_ramp_time(seconds);
+                self.gain.set_ramp_time(seconds);
+            }
+        }
+    }
+}
+
+impl<S: SampleFloat> Parameters for Filter<S> {
+    fn num_parameters(&self) -> usize {
+        3
+    }
+
+    fn get_parameter_info(&self, i: usize) -> ParameterInfo {
+        match i {
+            0 => ParameterInfo::choice("mode", &MODE_NAMES, 0),
+            1 => ParameterInfo::new("cutoff", 20.0, 20000.0, 1000.0)
+                .unit(Hertz).scale(Logarithmic),
+            2 => ParameterInfo::new("gain", -24.0, 24.0, 0.0).unit(Decibels),
+            _ => panic!("Filter: parameter index {} out of range", i)
+        }
+    }
+
+    fn get_parameter(&self, i: usize) -> f32 {
+        let (mode, cutoff, gain) = mode_to_parts(self.mode);
+        match i {
+            0 => mode as f32,
+            1 => cutoff,
+            2 => gain,
+            _ => panic!("Filter: parameter index {} out of range", i)
+        }
+    }
+
+    fn set_parameter(&mut self, i: usize, value: f32) {
+        let value = self.get_parameter_info(i).clamp(value);
+        let (mut mode, mut cutoff, mut gain) = mode_to_parts(self.mode);
+        match i {
+            0 => mode = value as usize,
+            1 => cutoff = value,
+            _ => gain = value
+        }
+        self.handle_message(SetMode(mode_from_parts(mode, cutoff, gain)));
     }
 }
 
 /// Computes the (alpha, H0) parameters for our filter
-fn compute_parameters(mode: FilterMode) -> (f32, f32) {
+///
+/// The parameters are computed at double precision, regardless of the sample
+/// type.
+fn compute_parameters(mode: FilterMode, sample_rate: u32) -> (f64, f64) {
     let cutoff = match mode {
         LowPass(cutoff) => cutoff,
         HighPass(cutoff) => cutoff,
         LowShelf(cutoff, _) => cutoff,
         HighShelf(cutoff, _) => cutoff
     };
-    let K = (PI * cutoff / (SAMPLE_RATE as f32)).tan();
+    let K = (PI * cutoff as f64 / (sample_rate as f64)).tan();
 
     match mode {
         LowPass(_) | HighPass(_) => {
             ((K-1.0) / (K+1.0), 0.0)
         },
         LowShelf(_, gain) => {
+            let gain = gain as f64;
             let V0 = decibel_to_ratio(gain/2.0); // amplitude dB
             let H0 = V0 - 1.0;
             let alpha = if gain < 0.0 {
@@ -105,6 +214,7 @@ fn compute_parameters(mode: FilterMode) -> (f32, f32) {
             (alpha, H0)
         },
         HighShelf(_, gain) => {
+            let gain = gain as f64;
             let V0 = decibel_to_ratio(gain/2.0); // amplitude dB
             let H0 = V0 - 1.0;
             let alpha = if gain > 0.0 {
@@ -117,7 +227,7 @@ fn compute_parameters(mode: FilterMode) -> (f32, f32) {
     }
 }
 
-impl AudioDevice for Filter {
+impl<S: SampleFloat> AudioDevice<S> for Filter<S> {
     fn num_inputs(&self) -> usize {
         self.num_channels
     }
@@ -126,21 +236,62 @@ impl AudioDevice for Filter {
         self.num_channels
     }
 
-    fn tick(&mut self, _: Time, inputs: &[Sample], outputs: &mut[Sample]) {
-        for (i,x) in inputs.iter().enumerate() {
+    fn parameters(&self) -> Option<&Parameters> {
+        Some(self)
+    }
+
+    fn parameters_mut(&mut self) -> Option<&mut Parameters> {
+        Some(self)
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.cutoff.set_sample_rate(sample_rate);
+        self.gain.set_sample_rate(sample_rate);
+        self.update_coefficients();
+    }
+
+    fn reset(&mut self) {
+        for i in 0..self.num_channels {
+            self.x_last[i] = S::zero();
+            self.y1_last[i] = S::zero();
+        }
+        self.cutoff.finish();
+        self.gain.finish();
+        self.update_coefficients();
+    }
+
+    fn tick(&mut self, t: Time, inputs: &[S], outputs: &mut[S]) {
+        self.tick_block(t, 1, inputs, outputs);
+    }
+
+    fn tick_block(&mut self, _: Time, frames: usize, inputs: &[S],
+                  outputs: &mut[S]) {
+        let n = self.num_channels;
+        let two = S::from_f32(2.0);
+        for (j,&x) in inputs[..frames*n].iter().enumerate() {
+            let i = j % n;
+
+            // Advance any ramps once per frame
+            if i == 0 && (self.cutoff.is_ramping() || self.gain.is_ramping()) {
+                self.cutoff.tick();
+                self.gain.tick();
+                self.update_coefficients();
+            }
+
             // Run the all pass filter, and feedback the result
             let y1 = self.alpha*x + self.x_last[i] - self.alpha*self.y1_last[i];
             let y = match self.mode {
-                LowPass(_) => (x+y1)/2.0,
-                HighPass(_) => (x-y1)/2.0,
-                LowShelf(_,_) => self.H0*(x+y1)/2.0 + x,
-                HighShelf(_,_) => self.H0*(x-y1)/2.0 + x
+                LowPass(_) => (x+y1)/two,
+                HighPass(_) => (x-y1)/two,
+                LowShelf(_,_) => self.H0*(x+y1)/two + x,
+                HighShelf(_,_) => self.H0*(x-y1)/two + x
             };
 
             // Store our results
-            self.x_last[i] = *x;
+            self.x_last[i] = x;
             self.y1_last[i] = y1;
-            outputs[i] = y;
+            outputs[j] = y;
         }
     }
 }
@@ -9,13 +9,21 @@
 //! A `Peak` filter will provide the specified gain around a center frequency,
 //! with the width of the peak determined by the Q. A higher Q means a narrower
 //! peak.
+//!
+//! Changes to the cutoff, gain and Q are smoothed to avoid clicks, with the
+//! coefficients recomputed every sample while they ramp. By default they ramp
+//! over `DEFAULT_RAMP_TIME`; the type of filter changes instantly.
 
 
-use std::f32::consts::PI;
+use std::f32::consts::FRAC_1_SQRT_2;
+use std::f64::consts::PI;
 use num::traits::Float;
 
-use types::{SAMPLE_RATE, AudioDevice, MessageReceiver, Sample, Time};
+use parameters::{Decibels, Hertz, Logarithmic, ParameterInfo, Parameters};
+use types::{SAMPLE_RATE, AudioDevice, MessageReceiver, Sample, SampleFloat,
+            Time};
 use utils::helpers::decibel_to_ratio;
+use utils::smoothed::{DEFAULT_RAMP_TIME, Exponential, Linear, Smoothed};
 
 
 /// Defines the messages that the Filter supports.
@@ -23,6 +31,8 @@ use utils::helpers::decibel_to_ratio;
 pub enum Message {
     /// Sets the filter's mode of operation.
     SetMode(FilterMode),
+    /// Sets the time taken to ramp to a new cutoff, gain or Q, in seconds.
+    SetRampTime(f32),
 }
 pub use self::Message::*;
 
@@ -45,49 +55,173 @@ pub enum FilterMode {
 }
 pub use self::FilterMode::*;
 
+/// The names of each filter mode, in the order of the mode parameter.
+static MODE_NAMES: [&str; 5] =
+    ["low pass", "high pass", "low shelf", "high shelf", "peak"];
+
+/// The Q used by modes that have no Q of their own.
+const DEFAULT_Q: f32 = FRAC_1_SQRT_2;
+
+/// Splits a mode into its (index, cutoff, gain, q). Modes without a gain use
+/// 0, and modes without a Q use `DEFAULT_Q`.
+fn mode_to_parts(mode: FilterMode) -> (usize, f32, f32, f32) {
+    match mode {
+        LowPass(cutoff) => (0, cutoff, 0.0, DEFAULT_Q),
+        HighPass(cutoff) => (1, cutoff, 0.0, DEFAULT_Q),
+        LowShelf(cutoff, gain) => (2, cutoff, gain, DEFAULT_Q),
+        HighShelf(cutoff, gain) => (3, cutoff, gain, DEFAULT_Q),
+        Peak(cutoff, gain, q) => (4, cutoff, gain, q)
+    }
+}
+
+/// Builds a mode from its (index, cutoff, gain, q).
+fn mode_from_parts(i: usize, cutoff: f32, gain: f32, q: f32) -> FilterMode {
+    match i {
+        0 => LowPass(cutoff),
+        1 => HighPass(cutoff),
+        2 => LowShelf(cutoff, gain),
+        3 => HighShelf(cutoff, gain),
+        _ => Peak(cutoff, gain, q)
+    }
+}
+
 
 /// A two pole filter.
-pub struct Filter {
+pub struct Filter<S: SampleFloat = Sample> {
+    sample_rate: u32,
+    mode: FilterMode, // the mode being ramped towards
+    cutoff: Smoothed<f32>,
+    gain: Smoothed<f32>,
+    q: Smoothed<f32>,
     num_channels: usize,
-    x_last1: Vec<Sample>, x_last2: Vec<Sample>, // two time step delay elements
-    y_last1: Vec<Sample>, y_last2: Vec<Sample>,
-    b0: f32, b1: f32, b2: f32, a1: f32, a2: f32
+    x_last1: Vec<S>, x_last2: Vec<S>, // two time step delay elements
+    y_last1: Vec<S>, y_last2: Vec<S>,
+    b0: S, b1: S, b2: S, a1: S, a2: S
 }
 
 impl Filter {
     /// Creates a new second order filter with the provided mode. Each channel
     /// is filtered independently.
     pub fn new(mode: FilterMode, num_channels: usize) -> Self {
-        // Compute the parameter values
-        let (b0, b1, b2, a1, a2) = compute_parameters(mode);
+        Filter::new_generic(mode, num_channels)
+    }
+}
 
-        Filter {
+impl<S: SampleFloat> Filter<S> {
+    /// Returns a filter processing any sample type. `new` returns the same
+    /// device for the default `Sample` type.
+    pub fn new_generic(mode: FilterMode, num_channels: usize) -> Self {
+        let (_, cutoff, gain, q) = mode_to_parts(mode);
+        let mut filter = Filter {
+            sample_rate: SAMPLE_RATE,
+            mode: mode,
+            cutoff: Smoothed::new(cutoff, Exponential)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
+            gain: Smoothed::new(gain, Linear)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
+            q: Smoothed::new(q, Exponential)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
             num_channels: num_channels,
-            x_last1: vec![0.0; num_channels],
-            x_last2: vec![0.0; num_channels],
-            y_last1: vec![0.0; num_channels],
-            y_last2: vec![0.0; num_channels],
-            b0: b0, b1: b1, b2: b2, a1: a1, a2: a2
-        }
+            x_last1: vec![S::zero(); num_channels],
+            x_last2: vec![S::zero(); num_channels],
+            y_last1: vec![S::zero(); num_channels],
+            y_last2: vec![S::zero(); num_channels],
+            b0: S::zero(), b1: S::zero(), b2: S::zero(),
+            a1: S::zero(), a2: S::zero()
+        };
+        filter.update_coefficients();
+        filter
+    }
+
+    /// Returns true while the cutoff, gain or Q is still ramping.
+    fn is_ramping(&self) -> bool {
+        self.cutoff.is_ramping() || self.gain.is_ramping() ||
+            self.q.is_ramping()
+    }
+
+    /// Recomputes the coefficients from the current cutoff, gain and Q.
+    fn update_coefficients(&mut self) {
+        let (i, _, _, _) = mode_to_parts(self.mode);
+        let mode = mode_from_parts(i, self.cutoff.get_value(),
+                                   self.gain.get_value(), self.q.get_value());
+        let (b0, b1, b2, a1, a2) = compute_parameters(mode, self.sample_rate);
+        self.b0 = S::from_f64(b0);
+        self.b1 = S::from_f64(b1);
+        self.b2 = S::from_f64(b2);
+        self.a1 = S::from_f64(a1);
+        self.a2 = S::from_f64(a2);
     }
 }
 
-impl MessageReceiver for Filter {
+impl<S: SampleFloat> MessageReceiver for Filter<S> {
     type Msg = Message;
     fn handle_message(&mut self, msg: Message) {
-        let SetMode(mode) = msg;
-        let (b0, b1, b2, a1, a2) = compute_parameters(mode);
-        self.b0 = b0;
-        self.b1 = b1;
-        self.b2 = b2;
-        self.a1 = a1;
-        self.a2 = a2;
+        match msg {
+            SetMode(mode) => {
+                let (_, cutoff, gain, q) = mode_to_parts(mode);
+                self.mode = mode;
+                self.cutoff.set_target(cutoff);
+                self.gain.set_target(gain);
+                self.q.set_target(q);
+                self.update_coefficients();
+            },
+            SetRampTime(seconds) => {
+                self.cutoff.set_ramp_time(seconds);
+                self.gain.set_ramp_time(seconds);
+                self.q.set_ramp_time(seconds);
+            }
+        }
+    }
+}
+
+impl<S: SampleFloat> Parameters for Filter<S> {
+    fn num_parameters(&self) -> usize {
+        4
+    }
+
+    fn get_parameter_info(&self, i: usize) -> ParameterInfo {
+        match i {
+            0 => ParameterInfo::choice("mode", &MODE_NAMES, 0),
+            1 => ParameterInfo::new("cutoff", 20.0, 20000.0, 1000.0)
+                .unit(Hertz).scale(Logarithmic),
+            2 => ParameterInfo::new("gain", -24.0, 24.0, 0.0).unit(Decibels),
+            3 => ParameterInfo::new("Q", 0.1, 10.0, DEFAULT_Q)
+                .scale(Logarithmic),
+            _ => panic!("Filter: parameter index {} out of range", i)
+        }
+    }
+
+    fn get_parameter(&self, i: usize) -> f32 {
+        let (mode, cutoff, gain, q) = mode_to_parts(self.mode);
+        match i {
+            0 => mode as f32,
+            1 => cutoff,
+            2 => gain,
+            3 => q,
+            _ => panic!("Filter: parameter index {} out of range", i)
+        }
+    }
+
+    fn set_parameter(&mut self, i: usize, value: f32) {
+        let value = self.get_parameter_info(i).clamp(value);
+        let (mut mode, mut cutoff, mut gain, mut q) = mode_to_parts(self.mode);
+        match i {
+            0 => mode = value as usize,
+            1 => cutoff = value,
+            2 => gain = value,
+            _ => q = value
+        }
+        self.handle_message(SetMode(mode_from_parts(mode, cutoff, gain, q)));
     }
 }
 
 /// Computes the parameters for our filter
+///
+/// The parameters are computed at double precision, regardless of the sample
+/// type.
 #[allow(non_snake_case)]
-fn compute_parameters(mode: FilterMode) -> (f32, f32, f32, f32, f32) {
+fn compute_parameters(mode: FilterMode, sample_rate: u32)
+        -> (f64, f64, f64, f64, f64) {
     let cutoff = match mode {
         LowPass(cutoff) => cutoff,
         HighPass(cutoff) => cutoff,
@@ -95,7 +229,7 @@ fn compute_parameters(mode: FilterMode) -> (f32, f32, f32, f32, f32) {
         HighShelf(cutoff, _) => cutoff,
         Peak(center, _, _) => center
     };
-    let K = (PI * cutoff / (SAMPLE_RATE as f32)).tan();
+    let K = (PI * cutoff as f64 / (sample_rate as f64)).tan();
 
     match mode {
         LowPass(_)  => {
@@ -115,6 +249,7 @@ fn compute_parameters(mode: FilterMode) -> (f32, f32, f32, f32, f32) {
             (b0, b1, b2, a1, a2)
         },
         LowShelf(_, gain) => {
+            let gain = gain as f64;
             if gain < 0.0 { // cut
                 let V0 = 1.0 / decibel_to_ratio(gain/2.0); // amplitude dB
                 let b0 = (1.0 + 2.0.sqrt()*K + K*K) /
@@ -140,6 +275,7 @@ fn compute_parameters(mode: FilterMode) -> (f32, f32, f32, f32, f32) {
             }
         },
         HighShelf(_, gain) => {
+            let gain = gain as f64;
             if gain < 0.0 { // cut
                 let V0 = 1.0 / decibel_to_ratio(gain/2.0); // amplitude dB
                 let b0 = (1.0 + 2.0.sqrt()*K + K*K) /
@@ -165,6 +301,7 @@ fn compute_parameters(mode: FilterMode) -> (f32, f32, f32, f32, f32) {
             }
         },
         Peak(_, gain, Q) => {
+            let (gain, Q) = (gain as f64, Q as f64);
             if gain < 0.0 { // cut
                 let V0 = 1.0 / decibel_to_ratio(gain/2.0); // amplitude dB
                 let b0 = (1.0 + K/Q + K*K) / (1.0 + V0*K/Q + K*K);
@@ -186,7 +323,7 @@ fn compute_parameters(mode: FilterMode) -> (f32, f32, f32, f32, f32) {
     }
 }
 
-impl AudioDevice for Filter {
+impl<S: SampleFloat> AudioDevice<S> for Filter<S> {
     fn num_inputs(&self) -> usize {
         self.num_channels
     }
@@ -195,8 +332,53 @@ impl AudioDevice for Filter {
         self.num_channels
     }
 
-    fn tick(&mut self, _: Time, inputs: &[Sample], outputs: &mut[Sample]) {
-        for (i,x) in inputs.iter().enumerate() {
+    fn parameters(&self) -> Option<&Parameters> {
+        Some(self)
+    }
+
+    fn parameters_mut(&mut self) -> Option<&mut Parameters> {
+        Some(self)
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.cutoff.set_sample_rate(sample_rate);
+        self.gain.set_sample_rate(sample_rate);
+        self.q.set_sample_rate(sample_rate);
+        self.update_coefficients();
+    }
+
+    fn reset(&mut self) {
+        for i in 0..self.num_channels {
+            self.x_last1[i] = S::zero();
+            self.x_last2[i] = S::zero();
+            self.y_last1[i] = S::zero();
+            self.y_last2[i] = S::zero();
+        }
+        self.cutoff.finish();
+        self.gain.finish();
+        self.q.finish();
+        self.update_coefficients();
+    }
+
+    fn tick(&mut self, t: Time, inputs: &[S], outputs: &mut[S]) {
+        self.tick_block(t, 1, inputs, outputs);
+    }
+
+    fn tick_block(&mut self, _: Time, frames: usize, inputs: &[S],
+                  outputs: &mut[S]) {
+        let n = self.num_channels;
+        for (j,&x) in inputs[..frames*n].iter().enumerate() {
+            let i = j % n;
+
+            // Advance any ramps once per frame
+            if i == 0 && self.is_ramping() {
+                self.cutoff.tick();
+                self.gain.tick();
+                self.q.tick();
+                self.update_coefficients();
+            }
+
             // Run the all pass filter, and feedback the result
             let y = self.b0*x + self.b1*self.x_last1[i]
                 + self.b2*self.x_last2[i] - self.a1*self.y_last1[i]
@@ -205,9 +387,9 @@ impl AudioDevice for Filter {
             // Store our results
             self.x_last2[i] = self.x_last1[i];
             self.y_last2[i] = self.y_last1[i];
-            self.x_last1[i] = *x;
+            self.x_last1[i] = x;
             self.y_last1[i] = y;
-            outputs[i] = y;
+            outputs[j] = y;
         }
     }
 }
@@ -6,7 +6,81 @@
 //!
 //! A graph is initialized by adding each device as a node in the graph, and
 //! then specifying the edges between devices. The graph will automatically
-//! process the devices in order of their dependencies.
+//! process the devices in order of their dependencies. When several edges lead
+//! into the same input, the input receives their sum, so mixing doesn't need a
+//! separate `Adder` node. Each edge may also scale its signal by a gain.
+//!
+//! Nodes and edges can be
+//! removed again at any time; the identifier of a removed node is never valid
+//! again.
+//!
+//! # Feedback
+//!
+//! Normal edges may not create a cycle, since every device must be processed
+//! after the devices feeding it. Feedback edges, added with
+//! `DeviceGraph::add_feedback_edge`, may close a cycle instead, because they
+//! delay their signal by at least one sample. When a feedback edge is shorter
+//! than the block size, the graph processes each block in smaller pieces, so
+//! that the delayed samples are always ready in time.
+//!
+//! # Nesting graphs
+//!
+//! A graph is itself an `AudioDevice`, so a sub-patch can be reused as a node
+//! in another container. Its external inputs and outputs are declared with
+//! `DeviceGraph::set_inputs` and `DeviceGraph::set_outputs`, which each add
+//! a port node to connect edges to and from. The graph can also be wrapped in
+//! `Messaged`, to set the parameters of its nodes from another thread.
+//!
+//! Since both `AudioDevice` and `Tick` define a `tick` method, code that has
+//! both traits in scope must call `Tick::tick(&mut graph)` to process a block.
+//!
+//! # Inspecting graphs
+//!
+//! Nodes can be given names with `DeviceGraph::set_name`. A graph can be
+//! exported to Graphviz with `DeviceGraph::to_dot`, or described in text with
+//! `DeviceGraph::summary`, which also lists any unconnected inputs and unused
+//! outputs.
+//!
+//! # Live editing
+//!
+//! A graph can be edited from another thread while it plays, through
+//! a `GraphEditor` returned by `DeviceGraph::get_editor`. Like the senders of
+//! `Messaged`, an editor sends `Edit`s over a channel; the graph applies them
+//! at the start of its next tick. Devices replaced this way crossfade into
+//! their replacement, so that the swap doesn't click.
+//!
+//! # Bypass, mute and solo
+//!
+//! Any node can be bypassed, so that its inputs pass straight to its outputs,
+//! or muted, so that its outputs are silent. Soloing nodes mutes every node
+//! that neither feeds into nor is fed by a soloed node. Each of these ramps
+//! over a few milliseconds, so they can be switched while the graph plays
+//! without clicks.
+//!
+//! # Accessing devices
+//!
+//! Devices are boxed once they are added to a graph. To reach a device again,
+//! `DeviceGraph::get_handle` returns a `NodeHandle` that remembers the
+//! device's type. The handle borrows the device mutably through
+//! `DeviceGraph::get_device_mut`, or passes it messages through
+//! `DeviceGraph::send_message`.
+//!
+//! # Multithreading
+//!
+//! Large graphs often have independent branches. With
+//! `DeviceGraph::set_num_threads`, the graph processes each stage of
+//! independent nodes across several threads, one block at a time. Only devices
+//! added with `DeviceGraph::add_parallel_node` leave the calling thread, since
+//! they must be `Send`. The output is bit-identical to processing on a single
+//! thread.
+//!
+//! # Latency compensation
+//!
+//! Devices may report a processing latency. When branches of differing latency
+//! merge into the same device, the graph delays the faster branches so that
+//! all inputs to the device stay sample-aligned. The total latency through the
+//! graph is available from `DeviceGraph::latency`. Feedback edges are not
+//! compensated, since their delay is part of the patch.
 //!
 //! # Example
 //! 
@@ -38,264 +112,2534 @@
 //! graph.tick_forever();
 //! ```
 
+use std::cmp::{max, min};
 use std::collections::VecDeque;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
 
 use error::{Error, Result};
-use types::{AudioDevice, Sample, Time};
+use parameters::Parameters;
+use patch::DeviceType;
+use transport::{Transport, TransportHandle};
+use types::{SAMPLE_RATE, AnyDevice, AudioDevice, MessageReceiver, Sample,
+            SampleFloat, Time};
+use utils::ringbuffer::RingBuffer;
+use utils::smoothed::{DEFAULT_RAMP_TIME, Linear, Smoothed};
 pub use tick::Tick;
 
 
+/// The shortest piece of a block that is spread across threads. Shorter
+/// pieces are processed on the calling thread.
+pub const MIN_PARALLEL_FRAMES: usize = 16;
+
+/// The id of the next graph created.
+static NEXT_GRAPH_ID: AtomicUsize = AtomicUsize::new(0);
+
 /// An acyclic graph for audio devices.
-pub struct DeviceGraph {
-    nodes: Vec<AudioNode>, // the actual nodes
+///
+/// Each tick of the graph processes one block of audio. By default a block is
+/// a single frame; a larger block size can be set using `set_block_size`, so
+/// that each device processes many frames at once.
+///
+/// The graph also holds the sample rate for all its devices. Every device
+/// added to the graph is prepared with the graph's sample rate and block size.
+///
+/// Every device shares the graph's transport, which advances after each tick
+/// while it is playing.
+///
+/// When the graph is used as an `AudioDevice` inside another container, it
+/// processes whatever block its container passes it, and shares the
+/// container's transport instead of advancing its own.
+///
+/// All devices in a graph process samples of the same precision, `S`.
+/// `DeviceGraph::new` creates a graph using the default `Sample` type; graphs
+/// of other precisions can be created with `DeviceGraph::default`.
+pub struct DeviceGraph<S: SampleFloat = Sample> {
+    id: usize, // unique among every graph, and stored in its identifiers
+    nodes: Vec<Option<AudioNode<S>>>, // the actual nodes, None once removed
+    generations: Vec<usize>, // the number of times each slot was removed
     topology: Vec<usize>, // the order to tick the nodes
-    bus: Vec<Sample>, // the audio bus to write samples to
+    stages: Vec<Vec<usize>>, // the nodes that can be ticked in parallel
+    num_threads: usize, // the number of threads to tick stages on
+    bus: Vec<S>, // the audio bus to write samples to
+    bus_used: Vec<bool>, // whether each bus channel belongs to a node
+    block_size: usize, // the number of frames in each tick
+    sample_rate: u32, // the sample rate of all devices
+    latency: usize, // the latency of the longest path through the graph
+    transport: TransportHandle, // the transport shared by all devices
+    input_port: Option<AudioNodeIdx>, // the node holding external inputs
+    output_port: Option<AudioNodeIdx>, // the node taking external outputs
+    input: Vec<S>, // one interleaved block of external inputs
+    output: Vec<S>, // one interleaved block of external outputs
+    crossfade: f32, // the time taken to replace a device, in seconds
+    edit_tx: Sender<(Edit<S>, Sender<Error>)>, // the sender for editors
+    edit_rx: Receiver<(Edit<S>, Sender<Error>)>, // the edits to apply
     time: Time // the next timestep
 }
 
 impl DeviceGraph {
     /// Creates an empty graph.
     pub fn new() -> Self {
+        DeviceGraph::default()
+    }
+}
+
+impl<S: SampleFloat> Default for DeviceGraph<S> {
+    fn default() -> Self {
+        let (edit_tx, edit_rx) = channel();
         DeviceGraph {
+            id: NEXT_GRAPH_ID.fetch_add(1, Ordering::Relaxed),
             nodes: Vec::new(),
+            generations: Vec::new(),
             topology: Vec::new(),
+            stages: Vec::new(),
+            num_threads: 1,
             bus: Vec::new(),
+            bus_used: Vec::new(),
+            block_size: 1,
+            sample_rate: SAMPLE_RATE,
+            latency: 0,
+            transport: Transport::new_handle(SAMPLE_RATE),
+            input_port: None,
+            output_port: None,
+            input: Vec::new(),
+            output: Vec::new(),
+            crossfade: DEFAULT_RAMP_TIME,
+            edit_tx: edit_tx,
+            edit_rx: edit_rx,
             time: 0
         }
     }
+}
 
-    /// Adds a new device into the graph, with no connections. Returns
-    /// a identifier that refers back to this device.
-    pub fn add_node<D>(&mut self, device: D) -> AudioNodeIdx
-            where D: 'static+AudioDevice {
-        let node = AudioNode::new(device, &mut self.bus);
-        let idx = self.nodes.len();
-        self.nodes.push(node);
-        self.topology.push(idx);
-        AudioNodeIdx(idx)
+impl<S: SampleFloat> DeviceGraph<S> {
+    /// Returns the number of frames processed by each tick.
+    pub fn get_block_size(&self) -> usize {
+        self.block_size
     }
 
-    /// Connects two devices in the graph. 
+    /// Sets the number of frames processed by each tick, and prepares every
+    /// device in the graph for the new block size.
     ///
-    /// * `src` and `dest` are identifiers for the actual devices to connect.
-    /// * `src_ch` and `dest_ch` are the channel indices of the two devices.
+    /// The audio bus is reallocated, and will be zeroed.
     ///
-    /// If invalid indices are provided, or if the specified edge would create
-    /// a cycle in the graph, an Err is returned and no changes dest the graph are
-    /// made.
-    pub fn add_edge(&mut self, src: AudioNodeIdx, src_ch: usize,
-                    dest: AudioNodeIdx, dest_ch: usize) -> Result<()> {
-        // Check device indices
-        let AudioNodeIdx(src_i) = src;
-        let AudioNodeIdx(dest_i) = dest;
-        if src_i >= self.nodes.len() {
-            return Err(Error::OutOfRange("src"));
-        } else if dest_i >= self.nodes.len() {
-            return Err(Error::OutOfRange("dest"));
+    /// # Panics
+    ///
+    /// Panics if `frames` is zero.
+    pub fn set_block_size(&mut self, frames: usize) {
+        let sample_rate = self.sample_rate;
+        self.prepare(sample_rate, frames);
+    }
+
+    /// Returns the sample rate of the graph, in Hz.
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Sets the sample rate of the graph, in Hz, and prepares every device in
+    /// the graph for the new rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        let block_size = self.block_size;
+        self.prepare(sample_rate, block_size);
+    }
+
+    /// Sets both the sample rate and block size of the graph, then prepares
+    /// every device in the graph for them.
+    ///
+    /// The audio bus is reallocated, and will be zeroed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is zero.
+    pub fn prepare(&mut self, sample_rate: u32, block_size: usize) {
+        if block_size == 0 {
+            panic!("DeviceGraph: block size must be at least one frame");
+        }
+        self.sample_rate = sample_rate;
+        self.block_size = block_size;
+        self.transport.borrow_mut().set_sample_rate(sample_rate);
+        self.bus = vec![S::zero(); self.bus_used.len()*block_size];
+        self.input = vec![S::zero(); self.num_port_inputs()*block_size];
+        self.output = vec![S::zero(); self.num_port_outputs()*block_size];
+        for node in self.nodes.iter_mut().filter_map(|node| node.as_mut()) {
+            node.device.prepare(sample_rate, block_size);
+            node.resize(block_size);
+            node.fade = None;
+            node.bypass.set_sample_rate(sample_rate);
+            node.gain.set_sample_rate(sample_rate);
         }
+        self.compensate_latency();
+    }
 
-        // Check channels
-        if self.nodes[src_i].device.num_outputs() <= src_ch {
-            return Err(Error::OutOfRange("src_ch"));
+    /// Resets every device in the graph, and zeroes the audio bus, the
+    /// external inputs and outputs, and any latency compensation delays.
+    pub fn reset(&mut self) {
+        for s in self.bus.iter_mut().chain(self.input.iter_mut())
+                .chain(self.output.iter_mut()) {
+            *s = S::zero();
         }
-        if self.nodes[dest_i].device.num_inputs() <= dest_ch {
-            return Err(Error::OutOfRange("dest_ch"));
+        for node in self.nodes.iter_mut().filter_map(|node| node.as_mut()) {
+            node.device.reset();
+            node.clear_delays();
+            node.fade = None;
+            node.bypass.finish();
+            node.gain.finish();
         }
-        while self.nodes[dest_i].inputs.len() < dest_ch {
-            self.nodes[dest_i].inputs.push(None);
+    }
+
+    /// Returns the total latency of the graph, in samples.
+    ///
+    /// This is the latency along the slowest path through the graph. Faster
+    /// paths are delayed to match wherever they merge.
+    ///
+    /// Latencies are computed when nodes and edges are added or removed, and
+    /// when the graph is prepared. If a device changes its latency, call
+    /// `prepare` again to update the compensation.
+    pub fn latency(&self) -> usize {
+        self.latency
+    }
+
+    /// Returns true if the node is bypassed, or an Err if the node doesn't
+    /// exist.
+    pub fn is_bypassed(&self, node: AudioNodeIdx) -> Result<bool> {
+        self.node(node).map(|node| node.bypassed)
+            .ok_or(Error::OutOfRange("node"))
+    }
+
+    /// Bypasses a node, or returns it to normal processing. Returns an Err if
+    /// the node doesn't exist.
+    ///
+    /// A bypassed node passes each input channel to the output channel of the
+    /// same number. Outputs without a matching input are silent. The device
+    /// is still processed, so that it is ready to switch back.
+    pub fn set_bypass(&mut self, node: AudioNodeIdx, bypassed: bool)
+            -> Result<()> {
+        match self.node_mut(node) {
+            Some(node) => {
+                node.bypassed = bypassed;
+                node.bypass.set_target(if bypassed { 1.0 } else { 0.0 });
+                Ok(())
+            },
+            None => Err(Error::OutOfRange("node"))
         }
+    }
 
-        // Set input
-        let (start,_) = self.nodes[src_i].outputs;
-        self.nodes[dest_i].inputs[dest_ch] = Some(start+src_ch);
-        self.topological_sort(dest_i, dest_ch)
+    /// Returns true if the node is muted, or an Err if the node doesn't exist.
+    ///
+    /// Nodes silenced by another node's solo are not reported as muted.
+    pub fn is_muted(&self, node: AudioNodeIdx) -> Result<bool> {
+        self.node(node).map(|node| node.muted)
+            .ok_or(Error::OutOfRange("node"))
     }
 
-    /// Determines the topology of our device graph. If the graph has a cycle,
-    /// then we remove the last edge. Otherwise, we set self.topology to
-    /// a topologically sorted order.
-    fn topological_sort(&mut self, dest_i: usize, dest_ch: usize) -> Result<()> {
-        // Intialize our set of input edges, and our set of edgeless nodes
-        let mut topology = Vec::new();
-        let mut inputs: Vec<Vec<_>> = self.nodes.iter().map(
-            |node| node.inputs.iter().filter_map(|&o| o).collect()
-        ).collect();
-        let mut no_inputs: VecDeque<_> = inputs.iter().enumerate().filter_map(
-            |(i, ins)| if ins.len() == 0 { Some(i) } else { None }
-        ).collect();
+    /// Mutes or unmutes a node, silencing all of its outputs. Returns an Err
+    /// if the node doesn't exist.
+    pub fn set_mute(&mut self, node: AudioNodeIdx, muted: bool) -> Result<()> {
+        match self.node_mut(node) {
+            Some(node) => node.muted = muted,
+            None => return Err(Error::OutOfRange("node"))
+        }
+        self.update_mutes();
+        Ok(())
+    }
 
-        // While there are nodes with no input, we choose one, add it as the
-        // next node in our topology, and remove all edges from that node. Any
-        // nodes that lose their final edge are added to the edgeless set.
-        loop {
-            match no_inputs.pop_front() {
-                Some(i) => {
-                    topology.push(i);
-                    let (out_start, out_end) = self.nodes[i].outputs;
-                    for out in out_start..out_end {
-                        for (j, ins) in inputs.iter_mut().enumerate() {
-                            let mut idx = None;
-                            for k in 0..ins.len() {
-                                if ins[k] == out {
-                                    idx = Some(k);
-                                    break;
-                                }
-                            }
-                            match idx {
-                                Some(k) => {
-                                    ins.swap_remove(k);
-                                    if ins.len() == 0 {
-                                        no_inputs.push_back(j);
-                                    }
-                                },
-                                None => ()
-                            }
-                        }
-                    }
-                },
-                None => break
-            }
+    /// Sets whether a node's device stops processing while the node is
+    /// silent, from being muted or outside a solo. Returns an Err if the node
+    /// doesn't exist.
+    ///
+    /// Skipping saves processing time, but the device's state, such as the
+    /// phase of an oscillator, stops advancing while it is silent.
+    pub fn set_skip_muted(&mut self, node: AudioNodeIdx, skip: bool)
+            -> Result<()> {
+        match self.node_mut(node) {
+            Some(node) => {
+                node.skip_muted = skip;
+                Ok(())
+            },
+            None => Err(Error::OutOfRange("node"))
         }
+    }
 
-        if topology.len() == self.nodes.len() {
-            self.topology = topology;
-            Ok(())
-        } else {
-            self.nodes[dest_i].inputs[dest_ch] = None;
-            Err(Error::CreatesCycle)
+    /// Returns true if the node is soloed, or an Err if the node doesn't
+    /// exist.
+    pub fn is_soloed(&self, node: AudioNodeIdx) -> Result<bool> {
+        self.node(node).map(|node| node.soloed)
+            .ok_or(Error::OutOfRange("node"))
+    }
+
+    /// Solos a node, or removes its solo. Returns an Err if the node doesn't
+    /// exist.
+    ///
+    /// While any node is soloed, only the soloed nodes, and the nodes along
+    /// any path into or out of them, are audible. Every other node is muted.
+    pub fn set_solo(&mut self, node: AudioNodeIdx, soloed: bool) -> Result<()> {
+        match self.node_mut(node) {
+            Some(node) => node.soloed = soloed,
+            None => return Err(Error::OutOfRange("node"))
         }
+        self.update_mutes();
+        Ok(())
     }
-}
 
-impl Tick for DeviceGraph {
-    fn tick(&mut self) {
-        for &i in self.topology.iter() {
-            self.nodes[i].tick(self.time, &mut self.bus);
+    /// Returns the time taken to crossfade into a replaced device, in seconds.
+    pub fn get_crossfade_time(&self) -> f32 {
+        self.crossfade
+    }
+
+    /// Sets the time taken to crossfade into a replaced device, in seconds.
+    /// A time of zero swaps devices instantly.
+    pub fn set_crossfade_time(&mut self, seconds: f32) {
+        self.crossfade = seconds;
+    }
+
+    /// Returns an editor, that can change the graph from another thread.
+    pub fn get_editor(&self) -> GraphEditor<S> {
+        GraphEditor::new(self.edit_tx.clone())
+    }
+
+    /// Applies every edit received from the graph's editors, in the order they
+    /// were sent. Edits that fail are reported back to the editor that sent
+    /// them.
+    ///
+    /// This is called at the start of every tick.
+    pub fn apply_edits(&mut self) {
+        while let Ok((edit, errors)) = self.edit_rx.try_recv() {
+            if let Err(e) = self.apply_edit(edit) {
+                let _ = errors.send(e);
+            }
         }
-        self.time += 1;
     }
-}
 
+    /// Returns the number of threads the graph is processed on.
+    pub fn get_num_threads(&self) -> usize {
+        self.num_threads
+    }
 
-/// An identifier used to refer back to a node in the graph.
-#[derive(Copy, Clone, Debug)]
-pub struct AudioNodeIdx(usize);
+    /// Sets the number of threads the graph is processed on. With a single
+    /// thread, the default, every node is ticked in turn on the calling thread.
+    ///
+    /// With more threads, the graph is split into stages of nodes that don't
+    /// depend on each other. The nodes of each stage that were added with
+    /// `add_parallel_node` are spread across up to `threads` threads, while
+    /// the rest are ticked on the calling thread. The output is identical to
+    /// processing on a single thread.
+    ///
+    /// Threads are started for each stage of every block, so this only pays
+    /// off when the stages hold several expensive devices, or the block size
+    /// is large. Blocks split into pieces shorter than `MIN_PARALLEL_FRAMES`
+    /// by short feedback delays are processed on the calling thread.
+    pub fn set_num_threads(&mut self, threads: usize) {
+        self.num_threads = max(threads, 1);
+    }
 
+    /// Returns a handle to the transport shared by every device in the graph.
+    pub fn get_transport(&self) -> TransportHandle {
+        self.transport.clone()
+    }
 
-/// A wrapper for a node in the graph.
-///
-/// Management of indices in the bus is handled in the graph itself.
-struct AudioNode {
-    device: Box<AudioDevice>, // wraps the device
-    inputs: Vec<Option<usize>>, // bus indices of the inputs
-    input_buf: Vec<Sample>, // an allocated buffer for containing inputs
-    outputs: (usize, usize) // the range of outputs in the bus
-}
+    /// Adds a new device into the graph, with no connections. Returns
+    /// a identifier that refers back to this device.
+    ///
+    /// The slot and bus channels of previously removed nodes are reused.
+    pub fn add_node<D>(&mut self, device: D) -> AudioNodeIdx
+            where D: 'static+AudioDevice<S> {
+        self.add_device(Box::new(device), false)
+    }
 
-impl AudioNode {
-    /// Wraps the device in a new node
-    fn new<D>(device: D, bus: &mut Vec<Sample>) -> AudioNode
-            where D: 'static+AudioDevice {
-        let num_in = device.num_inputs();
-        let num_out = device.num_outputs();
-        let start = bus.len();
-        for _ in 0..num_out {
-            bus.push(0.0);
+    /// Adds a new device into the graph, like `add_node`, and allows it to be
+    /// processed on a worker thread. Returns a identifier that refers back to
+    /// this device.
+    ///
+    /// Only devices added this way are spread across threads; see
+    /// `set_num_threads`. A device that keeps the graph's transport is not
+    /// `Send`, and must be added with `add_node` instead.
+    pub fn add_parallel_node<D>(&mut self, device: D) -> AudioNodeIdx
+            where D: 'static+AudioDevice<S>+Send {
+        self.add_device(Box::new(device), true)
+    }
+
+    /// Adds a boxed device into the graph, and returns its identifier.
+    fn add_device(&mut self, mut device: Box<AnyDevice<S>>, parallel: bool)
+            -> AudioNodeIdx {
+        device.prepare(self.sample_rate, self.block_size);
+        device.set_transport(self.transport.clone());
+        let start = self.allocate_bus(device.num_outputs());
+        let mut node = AudioNode::new(device, start, self.sample_rate,
+                                      self.block_size);
+        node.parallel = parallel;
+        let i = match self.nodes.iter().position(|node| node.is_none()) {
+            Some(i) => {
+                self.nodes[i] = Some(node);
+                i
+            },
+            None => {
+                self.nodes.push(Some(node));
+                self.generations.push(0);
+                self.nodes.len() - 1
+            }
+        };
+        self.topology.push(i);
+        self.compute_stages();
+        self.update_mutes();
+        self.compensate_latency();
+        self.idx(i)
+    }
+
+    /// Replaces the device of a node, keeping all its edges. The old device
+    /// crossfades into the new one over the graph's crossfade time.
+    ///
+    /// The new device must have the same number of inputs and outputs as the
+    /// old one. Otherwise, or if the node doesn't exist, an Err is returned and
+    /// no changes to the graph are made.
+    ///
+    /// Like a device added with `add_node`, the new device is processed on the
+    /// calling thread.
+    pub fn replace_node<D>(&mut self, node: AudioNodeIdx, device: D)
+            -> Result<()> where D: 'static+AudioDevice<S> {
+        self.replace_device(node, Box::new(device), false)
+    }
+
+    /// Replaces the device of a node, like `replace_node`, and allows the new
+    /// device to be processed on a worker thread, like `add_parallel_node`.
+    pub fn replace_parallel_node<D>(&mut self, node: AudioNodeIdx, device: D)
+            -> Result<()> where D: 'static+AudioDevice<S>+Send {
+        let parallel = self.node(node).is_some_and(|node| node.parallel);
+        self.replace_device(node, Box::new(device), parallel)
+    }
+
+    /// Replaces the device of a node, crossfading from the old device.
+    ///
+    /// The node is only processed on worker threads if `parallel` is true and
+    /// the old device was too, since the old device is still processed until
+    /// it fades out.
+    fn replace_device(&mut self, node: AudioNodeIdx,
+                      mut device: Box<AnyDevice<S>>, parallel: bool)
+            -> Result<()> {
+        let length = (self.crossfade * self.sample_rate as f32).round();
+        let (sample_rate, block_size) = (self.sample_rate, self.block_size);
+        let transport = self.transport.clone();
+        {
+            let node = match self.node_mut(node) {
+                Some(node) => node,
+                None => return Err(Error::OutOfRange("node"))
+            };
+            if device.num_inputs() != node.device.num_inputs() ||
+                    device.num_outputs() != node.device.num_outputs() {
+                return Err(Error::Unsupported(
+                    "replacing a device with a different number of channels"));
+            }
+            device.prepare(sample_rate, block_size);
+            device.set_transport(transport);
+            let old = ::std::mem::replace(&mut node.device, device);
+            node.parallel = parallel;
+            node.fade = if length >= 1.0 {
+                Some(Fade {
+                    device: old,
+                    output_buf: vec![S::zero(); node.output_buf.len()],
+                    elapsed: 0,
+                    length: length as usize
+                })
+            } else {
+                None
+            };
         }
-        let end = bus.len();
+        self.compensate_latency();
+        Ok(())
+    }
 
-        AudioNode {
-            device: Box::new(device),
-            inputs: vec![None; num_in],
-            input_buf: vec![0.0; num_in],
-            outputs: (start, end)
+    /// Removes a node from the graph, along with every edge to or from it.
+    /// Returns the node's device, or an Err if the node doesn't exist.
+    ///
+    /// The identifier of the removed node becomes stale: using it again
+    /// returns an Err, even once its slot is reused by a new node.
+    pub fn remove_node(&mut self, node: AudioNodeIdx)
+            -> Result<Box<AnyDevice<S>>> {
+        let i = match self.index(node) {
+            Some(i) => i,
+            None => return Err(Error::OutOfRange("node"))
+        };
+        if self.input_port == Some(node) {
+            self.input_port = None;
+            self.input = Vec::new();
+        }
+        if self.output_port == Some(node) {
+            self.output_port = None;
+            self.output = Vec::new();
+        }
+        let node = self.nodes[i].take().unwrap();
+        self.generations[i] += 1;
+
+        // Disconnect any nodes listening to our outputs, then free the bus
+        let (start, end) = node.outputs;
+        for other in self.nodes.iter_mut().filter_map(|node| node.as_mut()) {
+            for input in other.inputs.iter_mut() {
+                input.retain(|conn| conn.ch < start || end <= conn.ch);
+            }
+            other.feedback.retain(|fb| fb.ch < start || end <= fb.ch);
+        }
+        for ch in start..end {
+            self.bus_used[ch] = false;
+            for s in self.bus[ch*self.block_size..(ch+1)*self.block_size]
+                    .iter_mut() {
+                *s = S::zero();
+            }
         }
+
+        // Removing a node can't create a cycle
+        self.topological_sort();
+        Ok(node.device)
     }
 
-    /// Extracts the inputs out of the bus, tick the device and place the outputs
-    /// back into the bus.
-    fn tick(&mut self, t: Time, bus: &mut[Sample]) {
-        for (i, ch) in self.inputs.iter().enumerate() {
-            self.input_buf[i] = ch.map_or(0.0, |j| bus[j]);
+    /// Adds a node that carries `channels` external inputs into the graph, and
+    /// returns its identifier. Connect edges from its outputs to feed the
+    /// inputs to other devices.
+    ///
+    /// When the graph is ticked by a container, the node outputs the inputs
+    /// passed to the graph. When the graph is ticked on its own, it outputs
+    /// the contents of `get_input`.
+    ///
+    /// Any previous input node is removed, along with its edges.
+    pub fn set_inputs(&mut self, channels: usize) -> AudioNodeIdx {
+        if let Some(node) = self.input_port {
+            self.remove_node(node).unwrap();
         }
-        let (start, end) = self.outputs;
-        self.device.tick(t, &self.input_buf, &mut bus[start..end]);
+        let node = self.add_node(Port { num_inputs: 0, num_outputs: channels });
+        self.input_port = Some(node);
+        self.input = vec![S::zero(); channels*self.block_size];
+        node
     }
-}
 
+    /// Adds a node that carries `channels` external outputs out of the graph,
+    /// and returns its identifier. Connect edges into its inputs to send
+    /// signals out of the graph.
+    ///
+    /// Any previous output node is removed, along with its edges.
+    pub fn set_outputs(&mut self, channels: usize) -> AudioNodeIdx {
+        if let Some(node) = self.output_port {
+            self.remove_node(node).unwrap();
+        }
+        let node = self.add_node(Port { num_inputs: channels, num_outputs: 0 });
+        self.output_port = Some(node);
+        self.output = vec![S::zero(); channels*self.block_size];
+        node
+    }
 
-#[cfg(test)]
-mod test {
-    use testing::MockAudioDevice;
-    use super::{DeviceGraph, Tick};
+    /// Returns the node carrying external inputs, if there is one.
+    pub fn get_input_node(&self) -> Option<AudioNodeIdx> {
+        self.input_port
+    }
 
-    #[test]
-    fn test_empty_graph() {
-        DeviceGraph::new().tick();
+    /// Returns the node carrying external outputs, if there is one.
+    pub fn get_output_node(&self) -> Option<AudioNodeIdx> {
+        self.output_port
     }
 
-    #[test]
-    fn test_one_node() {
-        let mut mock = MockAudioDevice::new("mock", 1, 1);
-        mock.will_tick(&[0.0], &[1.0]);
+    /// Returns a mutable slice to the external inputs of the graph.
+    ///
+    /// The input holds one block of interleaved frames. These inputs never get
+    /// overwritten, so if you are supplying input you must manually zero the
+    /// buffer again.
+    pub fn get_input(&mut self) -> &mut[S] {
+        &mut self.input
+    }
 
-        let mut graph = DeviceGraph::new();
-        graph.add_node(mock);
-        graph.tick();
+    /// Returns a slice to the external outputs of the graph.
+    ///
+    /// The output holds the last block of interleaved frames.
+    pub fn get_graph_output(&self) -> &[S] {
+        &self.output
     }
 
-    #[test]
-    fn test_disconnected() {
-        let mut mock1 = MockAudioDevice::new("mock1", 1, 1);
-        let mut mock2 = MockAudioDevice::new("mock2", 1, 1);
-        mock1.will_tick(&[0.0], &[1.0]);
-        mock2.will_tick(&[0.0], &[2.0]);
+    /// Returns true if the node exists in the graph.
+    pub fn contains_node(&self, node: AudioNodeIdx) -> bool {
+        self.index(node).is_some()
+    }
 
-        let mut graph = DeviceGraph::new();
-        graph.add_node(mock1);
-        graph.add_node(mock2);
-        graph.tick();
+    /// Returns the parameters of a node's device, or `None` if the device has
+    /// no parameters or the node doesn't exist.
+    pub fn get_parameters(&self, node: AudioNodeIdx) -> Option<&Parameters> {
+        self.node(node).and_then(|node| node.device.parameters())
     }
 
-    #[test]
-    fn test_linear() {
-        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
-        let mut mock2 = MockAudioDevice::new("mock2", 1, 0);
-        mock1.will_tick(&[], &[1.0]);
-        mock2.will_tick(&[1.0], &[]);
+    /// Returns the parameters of a node's device mutably, or `None` if the
+    /// device has no parameters or the node doesn't exist.
+    pub fn get_parameters_mut(&mut self, node: AudioNodeIdx)
+            -> Option<&mut Parameters> {
+        self.node_mut(node).and_then(|node| node.device.parameters_mut())
+    }
 
-        let mut graph = DeviceGraph::new();
-        let mock1 = graph.add_node(mock1);
-        let mock2 = graph.add_node(mock2);
-        graph.add_edge(mock1, 0, mock2, 0).unwrap();
-        graph.tick();
+    /// Returns a handle to a node whose device has type `D`, or `None` if the
+    /// node doesn't exist or its device has another type.
+    ///
+    /// Nodes added through a boxed device, such as those built from a patch,
+    /// have the type of the box.
+    pub fn get_handle<D>(&self, node: AudioNodeIdx) -> Option<NodeHandle<D>>
+            where D: 'static+AudioDevice<S> {
+        self.node(node).and_then(|n| (*n.device).as_any().downcast_ref::<D>())
+            .map(|_| NodeHandle { node: node, device: PhantomData })
     }
 
-    #[test]
-    fn test_complex() {
-        let mut mock1 = MockAudioDevice::new("mock1", 1, 1);
-        let mut mock2 = MockAudioDevice::new("mock2", 1, 1);
-        let mut mock3 = MockAudioDevice::new("mock3", 2, 1);
-        let mut mock4 = MockAudioDevice::new("mock4", 1, 1);
-        let mut mock5 = MockAudioDevice::new("mock5", 1, 1);
+    /// Returns the device of a node, or `None` if the node doesn't exist or
+    /// its device has been replaced by one of another type.
+    pub fn get_device<D>(&self, handle: NodeHandle<D>) -> Option<&D>
+            where D: 'static+AudioDevice<S> {
+        self.node(handle.node)
+            .and_then(|node| (*node.device).as_any().downcast_ref())
+    }
 
-        mock1.will_tick(&[0.0], &[1.0]);
-        mock2.will_tick(&[4.0], &[2.0]);
-        mock3.will_tick(&[2.0, 4.0], &[3.0]);
-        mock4.will_tick(&[1.0], &[4.0]);
-        mock5.will_tick(&[0.0], &[5.0]);
+    /// Returns the device of a node mutably, or `None` if the node doesn't
+    /// exist or its device has been replaced by one of another type.
+    pub fn get_device_mut<D>(&mut self, handle: NodeHandle<D>)
+            -> Option<&mut D> where D: 'static+AudioDevice<S> {
+        self.node_mut(handle.node)
+            .and_then(|node| (*node.device).as_any_mut().downcast_mut())
+    }
 
-        let mut graph = DeviceGraph::new();
-        let mock1 = graph.add_node(mock1);
-        let mock2 = graph.add_node(mock2);
-        let mock3 = graph.add_node(mock3);
-        let mock4 = graph.add_node(mock4);
-        let _mock5 = graph.add_node(mock5);
-        graph.add_edge(mock1, 0, mock4, 0).unwrap();
-        graph.add_edge(mock4, 0, mock2, 0).unwrap();
-        graph.add_edge(mock2, 0, mock3, 0).unwrap();
-        graph.add_edge(mock4, 0, mock3, 1).unwrap();
+    /// Passes a message straight to the device of a node. Returns an Err if
+    /// the node doesn't exist or its device has been replaced by one of
+    /// another type.
+    ///
+    /// The message is handled immediately, so this must be called from the
+    /// thread that ticks the graph. Use a `Messaged` device to send messages
+    /// from other threads.
+    pub fn send_message<D>(&mut self, handle: NodeHandle<D>, msg: D::Msg)
+            -> Result<()> where D: 'static+AudioDevice<S>+MessageReceiver {
+        match self.get_device_mut(handle) {
+            Some(device) => {
+                device.handle_message(msg);
+                Ok(())
+            },
+            None => Err(Error::OutOfRange("node"))
+        }
+    }
+
+    /// Returns the outputs of a node from the last tick, or an Err if the node
+    /// doesn't exist.
+    ///
+    /// The outputs hold one block per channel, so channel `i` occupies
+    /// `i*block_size..(i+1)*block_size` of the slice.
+    pub fn get_output(&self, node: AudioNodeIdx) -> Result<&[S]> {
+        match self.node(node) {
+            Some(node) => {
+                let (start, end) = node.outputs;
+                Ok(&self.bus[start*self.block_size..end*self.block_size])
+            },
+            None => Err(Error::OutOfRange("node"))
+        }
+    }
+
+    /// Returns the identifiers of every node in the graph, in the order of
+    /// their slots.
+    pub fn get_nodes(&self) -> Vec<AudioNodeIdx> {
+        (0..self.nodes.len()).filter(|&i| self.nodes[i].is_some())
+            .map(|i| self.idx(i)).collect()
+    }
+
+    /// Returns every edge into a node, or an Err if the node doesn't exist.
+    pub fn get_inputs(&self, node: AudioNodeIdx) -> Result<Vec<Edge>> {
+        let i = match self.index(node) {
+            Some(i) => i,
+            None => return Err(Error::OutOfRange("node"))
+        };
+        let mut edges = Vec::new();
+        let inputs = &self.nodes[i].as_ref().unwrap().inputs;
+        for (dest_ch, input) in inputs.iter().enumerate() {
+            for conn in input.iter() {
+                let (src, src_ch) = self.source(conn.ch).unwrap();
+                edges.push(Edge {
+                    src: src,
+                    src_ch: src_ch,
+                    dest: node,
+                    dest_ch: dest_ch,
+                    gain: conn.gain.into_f32(),
+                    delay: 0
+                });
+            }
+        }
+        for fb in self.nodes[i].as_ref().unwrap().feedback.iter() {
+            let (src, src_ch) = self.source(fb.ch).unwrap();
+            edges.push(Edge {
+                src: src,
+                src_ch: src_ch,
+                dest: node,
+                dest_ch: fb.dest_ch,
+                gain: fb.gain.into_f32(),
+                delay: fb.history.capacity()
+            });
+        }
+        Ok(edges)
+    }
+
+    /// Returns every edge out of a node, or an Err if the node doesn't exist.
+    pub fn get_outputs(&self, node: AudioNodeIdx) -> Result<Vec<Edge>> {
+        if !self.contains_node(node) {
+            return Err(Error::OutOfRange("node"));
+        }
+        Ok(self.get_edges().into_iter().filter(|edge| edge.src == node)
+           .collect())
+    }
+
+    /// Returns every edge in the graph, grouped by destination node.
+    pub fn get_edges(&self) -> Vec<Edge> {
+        let mut edges = Vec::new();
+        for dest in self.get_nodes() {
+            edges.extend(self.get_inputs(dest).unwrap());
+        }
+        edges
+    }
+
+    /// Returns the type a node's device was created from, if it is known.
+    ///
+    /// Nodes loaded from a patch record their type. Only nodes with a known
+    /// type can be exported back into a patch.
+    pub fn get_device_type(&self, node: AudioNodeIdx) -> Option<&DeviceType> {
+        self.node(node).and_then(|node| node.device_type.as_ref())
+    }
+
+    /// Records the type a node's device was created from, so that the node
+    /// can be exported into a patch. Returns an Err if the node doesn't exist.
+    pub fn set_device_type(&mut self, node: AudioNodeIdx,
+                           device_type: DeviceType) -> Result<()> {
+        match self.node_mut(node) {
+            Some(node) => {
+                node.device_type = Some(device_type);
+                Ok(())
+            },
+            None => Err(Error::OutOfRange("node"))
+        }
+    }
+
+    /// Returns the name of a node, or `None` if it has no name or doesn't
+    /// exist.
+    pub fn get_name(&self, node: AudioNodeIdx) -> Option<&str> {
+        self.node(node).and_then(|node| node.name.as_ref())
+            .map(|name| &name[..])
+    }
+
+    /// Returns the first node with the name `name`, if there is one.
+    pub fn find_node(&self, name: &str) -> Option<AudioNodeIdx> {
+        self.get_nodes().into_iter()
+            .find(|&node| self.get_name(node) == Some(name))
+    }
+
+    /// Names a node, to label it when inspecting the graph. Returns an Err if
+    /// the node doesn't exist.
+    ///
+    /// Names don't need to be unique, but nodes loaded from a patch are named
+    /// after their ids, and named nodes keep their name when exported.
+    pub fn set_name(&mut self, node: AudioNodeIdx, name: &str) -> Result<()> {
+        match self.node_mut(node) {
+            Some(node) => {
+                node.name = Some(String::from(name));
+                Ok(())
+            },
+            None => Err(Error::OutOfRange("node"))
+        }
+    }
+
+    /// Returns the graph in the Graphviz DOT language.
+    ///
+    /// Each node is labelled with its name or device type, and its position in
+    /// the processing order. Each edge is labelled with its source and
+    /// destination channels, along with its gain if it isn't 1. Feedback edges
+    /// are dashed, and labelled with their delay.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n    rankdir=LR;\n");
+        for (order, &i) in self.topology.iter().enumerate() {
+            let shape = if self.is_port(i) { ", shape=box" } else { "" };
+            dot.push_str(&format!("    n{} [label=\"{}\\n#{}\"{}];\n", i,
+                                  self.label(i).replace("\"", "\\\""),
+                                  order, shape));
+        }
+        for edge in self.get_edges() {
+            let mut label = format!("{}:{}", edge.src_ch, edge.dest_ch);
+            if edge.gain != 1.0 {
+                label.push_str(&format!(" x{}", edge.gain));
+            }
+            let style = if edge.delay > 0 {
+                label.push_str(&format!(" z-{}", edge.delay));
+                ", style=dashed"
+            } else {
+                ""
+            };
+            dot.push_str(&format!("    n{} -> n{} [label=\"{}\"{}];\n",
+                                  edge.src.index, edge.dest.index, label,
+                                  style));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Returns a description of the graph in text.
+    ///
+    /// The summary lists every node in processing order with its number of
+    /// channels, then any inputs with no edges into them, and any outputs
+    /// with no edges out of them.
+    pub fn summary(&self) -> String {
+        let edges = self.get_edges();
+        let mut text = format!(
+            "{} nodes, {} edges, {} Hz, {} frames per block, {} samples \
+             latency\n", self.topology.len(), edges.len(), self.sample_rate,
+            self.block_size, self.latency);
+
+        text.push_str("Processing order:\n");
+        for (order, &i) in self.topology.iter().enumerate() {
+            let node = self.nodes[i].as_ref().unwrap();
+            text.push_str(&format!("    {}: {}, {} inputs, {} outputs\n",
+                                   order, self.label(i), node.inputs.len(),
+                                   node.device.num_outputs()));
+        }
+
+        let mut unconnected = Vec::new();
+        let mut unused = Vec::new();
+        for &i in self.topology.iter() {
+            let node = self.nodes[i].as_ref().unwrap();
+            let idx = self.idx(i);
+            for ch in 0..node.inputs.len() {
+                if !edges.iter().any(|e| e.dest == idx && e.dest_ch == ch) {
+                    unconnected.push(format!("{} input {}", self.label(i), ch));
+                }
+            }
+            for ch in 0..node.device.num_outputs() {
+                if !edges.iter().any(|e| e.src == idx && e.src_ch == ch) {
+                    unused.push(format!("{} output {}", self.label(i), ch));
+                }
+            }
+        }
+        for &(title, list) in [("Unconnected inputs", &unconnected),
+                               ("Unused outputs", &unused)].iter() {
+            if list.is_empty() {
+                text.push_str(&format!("{}: none\n", title));
+            } else {
+                text.push_str(&format!("{}:\n", title));
+                for item in list.iter() {
+                    text.push_str(&format!("    {}\n", item));
+                }
+            }
+        }
+        text
+    }
+
+    /// Connects two devices in the graph. 
+    ///
+    /// * `src` and `dest` are identifiers for the actual devices to connect.
+    /// * `src_ch` and `dest_ch` are the channel indices of the two devices.
+    ///
+    /// An input channel may be connected to any number of outputs, in which
+    /// case it receives the sum of them.
+    ///
+    /// If invalid indices are provided, or if the specified edge would create
+    /// a cycle in the graph, an Err is returned and no changes dest the graph are
+    /// made.
+    pub fn add_edge(&mut self, src: AudioNodeIdx, src_ch: usize,
+                    dest: AudioNodeIdx, dest_ch: usize) -> Result<()> {
+        self.add_edge_with_gain(src, src_ch, dest, dest_ch, 1.0)
+    }
+
+    /// Connects two devices in the graph, scaling the signal along the edge by
+    /// `gain`.
+    ///
+    /// If the edge already exists, its gain is changed instead. Otherwise this
+    /// behaves exactly like `add_edge`.
+    pub fn add_edge_with_gain(&mut self, src: AudioNodeIdx, src_ch: usize,
+                              dest: AudioNodeIdx, dest_ch: usize, gain: f32)
+            -> Result<()> {
+        let (ch, dest_i) = try!(self.check_edge(src, src_ch, dest, dest_ch));
+        let gain = S::from_f32(gain);
+
+        // Update an existing edge, or else add it
+        {
+            let input = &mut self.nodes[dest_i].as_mut().unwrap()
+                .inputs[dest_ch];
+            match input.iter().position(|conn| conn.ch == ch) {
+                Some(k) => {
+                    input[k].gain = gain;
+                    return Ok(());
+                },
+                None => input.push(Connection::new(ch, gain))
+            }
+        }
+
+        // Remove the edge again if we created a cycle
+        if self.topological_sort() {
+            Ok(())
+        } else {
+            self.nodes[dest_i].as_mut().unwrap().inputs[dest_ch].pop();
+            Err(Error::CreatesCycle)
+        }
+    }
+
+    /// Connects two devices in the graph with a feedback edge, that delays the
+    /// signal by `delay` samples and scales it by `gain`.
+    ///
+    /// Unlike normal edges, feedback edges may create a cycle, including from
+    /// a device back into itself. A feedback input is summed with any normal
+    /// edges into the same input.
+    ///
+    /// If the feedback edge already exists, its delay and gain are changed
+    /// instead. If invalid indices are provided, or `delay` is zero, an Err is
+    /// returned and no changes to the graph are made.
+    pub fn add_feedback_edge(&mut self, src: AudioNodeIdx, src_ch: usize,
+                             dest: AudioNodeIdx, dest_ch: usize, delay: usize,
+                             gain: f32) -> Result<()> {
+        let (ch, dest_i) = try!(self.check_edge(src, src_ch, dest, dest_ch));
+        if delay == 0 {
+            return Err(Error::OutOfRange("delay"));
+        }
+
+        let feedback = Feedback::new(dest_ch, ch, S::from_f32(gain), delay);
+        let node = self.nodes[dest_i].as_mut().unwrap();
+        match node.feedback.iter()
+                .position(|fb| fb.ch == ch && fb.dest_ch == dest_ch) {
+            Some(k) => node.feedback[k] = feedback,
+            None => node.feedback.push(feedback)
+        }
+        self.update_mutes();
+        Ok(())
+    }
+
+    /// Disconnects an edge previously added with `add_edge` or
+    /// `add_feedback_edge`.
+    ///
+    /// If invalid indices are provided, or the edge doesn't exist, an Err is
+    /// returned and no changes to the graph are made.
+    pub fn remove_edge(&mut self, src: AudioNodeIdx, src_ch: usize,
+                       dest: AudioNodeIdx, dest_ch: usize) -> Result<()> {
+        let (ch, dest_i) = try!(self.check_edge(src, src_ch, dest, dest_ch));
+        {
+            let input = &mut self.nodes[dest_i].as_mut().unwrap()
+                .inputs[dest_ch];
+            if let Some(k) = input.iter().position(|conn| conn.ch == ch) {
+                input.remove(k);
+            } else {
+                let feedback = &mut self.nodes[dest_i].as_mut().unwrap()
+                    .feedback;
+                match feedback.iter()
+                        .position(|fb| fb.ch == ch && fb.dest_ch == dest_ch) {
+                    Some(k) => feedback.remove(k),
+                    None => return Err(Error::OutOfRange("edge"))
+                };
+            }
+        }
+
+        // Removing an edge can't create a cycle
+        self.topological_sort();
+        Ok(())
+    }
+
+    /// Checks the nodes and channels of an edge, then returns the bus channel
+    /// of the source and the slot of the destination.
+    fn check_edge(&self, src: AudioNodeIdx, src_ch: usize,
+                  dest: AudioNodeIdx, dest_ch: usize)
+            -> Result<(usize, usize)> {
+        // Check device indices
+        let src = match self.node(src) {
+            Some(node) => node,
+            None => return Err(Error::OutOfRange("src"))
+        };
+        let dest_i = match self.index(dest) {
+            Some(i) => i,
+            None => return Err(Error::OutOfRange("dest"))
+        };
+
+        // Check channels
+        let (start, end) = src.outputs;
+        if end - start <= src_ch {
+            return Err(Error::OutOfRange("src_ch"));
+        }
+        if self.nodes[dest_i].as_ref().unwrap().inputs.len() <= dest_ch {
+            return Err(Error::OutOfRange("dest_ch"));
+        }
+        Ok((start + src_ch, dest_i))
+    }
+
+    /// Determines the topology of our device graph. If the graph has a cycle,
+    /// then the topology is left unchanged and false is returned. Otherwise,
+    /// we set self.topology to a topologically sorted order.
+    fn topological_sort(&mut self) -> bool {
+        // Intialize our set of input edges, and our set of edgeless nodes
+        let mut topology = Vec::new();
+        let mut inputs: Vec<Vec<_>> = self.nodes.iter().map(|node| match *node {
+            Some(ref node) => node.inputs.iter()
+                .flat_map(|input| input.iter().map(|conn| conn.ch)).collect(),
+            None => Vec::new()
+        }).collect();
+        let mut no_inputs: VecDeque<_> = (0..self.nodes.len()).filter(
+            |&i| self.nodes[i].is_some() && inputs[i].is_empty()
+        ).collect();
+
+        // While there are nodes with no input, we choose one, add it as the
+        // next node in our topology, and remove all edges from that node. Any
+        // nodes that lose their final edge are added to the edgeless set.
+        while let Some(i) = no_inputs.pop_front() {
+            topology.push(i);
+            let (start, end) = self.nodes[i].as_ref().unwrap().outputs;
+            for (j, ins) in inputs.iter_mut().enumerate() {
+                if ins.is_empty() {
+                    continue;
+                }
+                ins.retain(|&ch| ch < start || end <= ch);
+                if ins.is_empty() {
+                    no_inputs.push_back(j);
+                }
+            }
+        }
+
+        if topology.len() == self.nodes.iter().filter(|n| n.is_some()).count() {
+            self.topology = topology;
+            self.compute_stages();
+            self.update_mutes();
+            self.compensate_latency();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Computes the latency along the longest path to every node, then sets
+    /// the input delays of each node so that all its inputs arrive aligned.
+    ///
+    /// Nodes are visited in topological order, so the latency of every input
+    /// is known before the node itself is visited.
+    fn compensate_latency(&mut self) {
+        let mut bus_latency = vec![0; self.bus_used.len()];
+        self.latency = 0;
+        for &i in self.topology.iter() {
+            let node = self.nodes[i].as_mut().unwrap();
+            let arrival = node.inputs.iter()
+                .flat_map(|input| input.iter().map(|conn| bus_latency[conn.ch]))
+                .max().unwrap_or(0);
+            node.set_delays(arrival, &bus_latency);
+
+            let latency = arrival + node.device.latency();
+            let (start, end) = node.outputs;
+            for l in bus_latency[start..end].iter_mut() {
+                *l = latency;
+            }
+            self.latency = max(self.latency, latency);
+        }
+    }
+
+    /// Reserves `n` consecutive bus channels, and returns the first.
+    ///
+    /// The first gap left by removed nodes that is large enough is used,
+    /// otherwise the bus is extended.
+    fn allocate_bus(&mut self, n: usize) -> usize {
+        // Find the first run of n free channels, or the run at the end
+        let mut start = 0;
+        let mut run = 0;
+        for &used in self.bus_used.iter() {
+            if run == n {
+                break;
+            } else if used {
+                start += run + 1;
+                run = 0;
+            } else {
+                run += 1;
+            }
+        }
+
+        for ch in start..start+n {
+            if ch < self.bus_used.len() {
+                self.bus_used[ch] = true;
+            } else {
+                self.bus_used.push(true);
+                for _ in 0..self.block_size {
+                    self.bus.push(S::zero());
+                }
+            }
+        }
+        start
+    }
+
+    /// Applies a single edit.
+    fn apply_edit(&mut self, edit: Edit<S>) -> Result<()> {
+        match edit {
+            AddNode(name, device) => {
+                let node = self.add_device(device, true);
+                self.set_name(node, &name)
+            },
+            RemoveNode(node) => {
+                let node = try!(self.resolve(node));
+                self.remove_node(node).map(|_| ())
+            },
+            ReplaceNode(node, device) => {
+                let node = try!(self.resolve(node));
+                let parallel = self.node(node)
+                    .is_some_and(|node| node.parallel);
+                self.replace_device(node, device, parallel)
+            },
+            AddEdge(src, src_ch, dest, dest_ch, gain) => {
+                let src = try!(self.resolve(src));
+                let dest = try!(self.resolve(dest));
+                self.add_edge_with_gain(src, src_ch, dest, dest_ch, gain)
+            },
+            RemoveEdge(src, src_ch, dest, dest_ch) => {
+                let src = try!(self.resolve(src));
+                let dest = try!(self.resolve(dest));
+                self.remove_edge(src, src_ch, dest, dest_ch)
+            }
+        }
+    }
+
+    /// Returns the identifier of a node referred to by an edit.
+    fn resolve(&self, node: NodeRef) -> Result<AudioNodeIdx> {
+        let node = match node {
+            NodeRef::Idx(node) => Some(node),
+            NodeRef::Name(name) => self.find_node(&name)
+        };
+        match node {
+            Some(node) if self.contains_node(node) => Ok(node),
+            _ => Err(Error::OutOfRange("node"))
+        }
+    }
+
+    /// Ramps the gain of every node towards silence if it is muted or outside
+    /// a solo, or towards unity otherwise.
+    fn update_mutes(&mut self) {
+        let n = self.nodes.len();
+        let soloed: Vec<_> = (0..n).filter(|&i| {
+            self.nodes[i].as_ref().is_some_and(|node| node.soloed)
+        }).collect();
+
+        // Find every node connected to a soloed node, both up and downstream
+        let mut audible = vec![soloed.is_empty(); n];
+        if !soloed.is_empty() {
+            let edges: Vec<_> = self.get_edges().iter()
+                .map(|edge| (edge.src.index, edge.dest.index)).collect();
+            for &downstream in [false, true].iter() {
+                let mut visited = vec![false; n];
+                let mut stack = soloed.clone();
+                while let Some(i) = stack.pop() {
+                    if visited[i] {
+                        continue;
+                    }
+                    visited[i] = true;
+                    audible[i] = true;
+                    for &(src, dest) in edges.iter() {
+                        if downstream && src == i {
+                            stack.push(dest);
+                        } else if !downstream && dest == i {
+                            stack.push(src);
+                        }
+                    }
+                }
+            }
+        }
+
+        for (i, node) in self.nodes.iter_mut().enumerate() {
+            if let Some(ref mut node) = *node {
+                let on = audible[i] && !node.muted;
+                let target = if on { 1.0 } else { 0.0 };
+                if node.gain.get_target() != target {
+                    node.gain.set_target(target);
+                }
+            }
+        }
+    }
+
+    /// Returns true if the node in slot `i` is an input or output port.
+    fn is_port(&self, i: usize) -> bool {
+        let idx = Some(self.idx(i));
+        idx == self.input_port || idx == self.output_port
+    }
+
+    /// Returns a label for the node in slot `i`, from its name and device
+    /// type, or its slot if it has neither.
+    fn label(&self, i: usize) -> String {
+        let node = self.nodes[i].as_ref().unwrap();
+        let port = if Some(self.idx(i)) == self.input_port {
+            Some("inputs")
+        } else if Some(self.idx(i)) == self.output_port {
+            Some("outputs")
+        } else {
+            None
+        };
+        let kind = node.device_type.as_ref().map(|t| &t.name[..]).or(port);
+        match (node.name.as_ref(), kind) {
+            (Some(name), Some(kind)) => format!("{} ({})", name, kind),
+            (Some(name), None) => name.clone(),
+            (None, Some(kind)) => String::from(kind),
+            (None, None) => format!("node {}", i)
+        }
+    }
+
+    /// Returns the number of external inputs to the graph.
+    fn num_port_inputs(&self) -> usize {
+        self.input_port.and_then(|node| self.node(node))
+            .map_or(0, |node| node.device.num_outputs())
+    }
+
+    /// Returns the number of external outputs from the graph.
+    fn num_port_outputs(&self) -> usize {
+        self.output_port.and_then(|node| self.node(node))
+            .map_or(0, |node| node.device.num_inputs())
+    }
+
+    /// Processes the first `frames` frames of a block, starting at time `t`.
+    fn process(&mut self, t: Time, frames: usize) {
+        // Process in pieces no longer than the shortest feedback delay, so
+        // every delayed sample has been computed before it is read
+        let block_size = self.block_size;
+        let chunk = self.nodes.iter().filter_map(|node| node.as_ref())
+            .flat_map(|node| node.feedback.iter())
+            .map(|fb| fb.history.capacity()).fold(frames, min);
+        let input_port = self.input_port.and_then(|node| self.index(node));
+        let output_port = self.output_port.and_then(|node| self.index(node));
+        let num_in = self.num_port_inputs();
+        let num_out = self.num_port_outputs();
+
+        let mut offset = 0;
+        while offset < frames {
+            let n = min(chunk, frames - offset);
+            // The ports pass their buffers straight through
+            if let Some(i) = input_port {
+                self.nodes[i].as_mut().unwrap().output_buf[..n*num_in]
+                    .copy_from_slice(
+                        &self.input[offset*num_in..(offset+n)*num_in]);
+            }
+            if self.num_threads > 1 && n >= MIN_PARALLEL_FRAMES {
+                self.process_stages(t + offset as Time, offset, n);
+            } else {
+                for &i in self.topology.iter() {
+                    self.nodes[i].as_mut().unwrap().tick(
+                        t + offset as Time, offset, n, block_size,
+                        &mut self.bus);
+                }
+            }
+            if let Some(i) = output_port {
+                self.output[offset*num_out..(offset+n)*num_out]
+                    .copy_from_slice(&self.nodes[i].as_ref().unwrap()
+                                     .input_buf[..n*num_out]);
+            }
+            for node in self.nodes.iter_mut().filter_map(|node| node.as_mut()) {
+                node.record_feedback(offset, n, block_size, &self.bus);
+            }
+            offset += n;
+        }
+    }
+
+    /// Processes `frames` frames starting `offset` frames into the block, one
+    /// stage at a time, spreading the nodes of each stage across threads.
+    ///
+    /// Every node in a stage reads its inputs and processes independently,
+    /// then the outputs of the whole stage are written to the bus in order.
+    fn process_stages(&mut self, t: Time, offset: usize, frames: usize) {
+        let block_size = self.block_size;
+        let num_threads = self.num_threads;
+        for stage in self.stages.iter() {
+            // Borrow every node in the stage; stages are sorted by slot
+            let mut parallel = Vec::new();
+            let mut serial = Vec::new();
+            let mut members = stage.iter().peekable();
+            for (i, node) in self.nodes.iter_mut().enumerate() {
+                if members.peek() != Some(&&i) {
+                    continue;
+                }
+                members.next();
+                let node = node.as_mut().unwrap();
+                if node.parallel {
+                    parallel.push(ParallelNode(node));
+                } else {
+                    serial.push(node);
+                }
+            }
+
+            // Spread all but the first group across threads, and process the
+            // rest here while they run
+            let bus = &self.bus[..];
+            let per_thread = parallel.len().div_ceil(num_threads);
+            thread::scope(|scope| {
+                let mut groups = parallel.chunks_mut(max(per_thread, 1));
+                let local = groups.next();
+                for group in groups {
+                    scope.spawn(move || {
+                        for node in group.iter_mut() {
+                            node.0.read_inputs(offset, frames, block_size, bus);
+                            node.0.process(t, frames);
+                        }
+                    });
+                }
+                for node in local.into_iter().flat_map(|g| g.iter_mut()) {
+                    node.0.read_inputs(offset, frames, block_size, bus);
+                    node.0.process(t, frames);
+                }
+                for node in serial.iter_mut() {
+                    node.read_inputs(offset, frames, block_size, bus);
+                    node.process(t, frames);
+                }
+            });
+
+            for node in parallel.iter().map(|node| &*node.0)
+                    .chain(serial.iter().map(|node| &**node)) {
+                node.write_outputs(offset, frames, block_size, &mut self.bus);
+            }
+        }
+    }
+
+    /// Groups the nodes into stages, so that every node only depends on nodes
+    /// in earlier stages. Feedback edges are ignored, since they only read
+    /// samples from earlier pieces of the block.
+    fn compute_stages(&mut self) {
+        let mut bus_stage = vec![0; self.bus_used.len()];
+        let mut stages: Vec<Vec<usize>> = Vec::new();
+        for &i in self.topology.iter() {
+            let node = self.nodes[i].as_ref().unwrap();
+            let stage = node.inputs.iter()
+                .flat_map(|input| input.iter().map(|conn| bus_stage[conn.ch]+1))
+                .max().unwrap_or(0);
+            let (start, end) = node.outputs;
+            for s in bus_stage[start..end].iter_mut() {
+                *s = stage;
+            }
+            if stage == stages.len() {
+                stages.push(Vec::new());
+            }
+            stages[stage].push(i);
+        }
+        for stage in stages.iter_mut() {
+            stage.sort();
+        }
+        self.stages = stages;
+    }
+
+    /// Returns the node and output channel that writes to a bus channel.
+    fn source(&self, ch: usize) -> Option<(AudioNodeIdx, usize)> {
+        self.nodes.iter().position(|node| node.as_ref().is_some_and(|node| {
+            let (start, end) = node.outputs;
+            start <= ch && ch < end
+        })).map(|i| {
+            let (start, _) = self.nodes[i].as_ref().unwrap().outputs;
+            (self.idx(i), ch - start)
+        })
+    }
+
+    /// Returns the slot of a node, or None if the identifier is stale or from
+    /// another graph.
+    fn index(&self, node: AudioNodeIdx) -> Option<usize> {
+        if node.graph != self.id {
+            return None;
+        }
+        match self.nodes.get(node.index) {
+            Some(&Some(_)) if self.generations[node.index] == node.generation =>
+                Some(node.index),
+            _ => None
+        }
+    }
+
+    /// Returns the identifier of the node in slot `i`.
+    fn idx(&self, i: usize) -> AudioNodeIdx {
+        AudioNodeIdx {
+            graph: self.id,
+            index: i,
+            generation: self.generations[i]
+        }
+    }
+
+    /// Returns a node, or None if the identifier is stale.
+    fn node(&self, node: AudioNodeIdx) -> Option<&AudioNode<S>> {
+        self.index(node).and_then(|i| self.nodes[i].as_ref())
+    }
+
+    /// Returns a node mutably, or None if the identifier is stale.
+    fn node_mut(&mut self, node: AudioNodeIdx) -> Option<&mut AudioNode<S>> {
+        match self.index(node) {
+            Some(i) => self.nodes[i].as_mut(),
+            None => None
+        }
+    }
+}
+
+impl<S: SampleFloat> Tick for DeviceGraph<S> {
+    fn tick(&mut self) {
+        self.apply_edits();
+        let (time, block_size) = (self.time, self.block_size);
+        self.process(time, block_size);
+        self.transport.borrow_mut().advance(self.block_size);
+        self.time += self.block_size as Time;
+    }
+}
+
+
+impl<S: SampleFloat> AudioDevice<S> for DeviceGraph<S> {
+    fn num_inputs(&self) -> usize {
+        self.num_port_inputs()
+    }
+
+    fn num_outputs(&self) -> usize {
+        self.num_port_outputs()
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        DeviceGraph::set_sample_rate(self, sample_rate);
+    }
+
+    fn prepare(&mut self, sample_rate: u32, block_size: usize) {
+        DeviceGraph::prepare(self, sample_rate, block_size);
+    }
+
+    fn reset(&mut self) {
+        DeviceGraph::reset(self);
+    }
+
+    fn latency(&self) -> usize {
+        self.latency
+    }
+
+    fn set_transport(&mut self, transport: TransportHandle) {
+        for node in self.nodes.iter_mut().filter_map(|node| node.as_mut()) {
+            node.device.set_transport(transport.clone());
+        }
+        self.transport = transport;
+    }
+
+    fn tick(&mut self, t: Time, inputs: &[S], outputs: &mut[S]) {
+        self.tick_block(t, 1, inputs, outputs);
+    }
+
+    fn tick_block(&mut self, t: Time, frames: usize, inputs: &[S],
+                  outputs: &mut[S]) {
+        self.apply_edits();
+        let num_in = self.num_port_inputs();
+        let num_out = self.num_port_outputs();
+        self.input[..frames*num_in].copy_from_slice(&inputs[..frames*num_in]);
+        self.process(t, frames);
+        outputs[..frames*num_out]
+            .copy_from_slice(&self.output[..frames*num_out]);
+    }
+}
+
+
+/// Defines the messages that a DeviceGraph supports.
+#[derive(Clone, Copy, Debug)]
+pub enum Message {
+    /// Sets a parameter of a node's device, by node, parameter index and
+    /// value. Messages for missing nodes or parameters are ignored.
+    SetParameter(AudioNodeIdx, usize, f32),
+    /// Bypasses a node, or stops bypassing it.
+    SetBypass(AudioNodeIdx, bool),
+    /// Mutes a node, or unmutes it.
+    SetMute(AudioNodeIdx, bool),
+    /// Solos a node, or stops soloing it.
+    SetSolo(AudioNodeIdx, bool),
+}
+pub use self::Message::*;
+
+impl<S: SampleFloat> MessageReceiver for DeviceGraph<S> {
+    type Msg = Message;
+    fn handle_message(&mut self, msg: Message) {
+        match msg {
+            SetParameter(node, i, value) => {
+                if let Some(params) = self.get_parameters_mut(node) {
+                    if i < params.num_parameters() {
+                        params.set_parameter(i, value);
+                    }
+                }
+            },
+            SetBypass(node, bypass) => {
+                let _ = self.set_bypass(node, bypass);
+            },
+            SetMute(node, mute) => {
+                let _ = self.set_mute(node, mute);
+            },
+            SetSolo(node, solo) => {
+                let _ = self.set_solo(node, solo);
+            }
+        }
+    }
+}
+
+
+/// Refers to a node in an `Edit`, either by its identifier, or by its name.
+///
+/// Nodes added by an editor can only be referred to by name, since their
+/// identifiers aren't known until the edit is applied.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeRef {
+    /// A node identifier.
+    Idx(AudioNodeIdx),
+    /// The name of a node; see `DeviceGraph::set_name`. If several nodes share
+    /// the name, the first is used.
+    Name(String),
+}
+
+impl From<AudioNodeIdx> for NodeRef {
+    fn from(node: AudioNodeIdx) -> Self {
+        NodeRef::Idx(node)
+    }
+}
+
+impl<'a> From<&'a str> for NodeRef {
+    fn from(name: &'a str) -> Self {
+        NodeRef::Name(String::from(name))
+    }
+}
+
+
+/// Defines the edits that a `GraphEditor` can make to a running graph.
+///
+/// Devices must be `Send`, so that they can be created on the editing thread.
+pub enum Edit<S: SampleFloat = Sample> {
+    /// Adds a device as a new node with the given name, like
+    /// `DeviceGraph::add_parallel_node`.
+    AddNode(String, Box<AnyDevice<S>+Send>),
+    /// Removes a node, like `DeviceGraph::remove_node`.
+    RemoveNode(NodeRef),
+    /// Replaces the device of a node, like `DeviceGraph::replace_node`.
+    ReplaceNode(NodeRef, Box<AnyDevice<S>+Send>),
+    /// Connects two nodes with the given gain, like
+    /// `DeviceGraph::add_edge_with_gain`.
+    AddEdge(NodeRef, usize, NodeRef, usize, f32),
+    /// Disconnects two nodes, like `DeviceGraph::remove_edge`.
+    RemoveEdge(NodeRef, usize, NodeRef, usize),
+}
+pub use self::Edit::*;
+
+
+/// Sends edits to a graph from another thread.
+///
+/// Edits are applied at the start of the graph's next tick, in the order they
+/// were sent. An edit that fails, such as one naming a missing node, is
+/// skipped, and its error is returned by `get_errors`.
+///
+/// Each clone of an editor receives only the errors of its own edits.
+pub struct GraphEditor<S: SampleFloat = Sample> {
+    tx: Sender<(Edit<S>, Sender<Error>)>,
+    error_tx: Sender<Error>,
+    error_rx: Receiver<Error>
+}
+
+impl<S: SampleFloat> GraphEditor<S> {
+    /// Returns an editor that sends edits over `tx`.
+    fn new(tx: Sender<(Edit<S>, Sender<Error>)>) -> Self {
+        let (error_tx, error_rx) = channel();
+        GraphEditor { tx: tx, error_tx: error_tx, error_rx: error_rx }
+    }
+
+    /// Sends an edit to the graph. Returns an Err if the graph no longer
+    /// exists.
+    pub fn send(&self, edit: Edit<S>) -> Result<()> {
+        self.tx.send((edit, self.error_tx.clone()))
+            .map_err(|_| Error::Cancelled)
+    }
+
+    /// Adds a device as a new node named `name`.
+    pub fn add_node<D>(&self, name: &str, device: D) -> Result<()>
+            where D: 'static+AudioDevice<S>+Send {
+        self.send(AddNode(String::from(name), Box::new(device)))
+    }
+
+    /// Returns the errors of every failed edit since the last call.
+    pub fn get_errors(&self) -> Vec<Error> {
+        self.error_rx.try_iter().collect()
+    }
+}
+
+impl<S: SampleFloat> Clone for GraphEditor<S> {
+    fn clone(&self) -> Self {
+        GraphEditor::new(self.tx.clone())
+    }
+}
+
+
+/// An identifier used to refer back to a node in the graph.
+///
+/// Identifiers are generational: once a node is removed, its identifier is
+/// never valid again, even if a new node takes its place. An identifier is
+/// never valid in any graph other than the one that returned it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AudioNodeIdx {
+    graph: usize, // the id of the graph the node belongs to
+    index: usize, // the slot of the node
+    generation: usize // the generation of the slot when the node was added
+}
+
+
+/// A handle to a node in the graph, that remembers the type of its device.
+///
+/// Handles are returned by `DeviceGraph::get_handle`, and give typed access
+/// to a node's device through `DeviceGraph::get_device_mut`.
+pub struct NodeHandle<D> {
+    node: AudioNodeIdx,
+    device: PhantomData<fn() -> D>
+}
+
+impl<D> NodeHandle<D> {
+    /// Returns the identifier of the node.
+    pub fn get_idx(&self) -> AudioNodeIdx {
+        self.node
+    }
+}
+
+impl<D> From<NodeHandle<D>> for AudioNodeIdx {
+    fn from(handle: NodeHandle<D>) -> Self {
+        handle.node
+    }
+}
+
+impl<D> Clone for NodeHandle<D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D> Copy for NodeHandle<D> {}
+
+impl<D> fmt::Debug for NodeHandle<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NodeHandle({:?})", self.node)
+    }
+}
+
+impl<D> PartialEq for NodeHandle<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+
+/// An edge between two nodes in the graph.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Edge {
+    /// The node the edge starts from.
+    pub src: AudioNodeIdx,
+    /// The output channel of the source node.
+    pub src_ch: usize,
+    /// The node the edge ends at.
+    pub dest: AudioNodeIdx,
+    /// The input channel of the destination node.
+    pub dest_ch: usize,
+    /// The gain applied to the signal along the edge.
+    pub gain: f32,
+    /// The delay of a feedback edge, in samples, or 0 for a normal edge.
+    pub delay: usize,
+}
+
+
+/// A wrapper for a node in the graph.
+///
+/// Management of indices in the bus is handled in the graph itself. Each bus
+/// channel holds one block of samples, so channel `j` occupies the range
+/// `j*block_size..(j+1)*block_size` of the bus.
+///
+/// Each input sums any number of connections from the bus.
+struct AudioNode<S: SampleFloat> {
+    device: Box<AnyDevice<S>>, // wraps the device
+    device_type: Option<DeviceType>, // the patch type of the device
+    name: Option<String>, // the name given to the node
+    inputs: Vec<Vec<Connection<S>>>, // the connections into each input
+    parallel: bool, // whether the device may be ticked on another thread
+    fade: Option<Fade<S>>, // a replaced device that is fading out
+    bypassed: bool, // whether the node passes its inputs through
+    muted: bool, // whether the node is muted
+    soloed: bool, // whether the node is soloed
+    skip_muted: bool, // whether to skip processing while silent
+    bypass: Smoothed<f32>, // the mix of the inputs into the outputs
+    gain: Smoothed<f32>, // the gain of the outputs, zero while silent
+    feedback: Vec<Feedback<S>>, // the feedback connections into any input
+    input_buf: Vec<S>, // an allocated buffer for containing inputs
+    output_buf: Vec<S>, // an allocated buffer for containing outputs
+    outputs: (usize, usize) // the range of outputs in the bus
+}
+
+impl<S: SampleFloat> AudioNode<S> {
+    /// Wraps the device in a new node, that outputs to the bus channels
+    /// starting at `start`.
+    fn new(device: Box<AnyDevice<S>>, start: usize, sample_rate: u32,
+           frames: usize) -> AudioNode<S> {
+        let num_in = device.num_inputs();
+        let num_out = device.num_outputs();
+        let end = start + num_out;
+
+        AudioNode {
+            device: device,
+            device_type: None,
+            name: None,
+            inputs: (0..num_in).map(|_| Vec::new()).collect(),
+            parallel: false,
+            fade: None,
+            bypassed: false,
+            muted: false,
+            soloed: false,
+            skip_muted: false,
+            bypass: Smoothed::new(0.0, Linear)
+                .ramp_time(DEFAULT_RAMP_TIME, sample_rate),
+            gain: Smoothed::new(1.0, Linear)
+                .ramp_time(DEFAULT_RAMP_TIME, sample_rate),
+            feedback: Vec::new(),
+            input_buf: vec![S::zero(); num_in*frames],
+            output_buf: vec![S::zero(); num_out*frames],
+            outputs: (start, end)
+        }
+    }
+
+    /// Reallocates the input and output buffers to hold `frames` frames.
+    fn resize(&mut self, frames: usize) {
+        let (start, end) = self.outputs;
+        self.input_buf = vec![S::zero(); self.inputs.len()*frames];
+        self.output_buf = vec![S::zero(); (end-start)*frames];
+    }
+
+    /// Sets the input delays so that every input arrives with `arrival`
+    /// samples of latency, given the latency of each bus channel.
+    ///
+    /// Delay lines whose length is unchanged keep their contents.
+    fn set_delays(&mut self, arrival: usize, bus_latency: &[usize]) {
+        for conn in self.inputs.iter_mut().flat_map(|input| input.iter_mut()) {
+            let delay = arrival - bus_latency[conn.ch];
+            let current = conn.delay.as_ref().map_or(0, |d| d.capacity());
+            if delay != current {
+                conn.delay = if delay == 0 {
+                    None
+                } else {
+                    Some(RingBuffer::from(&vec![S::zero(); delay][..]))
+                };
+            }
+        }
+    }
+
+    /// Zeroes the contents of every input delay line, and the history of
+    /// every feedback connection.
+    fn clear_delays(&mut self) {
+        for conn in self.inputs.iter_mut().flat_map(|input| input.iter_mut()) {
+            if let Some(ref mut rb) = conn.delay {
+                *rb = RingBuffer::from(&vec![S::zero(); rb.capacity()][..]);
+            }
+        }
+        for fb in self.feedback.iter_mut() {
+            let delay = fb.history.capacity();
+            fb.history = RingBuffer::from(&vec![S::zero(); delay][..]);
+        }
+    }
+
+    /// Extracts the inputs out of the bus, tick the device and place the outputs
+    /// back into the bus.
+    ///
+    /// Processes `frames` frames starting `offset` frames into the block. Each
+    /// bus channel holds `block_size` frames.
+    fn tick(&mut self, t: Time, offset: usize, frames: usize,
+            block_size: usize, bus: &mut[S]) {
+        self.read_inputs(offset, frames, block_size, bus);
+        self.process(t, frames);
+        self.write_outputs(offset, frames, block_size, bus);
+    }
+
+    /// Sums the connections into each input out of the bus, and into the
+    /// input buffer.
+    fn read_inputs(&mut self, offset: usize, frames: usize, block_size: usize,
+                   bus: &[S]) {
+        let num_in = self.inputs.len();
+        for (i, input) in self.inputs.iter_mut().enumerate() {
+            for f in 0..frames {
+                let mut x = S::zero();
+                for conn in input.iter_mut() {
+                    let j = conn.ch*block_size + offset + f;
+                    x = x + conn.gain*conn.read(bus[j]);
+                }
+                self.input_buf[f*num_in + i] = x;
+            }
+        }
+        for fb in self.feedback.iter() {
+            let (oldest, _) = fb.history.times();
+            for f in 0..frames {
+                let y = fb.history[oldest + f as Time];
+                let x = &mut self.input_buf[f*num_in + fb.dest_ch];
+                *x = *x + fb.gain*y;
+            }
+        }
+    }
+
+    /// Ticks the device from the input buffer into the output buffer.
+    ///
+    /// While a replaced device is fading out, both devices are ticked, and
+    /// their outputs are mixed.
+    ///
+    /// The outputs are then mixed with the inputs while bypassed, and scaled
+    /// by the node's gain. A silent node skips processing if it is set to.
+    fn process(&mut self, t: Time, frames: usize) {
+        let num_in = self.inputs.len();
+        let (start, end) = self.outputs;
+        let num_out = end - start;
+        if self.skip_muted && !self.gain.is_ramping() &&
+                self.gain.get_value() == 0.0 {
+            for s in self.output_buf[..frames*num_out].iter_mut() {
+                *s = S::zero();
+            }
+            return;
+        }
+        self.device.tick_block(t, frames, &self.input_buf[..frames*num_in],
+                               &mut self.output_buf[..frames*num_out]);
+
+        let finished = match self.fade {
+            Some(ref mut fade) => {
+                fade.device.tick_block(t, frames,
+                                       &self.input_buf[..frames*num_in],
+                                       &mut fade.output_buf[..frames*num_out]);
+                for f in 0..frames {
+                    fade.elapsed = min(fade.elapsed + 1, fade.length);
+                    let g = S::from_f32(
+                        fade.elapsed as f32 / fade.length as f32);
+                    for i in f*num_out..(f+1)*num_out {
+                        let old = fade.output_buf[i];
+                        self.output_buf[i] = old + g*(self.output_buf[i]-old);
+                    }
+                }
+                fade.elapsed == fade.length
+            },
+            None => false
+        };
+        if finished {
+            self.fade = None;
+        }
+
+        // Skip the mix while processing normally
+        if !self.bypass.is_ramping() && self.bypass.get_value() == 0.0 &&
+                !self.gain.is_ramping() && self.gain.get_value() == 1.0 {
+            return;
+        }
+        for f in 0..frames {
+            let bypass = S::from_f32(self.bypass.tick());
+            let gain = S::from_f32(self.gain.tick());
+            for i in 0..num_out {
+                let dry = if i < num_in {
+                    self.input_buf[f*num_in + i]
+                } else {
+                    S::zero()
+                };
+                let wet = &mut self.output_buf[f*num_out + i];
+                *wet = gain * (*wet + bypass*(dry - *wet));
+            }
+        }
+    }
+
+    /// Copies the output buffer into the node's channels of the bus.
+    fn write_outputs(&self, offset: usize, frames: usize, block_size: usize,
+                     bus: &mut[S]) {
+        let (start, end) = self.outputs;
+        let num_out = end - start;
+        for i in 0..num_out {
+            for f in 0..frames {
+                bus[(start+i)*block_size + offset + f] =
+                    self.output_buf[f*num_out + i];
+            }
+        }
+    }
+
+    /// Records the samples just written to the bus into the history of each
+    /// feedback connection.
+    fn record_feedback(&mut self, offset: usize, frames: usize,
+                       block_size: usize, bus: &[S]) {
+        for fb in self.feedback.iter_mut() {
+            for f in 0..frames {
+                fb.history.push(bus[fb.ch*block_size + offset + f]);
+            }
+        }
+    }
+}
+
+
+/// A connection from a bus channel into an input of a node.
+///
+/// A connection may be delayed to compensate for latency, in which case the
+/// delay line holds exactly as many samples as the delay.
+struct Connection<S: SampleFloat> {
+    ch: usize, // the bus channel to read from
+    gain: S, // the gain applied to the channel
+    delay: Option<RingBuffer<S>> // latency compensation for the channel
+}
+
+impl<S: SampleFloat> Connection<S> {
+    /// Returns an undelayed connection from bus channel `ch`.
+    fn new(ch: usize, gain: S) -> Connection<S> {
+        Connection { ch: ch, gain: gain, delay: None }
+    }
+
+    /// Passes the next sample from the bus through the delay line.
+    fn read(&mut self, x: S) -> S {
+        match self.delay {
+            Some(ref mut rb) => {
+                // The oldest sample is exactly `delay` samples old
+                let (oldest, _) = rb.times();
+                let y = rb[oldest];
+                rb.push(x);
+                y
+            },
+            None => x
+        }
+    }
+}
+
+
+/// A feedback connection from a bus channel into an input of a node.
+///
+/// The history holds the last `delay` samples of the channel, oldest first,
+/// so the input reads the channel exactly `delay` samples late.
+struct Feedback<S: SampleFloat> {
+    dest_ch: usize, // the input to add into
+    ch: usize, // the bus channel to read from
+    gain: S, // the gain applied to the channel
+    history: RingBuffer<S> // the last `delay` samples of the channel
+}
+
+impl<S: SampleFloat> Feedback<S> {
+    /// Returns a feedback connection from bus channel `ch`, with a silent
+    /// history.
+    fn new(dest_ch: usize, ch: usize, gain: S, delay: usize) -> Feedback<S> {
+        Feedback {
+            dest_ch: dest_ch,
+            ch: ch,
+            gain: gain,
+            history: RingBuffer::from(&vec![S::zero(); delay][..])
+        }
+    }
+}
+
+
+/// A replaced device, that is fading out of a node.
+struct Fade<S: SampleFloat> {
+    device: Box<AnyDevice<S>>, // the old device
+    output_buf: Vec<S>, // an allocated buffer for the old device's outputs
+    elapsed: usize, // the samples since the fade started
+    length: usize // the length of the fade in samples
+}
+
+
+/// A node that may be ticked on another thread.
+///
+/// Only nodes added with `add_parallel_node` are wrapped, and their devices
+/// were `Send` when added, as was any device they are fading out. The rest of
+/// the node holds only samples and descriptions.
+struct ParallelNode<'a, S: 'a+SampleFloat>(&'a mut AudioNode<S>);
+
+unsafe impl<'a, S: SampleFloat> Send for ParallelNode<'a, S> {}
+
+
+/// A device standing in for the external inputs or outputs of the graph.
+///
+/// Ports do no processing; the graph fills the output buffer of the input
+/// port, and reads the input buffer of the output port.
+struct Port {
+    num_inputs: usize,
+    num_outputs: usize
+}
+
+impl<S: SampleFloat> AudioDevice<S> for Port {
+    fn num_inputs(&self) -> usize { self.num_inputs }
+    fn num_outputs(&self) -> usize { self.num_outputs }
+    fn tick(&mut self, _: Time, _: &[S], _: &mut[S]) {}
+}
+
+
+#[cfg(test)]
+mod test {
+    use chain::DeviceChain;
+    use filters::second_order::{Filter, LowPass};
+    use mixers::{gain, Adder, Gain};
+    use oscillator::{Oscillator, PolyBlep, Waveform};
+    use testing::MockAudioDevice;
+    use parameters::Parameters;
+    use testing::flt_eq_eps;
+    use types::{MessageReceiver, SampleFloat};
+    use wrappers::Messaged;
+    use std::thread;
+
+    use error::Error;
+    use super::{AddEdge, DeviceGraph, RemoveNode, SetParameter, SetSolo, Tick};
+
+    #[test]
+    fn test_empty_graph() {
+        DeviceGraph::new().tick();
+    }
+
+    #[test]
+    fn test_one_node() {
+        let mut mock = MockAudioDevice::new("mock", 1, 1);
+        mock.will_tick(&[0.0], &[1.0]);
+
+        let mut graph = DeviceGraph::new();
+        graph.add_node(mock);
+        graph.tick();
+    }
+
+    #[test]
+    fn test_disconnected() {
+        let mut mock1 = MockAudioDevice::new("mock1", 1, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 1);
+        mock1.will_tick(&[0.0], &[1.0]);
+        mock2.will_tick(&[0.0], &[2.0]);
+
+        let mut graph = DeviceGraph::new();
+        graph.add_node(mock1);
+        graph.add_node(mock2);
+        graph.tick();
+    }
+
+    #[test]
+    fn test_linear() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 0);
+        mock1.will_tick(&[], &[1.0]);
+        mock2.will_tick(&[1.0], &[]);
+
+        let mut graph = DeviceGraph::new();
+        let mock1 = graph.add_node(mock1);
+        let mock2 = graph.add_node(mock2);
+        graph.add_edge(mock1, 0, mock2, 0).unwrap();
+        graph.tick();
+    }
+
+    #[test]
+    fn test_complex() {
+        let mut mock1 = MockAudioDevice::new("mock1", 1, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 1);
+        let mut mock3 = MockAudioDevice::new("mock3", 2, 1);
+        let mut mock4 = MockAudioDevice::new("mock4", 1, 1);
+        let mut mock5 = MockAudioDevice::new("mock5", 1, 1);
+
+        mock1.will_tick(&[0.0], &[1.0]);
+        mock2.will_tick(&[4.0], &[2.0]);
+        mock3.will_tick(&[2.0, 4.0], &[3.0]);
+        mock4.will_tick(&[1.0], &[4.0]);
+        mock5.will_tick(&[0.0], &[5.0]);
+
+        let mut graph = DeviceGraph::new();
+        let mock1 = graph.add_node(mock1);
+        let mock2 = graph.add_node(mock2);
+        let mock3 = graph.add_node(mock3);
+        let mock4 = graph.add_node(mock4);
+        let _mock5 = graph.add_node(mock5);
+        graph.add_edge(mock1, 0, mock4, 0).unwrap();
+        graph.add_edge(mock4, 0, mock2, 0).unwrap();
+        graph.add_edge(mock2, 0, mock3, 0).unwrap();
+        graph.add_edge(mock4, 0, mock3, 1).unwrap();
+        graph.tick();
+    }
+
+    #[test]
+    fn test_block() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 2);
+        let mut mock2 = MockAudioDevice::new("mock2", 2, 1);
+        mock1.will_tick(&[], &[1.0, 2.0]).will_tick(&[], &[3.0, 4.0]);
+        mock2.will_tick(&[2.0, 1.0], &[5.0]).will_tick(&[4.0, 3.0], &[6.0]);
+
+        let mut graph = DeviceGraph::new();
+        graph.set_block_size(2);
+        let mock1 = graph.add_node(mock1);
+        let mock2 = graph.add_node(mock2);
+        graph.add_edge(mock1, 0, mock2, 1).unwrap();
+        graph.add_edge(mock1, 1, mock2, 0).unwrap();
+        graph.tick();
+    }
+
+    #[test]
+    fn test_latency_compensation() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 0, 1);
+        let mut mock3 = MockAudioDevice::new("mock3", 1, 1);
+        let mut mock4 = MockAudioDevice::new("mock4", 2, 0);
+        mock1.will_report_latency(2);
+        mock1.will_tick(&[], &[4.0]).will_tick(&[], &[5.0])
+            .will_tick(&[], &[6.0]);
+        mock2.will_tick(&[], &[1.0]).will_tick(&[], &[2.0])
+            .will_tick(&[], &[3.0]);
+        mock3.will_tick(&[1.0], &[1.0]).will_tick(&[2.0], &[2.0])
+            .will_tick(&[3.0], &[3.0]);
+        mock4.will_tick(&[4.0, 0.0], &[]).will_tick(&[5.0, 0.0], &[])
+            .will_tick(&[6.0, 1.0], &[]);
+
+        let mut graph = DeviceGraph::new();
+        let mock1 = graph.add_node(mock1);
+        let mock2 = graph.add_node(mock2);
+        let mock3 = graph.add_node(mock3);
+        let mock4 = graph.add_node(mock4);
+        graph.add_edge(mock1, 0, mock4, 0).unwrap();
+        graph.add_edge(mock2, 0, mock3, 0).unwrap();
+        graph.add_edge(mock3, 0, mock4, 1).unwrap();
+        assert_eq!(graph.latency(), 2);
+
+        graph.set_block_size(3);
+        graph.tick();
+    }
+
+    #[test]
+    fn test_parameters() {
+        let mut graph = DeviceGraph::new();
+        let adder = graph.add_node(Adder::new(2));
+        let gain = graph.add_node(Gain::new(-3.0, 1));
+        assert!(graph.get_parameters(adder).is_none());
+
+        let params = graph.get_parameters_mut(gain).unwrap();
+        assert_eq!(params.get_parameter(0), -3.0);
+        params.set_parameter(0, 6.0);
+        assert_eq!(params.get_parameter(0), 6.0);
+    }
+
+    #[test]
+    fn test_f64() {
+        fn run<S: SampleFloat>() -> Vec<f32> {
+            let mut graph: DeviceGraph<S> = DeviceGraph::default();
+            let osc1 = graph.add_node(
+                Oscillator::new_generic(Waveform::Saw(PolyBlep)).freq(220.0));
+            let osc2 = graph.add_node(
+                Oscillator::new_generic(Waveform::Sine).freq(440.0));
+            let adder = graph.add_node(Adder::new_generic(2));
+            graph.add_edge(osc1, 0, adder, 0).unwrap();
+            graph.add_edge(osc2, 0, adder, 1).unwrap();
+            (0..100).map(|_| {
+                graph.tick();
+                graph.get_output(adder).unwrap()[0].into_f32()
+            }).collect()
+        }
+
+        let single = run::<f32>();
+        let double = run::<f64>();
+        assert!(double.iter().any(|&s| s != 0.0));
+        for (&a, &b) in single.iter().zip(&double) {
+            assert!(flt_eq_eps(a, b, 1e-4));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_block_size() {
+        let mut graph = DeviceGraph::new();
+        graph.set_block_size(0);
+    }
+
+    #[test]
+    fn test_sum() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 0, 1);
+        let mut mock3 = MockAudioDevice::new("mock3", 1, 0);
+        mock1.will_tick(&[], &[1.0]).will_tick(&[], &[1.0]);
+        mock2.will_tick(&[], &[2.0]).will_tick(&[], &[2.0]);
+        mock3.will_tick(&[2.0], &[]).will_tick(&[3.0], &[]);
+
+        let mut graph = DeviceGraph::new();
+        let mock1 = graph.add_node(mock1);
+        let mock2 = graph.add_node(mock2);
+        let mock3 = graph.add_node(mock3);
+        graph.add_edge(mock1, 0, mock3, 0).unwrap();
+        graph.add_edge_with_gain(mock2, 0, mock3, 0, 0.5).unwrap();
+        graph.tick();
+
+        // Adding an existing edge again changes its gain
+        graph.add_edge(mock2, 0, mock3, 0).unwrap();
+        assert_eq!(graph.get_inputs(mock3).unwrap().len(), 2);
+        graph.tick();
+    }
+
+    #[test]
+    fn test_fan_out() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 2, 0);
+        mock1.will_tick(&[], &[1.0]);
+        mock2.will_tick(&[1.0, 1.0], &[]);
+
+        let mut graph = DeviceGraph::new();
+        let mock1 = graph.add_node(mock1);
+        let mock2 = graph.add_node(mock2);
+        graph.add_edge(mock1, 0, mock2, 0).unwrap();
+        graph.add_edge(mock1, 0, mock2, 1).unwrap();
+        graph.tick();
+    }
+
+    #[test]
+    fn test_remove_edge() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 0);
+        mock1.will_tick(&[], &[1.0]).will_tick(&[], &[2.0]);
+        mock2.will_tick(&[1.0], &[]).will_tick(&[0.0], &[]);
+
+        let mut graph = DeviceGraph::new();
+        let mock1 = graph.add_node(mock1);
+        let mock2 = graph.add_node(mock2);
+        graph.add_edge(mock1, 0, mock2, 0).unwrap();
+        graph.tick();
+
+        graph.remove_edge(mock1, 0, mock2, 0).unwrap();
+        assert!(graph.remove_edge(mock1, 0, mock2, 0).is_err());
+        assert_eq!(graph.get_edges().len(), 0);
+        graph.tick();
+    }
+
+    #[test]
+    fn test_remove_node() {
+        let mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 0);
+        let mut mock3 = MockAudioDevice::new("mock3", 0, 1);
+        mock2.will_tick(&[0.0], &[]).will_tick(&[3.0], &[]);
+        mock3.will_tick(&[], &[3.0]).will_tick(&[], &[3.0]);
+
+        let mut graph = DeviceGraph::new();
+        let mock1 = graph.add_node(mock1);
+        let mock2 = graph.add_node(mock2);
+        graph.add_edge(mock1, 0, mock2, 0).unwrap();
+        graph.remove_node(mock1).unwrap();
+        assert!(!graph.contains_node(mock1));
+        assert_eq!(graph.get_inputs(mock2).unwrap(), vec![]);
+
+        // The new node reuses the slot, but the old identifier stays stale
+        let mock3 = graph.add_node(mock3);
+        assert!(graph.get_output(mock1).is_err());
+        assert!(graph.add_edge(mock1, 0, mock2, 0).is_err());
+        assert_eq!(graph.get_nodes(), vec![mock3, mock2]);
+        graph.tick();
+
+        graph.add_edge(mock3, 0, mock2, 0).unwrap();
+        assert_eq!(graph.get_outputs(mock3).unwrap(), graph.get_edges());
+        assert_eq!(graph.get_edges()[0].dest, mock2);
+        graph.tick();
+    }
+
+    #[test]
+    fn test_feedback() {
+        let mut mock = MockAudioDevice::new("mock", 1, 1);
+        mock.will_tick(&[0.0], &[1.0]).will_tick(&[0.5], &[2.0])
+            .will_tick(&[1.0], &[3.0]).will_tick(&[1.5], &[4.0]);
+
+        // A delay shorter than the block still reads each sample in time
+        let mut graph = DeviceGraph::new();
+        graph.set_block_size(2);
+        let mock = graph.add_node(mock);
+        graph.add_feedback_edge(mock, 0, mock, 0, 1, 0.5).unwrap();
+        assert_eq!(graph.get_inputs(mock).unwrap()[0].delay, 1);
+        graph.tick();
+        graph.tick();
+    }
+
+    #[test]
+    fn test_feedback_cycle() {
+        let mut mock1 = MockAudioDevice::new("mock1", 1, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 1);
+        mock1.will_tick(&[0.0], &[1.0]).will_tick(&[0.0], &[2.0])
+            .will_tick(&[10.0], &[3.0]).will_tick(&[20.0], &[4.0]);
+        mock2.will_tick(&[1.0], &[10.0]).will_tick(&[2.0], &[20.0])
+            .will_tick(&[3.0], &[30.0]).will_tick(&[4.0], &[40.0]);
+
+        let mut graph = DeviceGraph::new();
+        graph.set_block_size(4);
+        let mock1 = graph.add_node(mock1);
+        let mock2 = graph.add_node(mock2);
+        graph.add_edge(mock1, 0, mock2, 0).unwrap();
+        assert!(graph.add_edge(mock2, 0, mock1, 0).is_err());
+        assert!(graph.add_feedback_edge(mock2, 0, mock1, 0, 0, 1.0).is_err());
+        graph.add_feedback_edge(mock2, 0, mock1, 0, 2, 1.0).unwrap();
+        graph.tick();
+    }
+
+    #[test]
+    fn test_nested_graph() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 2);
+        let mut mock3 = MockAudioDevice::new("mock3", 2, 0);
+        mock1.will_tick(&[], &[1.0]).will_tick(&[], &[2.0]);
+        mock2.will_tick(&[1.0], &[3.0, 4.0]).will_tick(&[2.0], &[5.0, 6.0]);
+        mock3.will_tick(&[4.0, 3.0], &[]).will_tick(&[6.0, 5.0], &[]);
+
+        let mut inner = DeviceGraph::new();
+        let inputs = inner.set_inputs(1);
+        let outputs = inner.set_outputs(2);
+        let mock2 = inner.add_node(mock2);
+        inner.add_edge(inputs, 0, mock2, 0).unwrap();
+        inner.add_edge(mock2, 0, outputs, 1).unwrap();
+        inner.add_edge(mock2, 1, outputs, 0).unwrap();
+
+        let mut graph = DeviceGraph::new();
+        let mock1 = graph.add_node(mock1);
+        let inner = graph.add_node(inner);
+        let mock3 = graph.add_node(mock3);
+        graph.add_edge(mock1, 0, inner, 0).unwrap();
+        graph.add_edge(inner, 0, mock3, 0).unwrap();
+        graph.add_edge(inner, 1, mock3, 1).unwrap();
+        graph.set_block_size(2);
+        graph.tick();
+    }
+
+    #[test]
+    fn test_ports() {
+        let mut graph = DeviceGraph::new();
+        let inputs = graph.set_inputs(1);
+        let outputs = graph.set_outputs(1);
+        graph.add_edge_with_gain(inputs, 0, outputs, 0, 0.5).unwrap();
+        graph.get_input()[0] = 2.0;
+        graph.tick();
+        assert_eq!(graph.get_graph_output(), [1.0]);
+
+        // Replacing a port removes its edges
+        let inputs = graph.set_inputs(2);
+        assert_eq!(graph.get_input_node(), Some(inputs));
+        assert!(graph.get_inputs(outputs).unwrap().is_empty());
+        assert_eq!(graph.get_input().len(), 2);
+    }
+
+    #[test]
+    fn test_nested_chain() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 1);
+        let mut mock3 = MockAudioDevice::new("mock3", 1, 0);
+        mock1.will_tick(&[], &[1.0]);
+        mock2.will_tick(&[1.0], &[2.0]);
+        mock3.will_tick(&[2.0], &[]);
+
+        let mut graph = DeviceGraph::new();
+        let mock1 = graph.add_node(mock1);
+        let chain = graph.add_node(DeviceChain::from(mock2).into(mock3));
+        graph.add_edge(mock1, 0, chain, 0).unwrap();
+        graph.tick();
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut graph = DeviceGraph::new();
+        let gain = graph.add_node(Gain::new(0.0, 1));
+        graph.handle_message(SetParameter(gain, 0, -6.0));
+        graph.handle_message(SetParameter(gain, 1, -6.0));
+        assert_eq!(graph.get_parameters(gain).unwrap().get_parameter(0), -6.0);
+
+        // Messages can be sent to a graph nested in another graph
+        let mut messaged = Messaged::from(graph);
+        messaged.schedule(0, SetParameter(gain, 0, -12.0));
+        let mut outer = DeviceGraph::new();
+        outer.add_node(messaged);
+        outer.tick();
+    }
+
+    #[test]
+    fn test_handles() {
+        let mut graph = DeviceGraph::new();
+        let gain = graph.add_node(Gain::new(0.0, 1));
+        assert!(graph.get_handle::<Adder>(gain).is_none());
+        let handle = graph.get_handle::<Gain>(gain).unwrap();
+        assert_eq!(handle.get_idx(), gain);
+
+        graph.send_message(handle, gain::SetGain(-6.0)).unwrap();
+        assert_eq!(graph.get_device(handle).unwrap().get_parameter(0), -6.0);
+        graph.get_device_mut(handle).unwrap().set_parameter(0, -12.0);
+        assert_eq!(graph.get_parameters(gain).unwrap().get_parameter(0), -12.0);
+
+        // The removed device can still be downcast
+        let device = graph.remove_node(gain).unwrap();
+        assert!((*device).as_any().is::<Gain>());
+        assert!(graph.get_device(handle).is_none());
+        assert!(graph.send_message(handle, gain::SetGain(0.0)).is_err());
+
+        // Identifiers from another graph are never valid
+        let mut other = DeviceGraph::new();
+        let other_gain = other.add_node(Gain::new(0.0, 1));
+        graph.add_node(Gain::new(0.0, 1));
+        assert!(!graph.contains_node(other_gain));
+        match graph.remove_node(other_gain) {
+            Err(Error::OutOfRange("node")) => (),
+            _ => panic!("node from another graph was accepted")
+        }
+    }
+
+    /// Builds a graph of parallel oscillator and filter branches, mixed with
+    /// feedback of the given delay into a stereo output.
+    fn branches(threads: usize, delay: usize) -> DeviceGraph {
+        let mut graph = DeviceGraph::new();
+        graph.set_block_size(64);
+        graph.set_num_threads(threads);
+        let outputs = graph.set_outputs(2);
+        let mix = graph.add_node(Adder::new(2));
+        let gain = graph.add_node(Gain::new(-6.0, 1));
+        for i in 0..6 {
+            let freq = 110.0 * (i+1) as f32;
+            let osc = graph.add_parallel_node(
+                Oscillator::new(Waveform::Saw(PolyBlep))
+                    .freq(freq));
+            let lpf = graph.add_parallel_node(
+                Filter::new(LowPass(2.0*freq), 1));
+            graph.add_edge(osc, 0, lpf, 0).unwrap();
+            graph.add_edge_with_gain(lpf, 0, mix, i % 2, 0.25).unwrap();
+        }
+        graph.add_edge(mix, 0, gain, 0).unwrap();
+        graph.add_feedback_edge(gain, 0, mix, 1, delay, 0.5).unwrap();
+        graph.add_edge(gain, 0, outputs, 0).unwrap();
+        graph.add_edge(mix, 0, outputs, 1).unwrap();
+        graph
+    }
+
+    #[test]
+    fn test_threads() {
+        // A delay of one splits every block into single frames, which are too
+        // short to spread across threads
+        for &delay in [16, 1].iter() {
+            let mut serial = branches(1, delay);
+            let mut parallel = branches(4, delay);
+            for _ in 0..32 {
+                serial.tick();
+                parallel.tick();
+                assert_eq!(serial.get_graph_output(),
+                           parallel.get_graph_output());
+            }
+            assert!(serial.get_graph_output().iter().any(|&s| s != 0.0));
+        }
+    }
+
+    #[test]
+    fn test_dot() {
+        let mut graph = DeviceGraph::new();
+        let osc = graph.add_node(Oscillator::new(Waveform::Sine));
+        let gain = graph.add_node(Gain::new(0.0, 1));
+        graph.set_name(osc, "lfo \"1\"").unwrap();
+        graph.add_edge_with_gain(osc, 0, gain, 0, 0.5).unwrap();
+        graph.add_feedback_edge(gain, 0, gain, 0, 4, 1.0).unwrap();
+        assert_eq!(graph.get_name(osc), Some("lfo \"1\""));
+        assert_eq!(graph.get_name(gain), None);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains("n0 [label=\"lfo \\\"1\\\"\\n#0\"];"));
+        assert!(dot.contains("n1 [label=\"node 1\\n#1\"];"));
+        assert!(dot.contains("n0 -> n1 [label=\"0:0 x0.5\"];"));
+        assert!(dot.contains("n1 -> n1 [label=\"0:0 z-4\", style=dashed];"));
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut graph = DeviceGraph::new();
+        let osc = graph.add_node(Oscillator::new(Waveform::Sine));
+        let adder = graph.add_node(Adder::new(2));
+        graph.set_name(osc, "osc").unwrap();
+        graph.set_name(adder, "mix").unwrap();
+        graph.add_edge(osc, 0, adder, 1).unwrap();
+
+        let summary = graph.summary();
+        assert!(summary.contains("    0: osc, 1 inputs, 1 outputs\n"));
+        assert!(summary.contains("Unconnected inputs:\n    osc input 0\n    \
+                                  mix input 0\n"));
+        assert!(summary.contains("Unused outputs:\n    mix output 0\n"));
+    }
+
+    #[test]
+    fn test_editor() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 0);
+        mock1.will_tick(&[], &[1.0]).will_tick(&[], &[2.0]);
+        mock2.will_tick(&[2.0], &[]);
+
+        let mut graph = DeviceGraph::new();
+        let source = graph.add_node(mock1);
+        graph.set_name(source, "source").unwrap();
+        graph.tick();
+
+        let editor = graph.get_editor();
+        let editor = thread::spawn(move || {
+            editor.add_node("sink", mock2).unwrap();
+            editor.send(AddEdge("source".into(), 0, "sink".into(), 0, 1.0))
+                .unwrap();
+            editor.send(RemoveNode("missing".into())).unwrap();
+            editor
+        }).join().unwrap();
+
+        graph.tick();
+        assert!(graph.find_node("sink").is_some());
+        match editor.get_errors()[..] {
+            [Error::OutOfRange("node")] => (),
+            ref errors => panic!("wrong errors: {:?}", errors)
+        }
+    }
+
+    #[test]
+    fn test_replace_node() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 0, 1);
+        for _ in 0..4 {
+            mock1.will_tick(&[], &[1.0]);
+        }
+        for _ in 0..5 {
+            mock2.will_tick(&[], &[3.0]);
+        }
+
+        // Crossfade over four samples
+        let mut graph = DeviceGraph::new();
+        graph.set_sample_rate(4);
+        graph.set_crossfade_time(1.0);
+        let node = graph.add_node(mock1);
+        graph.replace_node(node, mock2).unwrap();
+        for &expected in [1.5, 2.0, 2.5, 3.0, 3.0].iter() {
+            graph.tick();
+            assert_eq!(graph.get_output(node).unwrap(), [expected]);
+        }
+
+        let mock3 = MockAudioDevice::new("mock3", 1, 1);
+        assert!(graph.replace_node(node, mock3).is_err());
+    }
+
+    #[test]
+    fn test_bypass() {
+        let mut mock1 = MockAudioDevice::new("mock1", 0, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 2);
+        for _ in 0..3 {
+            mock1.will_tick(&[], &[4.0]);
+            mock2.will_tick(&[4.0], &[1.0, 1.0]);
+        }
+
+        // Ramp over two samples
+        let mut graph = DeviceGraph::new();
+        graph.set_sample_rate(200);
+        let mock1 = graph.add_node(mock1);
+        let mock2 = graph.add_node(mock2);
+        graph.add_edge(mock1, 0, mock2, 0).unwrap();
+        graph.set_bypass(mock2, true).unwrap();
+        assert!(graph.is_bypassed(mock2).unwrap());
+        graph.tick();
+        assert_eq!(graph.get_output(mock2).unwrap(), [2.5, 0.5]);
+        graph.tick();
+        assert_eq!(graph.get_output(mock2).unwrap(), [4.0, 0.0]);
+
+        graph.set_bypass(mock2, false).unwrap();
+        graph.tick();
+        assert_eq!(graph.get_output(mock2).unwrap(), [2.5, 0.5]);
+    }
+
+    #[test]
+    fn test_mute() {
+        let mut mock = MockAudioDevice::new("mock", 0, 1);
+        mock.will_tick(&[], &[2.0]).will_tick(&[], &[2.0]);
+
+        let mut graph = DeviceGraph::new();
+        graph.set_sample_rate(200);
+        let mock = graph.add_node(mock);
+        graph.set_mute(mock, true).unwrap();
+        graph.set_skip_muted(mock, true).unwrap();
+        graph.tick();
+        assert_eq!(graph.get_output(mock).unwrap(), [1.0]);
+        graph.tick();
+        assert_eq!(graph.get_output(mock).unwrap(), [0.0]);
+
+        // Once silent, the device is no longer ticked
+        graph.tick();
+        assert_eq!(graph.get_output(mock).unwrap(), [0.0]);
+    }
+
+    #[test]
+    fn test_solo() {
+        let mut graph = DeviceGraph::new();
+        graph.set_sample_rate(100);
+        let mut mocks = Vec::new();
+        let ticks: [&[f32]; 4] = [&[], &[], &[1.0, 0.0], &[]];
+        for &inputs in ticks.iter() {
+            let mut mock = MockAudioDevice::new("mock", inputs.len(), 1);
+            mock.will_tick(inputs, &[1.0]);
+            mocks.push(graph.add_node(mock));
+        }
+        graph.add_edge(mocks[0], 0, mocks[2], 0).unwrap();
+        graph.add_edge(mocks[1], 0, mocks[2], 1).unwrap();
+
+        // Only the soloed node and the nodes it feeds stay audible
+        graph.handle_message(SetSolo(mocks[0], true));
+        assert!(graph.is_soloed(mocks[0]).unwrap());
+        assert!(!graph.is_muted(mocks[1]).unwrap());
         graph.tick();
+        let outputs: Vec<_> = mocks.iter()
+            .map(|&mock| graph.get_output(mock).unwrap()[0]).collect();
+        assert_eq!(outputs, [1.0, 0.0, 1.0, 0.0]);
     }
 
     #[test]
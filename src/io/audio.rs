@@ -8,6 +8,10 @@
 //! is going to vary from system to system, but should ideally be as small as
 //! possible without causing skipping in the audio.
 //!
+//! All streams opened by an engine run at the engine's sample rate. Streams
+//! can't change their sample rate once opened, so they ignore
+//! `AudioDevice::set_sample_rate`.
+//!
 //! # Panics
 //!
 //! The `tick` functions for both input and output streams will panic if they
@@ -29,18 +33,33 @@ static PORTAUDIO_T: pa::SampleFormat = pa::SampleFormat::Float32;
 pub struct AudioEngine {
     marker: Rc<AudioEngineMarker>,
     buffer_size: usize,
+    sample_rate: u32,
 }
 
 impl AudioEngine {
     /// Initializes the audio driver and sets the buffer size to be used for IO.
+    ///
+    /// Streams are opened at the default sample rate.
     pub fn with_buffer_size(samples: usize) -> Result<Self> {
+        AudioEngine::new(samples, SAMPLE_RATE)
+    }
+
+    /// Initializes the audio driver, and sets the buffer size and sample rate
+    /// (in Hz) to be used for IO.
+    pub fn new(buffer_size: usize, sample_rate: u32) -> Result<Self> {
         try!(pa::initialize());
         Ok(AudioEngine {
             marker: Rc::new(AudioEngineMarker),
-            buffer_size: samples
+            buffer_size: buffer_size,
+            sample_rate: sample_rate
         })
     }
 
+    /// Returns the sample rate streams are opened at, in Hz.
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
     /// Opens an AudioIn using the default OS device.
     pub fn default_input(&self, num_channels: usize) -> Result<AudioIn> {
         AudioIn::new(self, num_channels)
@@ -81,7 +100,7 @@ impl AudioIn {
     fn new(engine: &AudioEngine, num_channels: usize) -> Result<Self> {
         // Open a stream in blocking mode
         let mut pa_stream = pa::Stream::new();
-        try!(pa_stream.open_default(SAMPLE_RATE as f64,
+        try!(pa_stream.open_default(engine.sample_rate as f64,
                                     engine.buffer_size as u32,
                                     num_channels as i32,
                                     0i32,
@@ -161,7 +180,7 @@ impl AudioOut {
     fn new(engine: &AudioEngine, num_channels: usize) -> Result<Self> {
         // Open a stream in blocking mode
         let mut pa_stream = pa::Stream::new();
-        try!(pa_stream.open_default(SAMPLE_RATE as f64,
+        try!(pa_stream.open_default(engine.sample_rate as f64,
                                     engine.buffer_size as u32,
                                     0i32,
                                     num_channels as i32,
@@ -3,10 +3,11 @@
 use byteorder::{self, ReadBytesExt, WriteBytesExt, LittleEndian};
 use std::fs::File;
 use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
 use std::path::Path;
 
 use error::{Error, Result};
-use types::{SAMPLE_RATE, AudioDevice, Time, Sample};
+use types::{SAMPLE_RATE, AudioDevice, Time, Sample, SampleFloat};
 use utils::helpers::{i16_to_sample, sample_to_16};
 
 
@@ -14,11 +15,17 @@ use utils::helpers::{i16_to_sample, sample_to_16};
 ///
 /// The reader will continue until it runs out of samples. When it does, the
 /// reader will return silence until it is reset to the beginning of the file.
-pub struct WavReader<R: Read> {
+///
+/// The reader does not perform sample rate conversion. The file's sample rate
+/// is reported by `get_sample_rate`, and should be used to configure the rest
+/// of the signal chain.
+pub struct WavReader<R: Read, S: SampleFloat = Sample> {
     num_channels: usize,
+    sample_rate: u32,
     num_samples: Time,
     samples_read: Time,
-    reader: R
+    reader: R,
+    sample: PhantomData<S>
 }
 
 impl WavReader<File> {
@@ -31,17 +38,32 @@ impl WavReader<File> {
 
 impl<R: Read> WavReader<R> {
     /// Retuns a `WavReader` reading anything implementing `Read`.
-    pub fn new(mut reader: R) -> Result<Self> {
+    pub fn new(reader: R) -> Result<Self> {
+        WavReader::new_generic(reader)
+    }
+}
+
+impl<R: Read, S: SampleFloat> WavReader<R, S> {
+    /// Returns a `WavReader` producing any sample type. `new` returns the
+    /// same reader for the default `Sample` type.
+    pub fn new_generic(mut reader: R) -> Result<Self> {
         let header = try!(WavHeader::read_from_file(&mut reader));
         Ok(WavReader {
             num_channels: header.num_channels as usize,
+            sample_rate: header.sample_rate,
             num_samples: (header.data_size / ((header.bit_depth/8) as u32) /
                 (header.num_channels as u32)) as Time,
             samples_read: 0,
-            reader: reader
+            reader: reader,
+            sample: PhantomData
         })
     }
 
+    /// Returns the sample rate of the wav file, in Hz.
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
     /// Returns the number of audio samples in the wav file.
     pub fn get_num_samples(&self) -> Time {
         self.num_samples
@@ -53,7 +75,7 @@ impl<R: Read> WavReader<R> {
     }
 }
 
-impl<R: Read+Seek> WavReader<R> {
+impl<R: Read+Seek, S: SampleFloat> WavReader<R, S> {
     /// Resets the reader to begin reading from the start of the file.
     pub fn restart(&mut self) -> io::Result<u64> {
         self.samples_read = 0;
@@ -61,7 +83,7 @@ impl<R: Read+Seek> WavReader<R> {
     }
 }
 
-impl<R: Read> AudioDevice for WavReader<R> {
+impl<R: Read, S: SampleFloat> AudioDevice<S> for WavReader<R, S> {
     fn num_inputs(&self) -> usize {
         0
     }
@@ -70,18 +92,25 @@ impl<R: Read> AudioDevice for WavReader<R> {
         self.num_channels
     }
 
-    fn tick(&mut self, _: Time, _: &[Sample], outputs: &mut[Sample]) {
-        for i in 0..self.num_channels {
-            let s = if self.samples_read < self.num_samples {
-                let n = self.reader.read_i16::<LittleEndian>()
-                    .expect("Failed to read next sample from wav.");
-                i16_to_sample(n)
-            } else {
-                0.0
-            };
-            outputs[i] = s;
+    fn tick(&mut self, t: Time, inputs: &[S], outputs: &mut[S]) {
+        self.tick_block(t, 1, inputs, outputs);
+    }
+
+    fn tick_block(&mut self, _: Time, frames: usize, _: &[S],
+                  outputs: &mut[S]) {
+        for frame in outputs[..frames*self.num_channels]
+                .chunks_mut(self.num_channels) {
+            for s in frame.iter_mut() {
+                *s = if self.samples_read < self.num_samples {
+                    let n = self.reader.read_i16::<LittleEndian>()
+                        .expect("Failed to read next sample from wav.");
+                    S::from_f32(i16_to_sample(n))
+                } else {
+                    S::zero()
+                };
+            }
+            self.samples_read += 1;
         }
-        self.samples_read += 1;
     }
 }
 
@@ -91,13 +120,19 @@ impl<R: Read> AudioDevice for WavReader<R> {
 /// The writer initializes the data_size to be 0. This will not be overwritten
 /// with the proper size until `update_data_size` is called.
 ///
+/// The writer starts at the default sample rate. If the sample rate is changed
+/// using `set_sample_rate`, the new rate is written to the header along with
+/// the data size.
+///
 /// While `WavReader` only requires its type be `Seek` to use the `restart`
 /// method, the `WavWriter` reqiures `Seek` for all types, because the final
 /// data size must be written to the header when the writer leaves scope.
-pub struct WavWriter<W: Write+Seek> {
+pub struct WavWriter<W: Write+Seek, S: SampleFloat = Sample> {
     num_channels: usize,
+    sample_rate: u32,
     samples_written: usize,
     writer: W,
+    sample: PhantomData<S>
 }
 
 impl WavWriter<File> {
@@ -111,23 +146,41 @@ impl WavWriter<File> {
 
 impl<W: Write+Seek> WavWriter<W> {
     /// Returns a `WavWriter` writing to anything implementing `Write`.
-    pub fn new(mut writer: W, num_channels: usize) -> Result<Self> {
+    pub fn new(writer: W, num_channels: usize) -> Result<Self> {
+        WavWriter::new_generic(writer, num_channels)
+    }
+}
+
+impl<W: Write+Seek, S: SampleFloat> WavWriter<W, S> {
+    /// Returns a `WavWriter` consuming any sample type. `new` returns the
+    /// same writer for the default `Sample` type.
+    pub fn new_generic(mut writer: W, num_channels: usize) -> Result<Self> {
         let header = WavHeader::new(num_channels as u16, SAMPLE_RATE as u32,
                                     0u32);
         try!(header.write_to_file(&mut writer));
         Ok(WavWriter {
             num_channels: num_channels,
+            sample_rate: SAMPLE_RATE,
             samples_written: 0,
             writer: writer,
+            sample: PhantomData
         })
     }
 }
 
-impl<W: Write+Seek> Drop for WavWriter<W> {
+impl<W: Write+Seek, S: SampleFloat> Drop for WavWriter<W, S> {
     fn drop(&mut self) {
-        // Updates the wav header to have the correct amount of data written
+        // Updates the wav header to have the correct sample rate and amount of
+        // data written
         let data_size = self.samples_written * self.num_channels * 16/8;
         let file_size = 36+data_size;
+        let byte_rate = self.sample_rate * (self.num_channels as u32) * 16/8;
+        self.writer.seek(SeekFrom::Start(24))
+            .expect("Failed to seek wav sample rate.");
+        self.writer.write_u32::<LittleEndian>(self.sample_rate)
+            .expect("Failed to write wav sample rate.");
+        self.writer.write_u32::<LittleEndian>(byte_rate)
+            .expect("Failed to write wav byte rate.");
         self.writer.seek(SeekFrom::Start(4))
             .expect("Failed to seek wav file size.");
         self.writer.write_u32::<LittleEndian>(file_size as u32)
@@ -139,7 +192,7 @@ impl<W: Write+Seek> Drop for WavWriter<W> {
     }
 }
 
-impl<W: Write+Seek> AudioDevice for WavWriter<W> {
+impl<W: Write+Seek, S: SampleFloat> AudioDevice<S> for WavWriter<W, S> {
     fn num_inputs(&self) -> usize {
         self.num_channels
     }
@@ -148,12 +201,21 @@ impl<W: Write+Seek> AudioDevice for WavWriter<W> {
         0
     }
 
-    fn tick(&mut self, _: Time, inputs: &[Sample], _: &mut[Sample]) {
-        for s in inputs.iter() {
-            self.writer.write_i16::<LittleEndian>(sample_to_16(*s))
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn tick(&mut self, t: Time, inputs: &[S], outputs: &mut[S]) {
+        self.tick_block(t, 1, inputs, outputs);
+    }
+
+    fn tick_block(&mut self, _: Time, frames: usize, inputs: &[S],
+                  _: &mut[S]) {
+        for s in inputs[..frames*self.num_channels].iter() {
+            self.writer.write_i16::<LittleEndian>(sample_to_16(s.into_f32()))
                 .expect("Failed to write next sample to wav file.");
         }
-        self.samples_written += 1;
+        self.samples_written += frames;
     }
 }
 
@@ -264,10 +326,6 @@ impl WavHeader {
         if self.format != 1 {
             return Err(Error::Unsupported("Only PCM is supported"));
         }
-        if self.sample_rate != (SAMPLE_RATE as u32) {
-            return Err(Error::Unsupported(
-                    "Sample rate conversion not supported"));
-        }
         if self.bit_depth != 16 {
             return Err(Error::Unsupported("Only 16-bit supported"));
         }
@@ -299,7 +357,7 @@ impl WavHeader {
 mod test {
     use std::io::Cursor;
 
-    use types::AudioDevice;
+    use types::{AudioDevice, Sample};
     use super::{WavHeader, WavReader, WavWriter};
 
     static WAV_HEADER: [u8; 48] =
@@ -325,9 +383,10 @@ mod test {
         assert_eq!(reader.num_inputs(), 0);
         assert_eq!(reader.num_outputs(), 2);
         assert_eq!(reader.get_num_samples(), 1);
+        assert_eq!(reader.get_sample_rate(), 44100);
         assert_eq!(reader.is_done(), false);
 
-        let mut output = [0.0, 0.0];
+        let mut output: [Sample; 2] = [0.0, 0.0];
         reader.tick(0, &[], &mut output);
         assert_eq!(reader.is_done(), true);
         assert_eq!(output, [-1.0, 0.9999695]);
@@ -357,4 +416,19 @@ mod test {
         }
         assert_eq!(&buffer[..], &WAV_HEADER[..]);
     }
+
+    #[test]
+    fn test_wav_writer_sample_rate() {
+        let mut buffer = [0u8; 48];
+        {
+            let cursor = Cursor::new(&mut buffer[..]);
+            let mut writer = WavWriter::new(cursor, 2).unwrap();
+            writer.set_sample_rate(48000);
+            writer.tick(0, &[-1.0, 0.9999695], &mut[]);
+        }
+        let mut cursor = Cursor::new(&buffer[..]);
+        let header = WavHeader::read_from_file(&mut cursor).unwrap();
+        assert_eq!(header.sample_rate, 48000);
+        assert_eq!(header.byte_rate, 192000);
+    }
 }
@@ -1,7 +1,6 @@
 //! An envelope estimator.
 
-use num::traits::Float;
-use types::{SAMPLE_RATE, Sample};
+use types::{SAMPLE_RATE, Sample, SampleFloat};
 
 
 /// Performs envelope estimation for a signal.
@@ -20,10 +19,12 @@ use types::{SAMPLE_RATE, Sample};
 /// while the release value can be high in order to coast through periodic
 /// troughs in the signal.
 #[derive(Clone, Copy, Debug)]
-pub struct LevelDetector {
-    attack_alpha: f32,
-    release_alpha: f32,
-    last_power: f32
+pub struct LevelDetector<S: SampleFloat = Sample> {
+    attack_tau: f32,
+    release_tau: f32,
+    attack_alpha: S,
+    release_alpha: S,
+    last_power: S
 }
 
 impl LevelDetector {
@@ -39,17 +40,40 @@ impl LevelDetector {
     ///   in milliseconds.
     /// * `release_tau` specifies the time constant when the signal id decaying,
     ///   in milliseconds.
+    ///
+    /// The detector assumes the default sample rate; use `set_sample_rate` to
+    /// process at a different rate.
     pub fn new(attack_tau: f32, release_tau: f32) -> Self {
+        LevelDetector::new_generic(attack_tau, release_tau)
+    }
+}
+
+impl<S: SampleFloat> LevelDetector<S> {
+    /// Returns a level detector processing any sample type. `new` returns the
+    /// same detector for the default `Sample` type.
+    pub fn new_generic(attack_tau: f32, release_tau: f32) -> Self {
         LevelDetector {
-            attack_alpha: time_constant_to_multiplier(attack_tau),
-            release_alpha: time_constant_to_multiplier(release_tau),
-            last_power: 0.0
+            attack_tau: attack_tau,
+            release_tau: release_tau,
+            attack_alpha: time_constant_to_multiplier(attack_tau, SAMPLE_RATE),
+            release_alpha: time_constant_to_multiplier(release_tau,
+                                                       SAMPLE_RATE),
+            last_power: S::zero()
         }
     }
 
+    /// Sets the sample rate of the signal, in Hz, and recomputes the leak
+    /// rates to match.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.attack_alpha = time_constant_to_multiplier(self.attack_tau,
+                                                        sample_rate);
+        self.release_alpha = time_constant_to_multiplier(self.release_tau,
+                                                         sample_rate);
+    }
+
     /// Given the next input sample `s`, computes the current estimate of the
     /// envelope value.
-    pub fn compute_next_level(&mut self, s: Sample) -> f32 {
+    pub fn compute_next_level(&mut self, s: S) -> S {
         // Perform leaky integration on the signal power, rather than amplitude
         let pow = s*s;
         let alpha = if pow > self.last_power {
@@ -57,7 +81,7 @@ impl LevelDetector {
         } else {
             self.release_alpha
         };
-        self.last_power = alpha*self.last_power + (1.0-alpha)*pow;
+        self.last_power = alpha*self.last_power + (S::one()-alpha)*pow;
 
         // Convert from power to amplitude and return
         self.last_power.sqrt()
@@ -65,6 +89,7 @@ impl LevelDetector {
 }
 
 /// Converts a time constant in milliseconds to a leak rate.
-fn time_constant_to_multiplier(tau: f32) -> f32 {
-    (-1.0 / (tau/1000.0 * (SAMPLE_RATE as f32))).exp()
+fn time_constant_to_multiplier<S: SampleFloat>(tau: f32, sample_rate: u32)
+        -> S {
+    S::from_f32(-1.0 / (tau/1000.0 * (sample_rate as f32))).exp()
 }
@@ -104,6 +104,11 @@
 //! wrapper](wrappers/struct.Messaged.html) provides an abstraction using
 //! [Rust channels](https://doc.rust-lang.org/std/sync/mpsc/fn.channel.html).
 //!
+//! Devices also describe their settings as named parameters, using the
+//! [`Parameters` trait](parameters/trait.Parameters.html). Unlike messages,
+//! parameters can be listed and set without knowing the device's type, which
+//! makes them useful for generic hosts and user interfaces.
+//!
 //! # Thread Safety
 //!
 //! Unfortunately, `oxcable` is not able to provide `AudioDevice` containers
@@ -128,10 +133,16 @@ pub mod io;
 pub mod level_detector;
 pub mod mixers;
 pub mod oscillator;
+pub mod parallel;
+pub mod parameters;
+pub mod patch;
+pub mod render;
 pub mod tick;
+pub mod transport;
 pub mod types;
 pub mod utils;
 pub mod voice_array;
+pub mod wavetable;
 pub mod wrappers;
 
 #[cfg(test)] mod testing;
@@ -1,21 +1,32 @@
-use types::{AudioDevice, Sample, Time};
+use std::marker::PhantomData;
+
+use types::{AudioDevice, Sample, SampleFloat, Time};
 
 
 /// An adder.
 ///
 /// The `Adder` simply sums all of its inputs.
-pub struct Adder {
-    num_channels: usize
+pub struct Adder<S: SampleFloat = Sample> {
+    num_channels: usize,
+    sample: PhantomData<S>
 }
 
 impl Adder {
     /// Returns a new adder with `num_inputs` inputs and one output.
     pub fn new(num_channels: usize) -> Self {
-        Adder { num_channels: num_channels }
+        Adder::new_generic(num_channels)
+    }
+}
+
+impl<S: SampleFloat> Adder<S> {
+    /// Returns an adder processing any sample type. `new` returns the same
+    /// device for the default `Sample` type.
+    pub fn new_generic(num_channels: usize) -> Self {
+        Adder { num_channels: num_channels, sample: PhantomData }
     }
 }
 
-impl AudioDevice for Adder {
+impl<S: SampleFloat> AudioDevice<S> for Adder<S> {
     fn num_inputs(&self) -> usize {
         self.num_channels
     }
@@ -24,11 +35,23 @@ impl AudioDevice for Adder {
         1
     }
 
-    fn tick(&mut self, _: Time, inputs: &[Sample], outputs: &mut[Sample]) {
-        let mut sum = 0.0;
-        for s in inputs.iter() {
-            sum += *s;
+    fn tick(&mut self, _: Time, inputs: &[S], outputs: &mut[S]) {
+        let mut sum = S::zero();
+        for &s in inputs.iter() {
+            sum = sum + s;
         }
         outputs[0] = sum;
     }
+
+    fn tick_block(&mut self, _: Time, frames: usize, inputs: &[S],
+                  outputs: &mut[S]) {
+        let n = self.num_channels;
+        for (f, y) in outputs[..frames].iter_mut().enumerate() {
+            let mut sum = S::zero();
+            for &s in inputs[f*n..(f+1)*n].iter() {
+                sum = sum + s;
+            }
+            *y = sum;
+        }
+    }
 }
@@ -0,0 +1,99 @@
+use types::{AudioDevice, Sample, SampleFloat, Time};
+
+
+/// A channel adapter.
+///
+/// The adapter converts between different numbers of channels by mixing its
+/// inputs through a matrix of gains. Each output is the sum of every input,
+/// scaled by that output's gain for the input.
+#[derive(Clone, Debug)]
+pub struct ChannelAdapter<S: SampleFloat = Sample> {
+    num_inputs: usize,
+    num_outputs: usize,
+    gains: Vec<S>, // one row of input gains per output
+}
+
+impl<S: SampleFloat> ChannelAdapter<S> {
+    /// Returns a new adapter from `num_inputs` to `num_outputs` channels.
+    ///
+    /// A single input is duplicated onto every output, and a single output
+    /// averages every input. Otherwise, each output passes through the input
+    /// with the same index; extra inputs are dropped, and extra outputs are
+    /// silent.
+    pub fn new(num_inputs: usize, num_outputs: usize) -> Self {
+        let mut gains = vec![S::zero(); num_inputs*num_outputs];
+        for j in 0..num_outputs {
+            for i in 0..num_inputs {
+                gains[j*num_inputs + i] = if num_inputs == 1 {
+                    S::one()
+                } else if num_outputs == 1 {
+                    S::from_f32(1.0 / num_inputs as f32)
+                } else if i == j {
+                    S::one()
+                } else {
+                    S::zero()
+                };
+            }
+        }
+        ChannelAdapter {
+            num_inputs: num_inputs,
+            num_outputs: num_outputs,
+            gains: gains
+        }
+    }
+
+    /// Returns a new adapter that mixes `num_inputs` inputs through a custom
+    /// matrix. The matrix holds one row for each output, with the gain of
+    /// each input into that output.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a row doesn't have `num_inputs` gains.
+    pub fn from_matrix(num_inputs: usize, matrix: &[Vec<f32>]) -> Self {
+        let mut gains = Vec::with_capacity(num_inputs*matrix.len());
+        for row in matrix.iter() {
+            if row.len() != num_inputs {
+                panic!("ChannelAdapter: each row needs a gain for every input");
+            }
+            gains.extend(row.iter().map(|&gain| S::from_f32(gain)));
+        }
+        ChannelAdapter {
+            num_inputs: num_inputs,
+            num_outputs: matrix.len(),
+            gains: gains
+        }
+    }
+
+    /// Returns the gain of input `i` into output `j`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either channel is out of range.
+    pub fn get_gain(&self, i: usize, j: usize) -> f32 {
+        if i >= self.num_inputs || j >= self.num_outputs {
+            panic!("ChannelAdapter: channel out of range");
+        }
+        self.gains[j*self.num_inputs + i].into_f32()
+    }
+}
+
+impl<S: SampleFloat> AudioDevice<S> for ChannelAdapter<S> {
+    fn num_inputs(&self) -> usize {
+        self.num_inputs
+    }
+
+    fn num_outputs(&self) -> usize {
+        self.num_outputs
+    }
+
+    fn tick(&mut self, _: Time, inputs: &[S], outputs: &mut[S]) {
+        let n = self.num_inputs;
+        for (j, y) in outputs[..self.num_outputs].iter_mut().enumerate() {
+            let mut sum = S::zero();
+            for (&x, &gain) in inputs.iter().zip(&self.gains[j*n..(j+1)*n]) {
+                sum = sum + gain*x;
+            }
+            *y = sum;
+        }
+    }
+}
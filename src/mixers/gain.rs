@@ -1,20 +1,29 @@
-use types::{AudioDevice, MessageReceiver, Sample, Time};
+use parameters::{Decibels, ParameterInfo, Parameters};
+use types::{SAMPLE_RATE, AudioDevice, MessageReceiver, Sample, SampleFloat,
+            Time};
 use utils::helpers::decibel_to_ratio;
+use utils::smoothed::{DEFAULT_RAMP_TIME, Exponential, Smoothed};
 
 
 /// Defines the messages that the Gain supports.
 #[derive(Clone, Copy, Debug)]
 pub enum Message {
     /// Sets the output gian, in decibels.
-    SetGain(f32)
+    SetGain(f32),
+    /// Sets the time taken to ramp to a new gain, in seconds.
+    SetRampTime(f32),
 }
 pub use self::Message::*;
 
 
 /// A gain filter.
-pub struct Gain {
+///
+/// Changes to the gain are smoothed to avoid clicks. By default the gain ramps
+/// to each new value over `DEFAULT_RAMP_TIME`.
+pub struct Gain<S: SampleFloat = Sample> {
     num_channels: usize,
-    gain: f32,
+    gain_db: f32,
+    gain: Smoothed<S>,
 }
 
 impl Gain {
@@ -22,22 +31,65 @@ impl Gain {
     ///
     /// * `gain` is in decibels.
     pub fn new(gain: f32, num_channels: usize) -> Self {
+        Gain::new_generic(gain, num_channels)
+    }
+}
+
+impl<S: SampleFloat> Gain<S> {
+    /// Returns a gain filter processing any sample type. `new` returns the same
+    /// device for the default `Sample` type.
+    pub fn new_generic(gain: f32, num_channels: usize) -> Self {
         Gain {
             num_channels: num_channels,
-            gain: decibel_to_ratio(gain)
+            gain_db: gain,
+            gain: Smoothed::new(S::from_f32(decibel_to_ratio(gain)),
+                                Exponential)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE)
         }
     }
 }
 
-impl MessageReceiver for Gain {
+impl<S: SampleFloat> MessageReceiver for Gain<S> {
     type Msg = Message;
     fn handle_message(&mut self, msg: Message) {
-        let SetGain(gain) = msg;
-        self.gain = decibel_to_ratio(gain);
+        match msg {
+            SetGain(gain) => {
+                self.gain_db = gain;
+                self.gain.set_target(S::from_f32(decibel_to_ratio(gain)));
+            },
+            SetRampTime(seconds) => {
+                self.gain.set_ramp_time(seconds);
+            }
+        }
     }
 }
 
-impl AudioDevice for Gain {
+impl<S: SampleFloat> Parameters for Gain<S> {
+    fn num_parameters(&self) -> usize {
+        1
+    }
+
+    fn get_parameter_info(&self, i: usize) -> ParameterInfo {
+        match i {
+            0 => ParameterInfo::new("gain", -60.0, 24.0, 0.0).unit(Decibels),
+            _ => panic!("Gain: parameter index {} out of range", i)
+        }
+    }
+
+    fn get_parameter(&self, i: usize) -> f32 {
+        match i {
+            0 => self.gain_db,
+            _ => panic!("Gain: parameter index {} out of range", i)
+        }
+    }
+
+    fn set_parameter(&mut self, i: usize, value: f32) {
+        let value = self.get_parameter_info(i).clamp(value);
+        self.handle_message(SetGain(value));
+    }
+}
+
+impl<S: SampleFloat> AudioDevice<S> for Gain<S> {
     fn num_inputs(&self) -> usize {
         self.num_channels
     }
@@ -46,9 +98,42 @@ impl AudioDevice for Gain {
         self.num_channels
     }
 
-    fn tick(&mut self, _: Time, inputs: &[Sample], outputs: &mut[Sample]) {
-        for (i,s) in inputs.iter().enumerate() {
-            outputs[i] = self.gain*s;
+    fn parameters(&self) -> Option<&Parameters> {
+        Some(self)
+    }
+
+    fn parameters_mut(&mut self) -> Option<&mut Parameters> {
+        Some(self)
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.gain.set_sample_rate(sample_rate);
+    }
+
+    fn reset(&mut self) {
+        self.gain.finish();
+    }
+
+    fn tick(&mut self, t: Time, inputs: &[S], outputs: &mut[S]) {
+        self.tick_block(t, 1, inputs, outputs);
+    }
+
+    fn tick_block(&mut self, _: Time, frames: usize, inputs: &[S],
+                  outputs: &mut[S]) {
+        let n = self.num_channels;
+        if self.gain.is_ramping() {
+            for f in 0..frames {
+                let gain = self.gain.tick();
+                for i in f*n..(f+1)*n {
+                    outputs[i] = gain*inputs[i];
+                }
+            }
+        } else {
+            let gain = self.gain.get_value();
+            let n = frames*n;
+            for (y, &x) in outputs[..n].iter_mut().zip(&inputs[..n]) {
+                *y = gain*x;
+            }
         }
     }
 }
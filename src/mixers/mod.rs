@@ -1,11 +1,13 @@
 //! Tools for combining several audio signals.
 
 pub mod adder;
+pub mod channel_adapter;
 pub mod gain;
 pub mod multiplexer;
 pub mod multiplier;
 
 pub use self::adder::Adder;
+pub use self::channel_adapter::ChannelAdapter;
 pub use self::gain::Gain;
 pub use self::multiplier::Multiplier;
 pub use self::multiplexer::Multiplexer;
@@ -1,4 +1,7 @@
-use types::{AudioDevice, MessageReceiver, Sample, Time};
+use std::marker::PhantomData;
+
+use parameters::{Discrete, Index, ParameterInfo, Parameters};
+use types::{AudioDevice, MessageReceiver, Sample, SampleFloat, Time};
 
 
 /// Defines the messages that the Multiplexer supports.
@@ -15,17 +18,27 @@ pub use self::Message::*;
 /// A multiplexer takes in several inputs, and chooses only one of them to send
 /// to the output. Since the input can be changed, this can be used as a switch
 /// between multiple signals.
-pub struct Multiplexer {
+pub struct Multiplexer<S: SampleFloat = Sample> {
     num_inputs: usize,
     selected: usize,
+    sample: PhantomData<S>
 }
 
 impl Multiplexer {
     /// Returns a new multiplexer with `num_inputs` input and one output.
     pub fn new(num_inputs: usize) -> Self {
+        Multiplexer::new_generic(num_inputs)
+    }
+}
+
+impl<S: SampleFloat> Multiplexer<S> {
+    /// Returns a multiplexer processing any sample type. `new` returns the same
+    /// device for the default `Sample` type.
+    pub fn new_generic(num_inputs: usize) -> Self {
         Multiplexer {
             num_inputs: num_inputs,
-            selected: 0
+            selected: 0,
+            sample: PhantomData
         }
     }
 
@@ -44,7 +57,7 @@ impl Multiplexer {
     }
 }
 
-impl MessageReceiver for Multiplexer {
+impl<S: SampleFloat> MessageReceiver for Multiplexer<S> {
     type Msg = Message;
     fn handle_message(&mut self, msg: Message) {
         let SelectChannel(i) = msg;
@@ -52,7 +65,34 @@ impl MessageReceiver for Multiplexer {
     }
 }
 
-impl AudioDevice for Multiplexer {
+impl<S: SampleFloat> Parameters for Multiplexer<S> {
+    fn num_parameters(&self) -> usize {
+        1
+    }
+
+    fn get_parameter_info(&self, i: usize) -> ParameterInfo {
+        let max = self.num_inputs.saturating_sub(1) as f32;
+        match i {
+            0 => ParameterInfo::new("input", 0.0, max, 0.0).unit(Index)
+                .scale(Discrete),
+            _ => panic!("Multiplexer: parameter index {} out of range", i)
+        }
+    }
+
+    fn get_parameter(&self, i: usize) -> f32 {
+        match i {
+            0 => self.selected as f32,
+            _ => panic!("Multiplexer: parameter index {} out of range", i)
+        }
+    }
+
+    fn set_parameter(&mut self, i: usize, value: f32) {
+        let value = self.get_parameter_info(i).clamp(value);
+        self.handle_message(SelectChannel(value as usize));
+    }
+}
+
+impl<S: SampleFloat> AudioDevice<S> for Multiplexer<S> {
     fn num_inputs(&self) -> usize {
         self.num_inputs
     }
@@ -61,7 +101,15 @@ impl AudioDevice for Multiplexer {
         1
     }
 
-    fn tick(&mut self, _: Time, inputs: &[Sample], outputs: &mut[Sample]) {
+    fn parameters(&self) -> Option<&Parameters> {
+        Some(self)
+    }
+
+    fn parameters_mut(&mut self) -> Option<&mut Parameters> {
+        Some(self)
+    }
+
+    fn tick(&mut self, _: Time, inputs: &[S], outputs: &mut[S]) {
         outputs[0] = inputs[self.selected];
     }
 }
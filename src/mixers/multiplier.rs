@@ -1,21 +1,32 @@
-use types::{AudioDevice, Sample, Time};
+use std::marker::PhantomData;
+
+use types::{AudioDevice, Sample, SampleFloat, Time};
 
 
 /// A multiplier.
 ///
 /// The `Multiplier` simply multiplies all of its inputs.
-pub struct Multiplier{
-    num_channels: usize
+pub struct Multiplier<S: SampleFloat = Sample> {
+    num_channels: usize,
+    sample: PhantomData<S>
 }
 
 impl Multiplier {
     /// Returns a new multiplier with `num_inputs` inputs and one output.
     pub fn new(num_channels: usize) -> Self {
-        Multiplier { num_channels: num_channels }
+        Multiplier::new_generic(num_channels)
+    }
+}
+
+impl<S: SampleFloat> Multiplier<S> {
+    /// Returns a multiplier processing any sample type. `new` returns the same
+    /// device for the default `Sample` type.
+    pub fn new_generic(num_channels: usize) -> Self {
+        Multiplier { num_channels: num_channels, sample: PhantomData }
     }
 }
 
-impl AudioDevice for Multiplier {
+impl<S: SampleFloat> AudioDevice<S> for Multiplier<S> {
     fn num_inputs(&self) -> usize {
         self.num_channels
     }
@@ -24,10 +35,10 @@ impl AudioDevice for Multiplier {
         1
     }
 
-    fn tick(&mut self, _: Time, inputs: &[Sample], outputs: &mut[Sample]) {
-        let mut prod = 1.0;
-        for s in inputs.iter() {
-            prod *= *s;
+    fn tick(&mut self, _: Time, inputs: &[S], outputs: &mut[S]) {
+        let mut prod = S::one();
+        for &s in inputs.iter() {
+            prod = prod * s;
         }
         outputs[0] = prod;
     }
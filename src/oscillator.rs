@@ -26,6 +26,34 @@
 //! If provided, then the LFO is used to modulate the frequency of the
 //! oscillator, producing a vibrato.
 //!
+//! ## Modulation Inputs
+//!
+//! By default, the oscillator's single input is the LFO. Using the `inputs`
+//! builder method, the oscillator can instead take any number of inputs, each
+//! with its own role:
+//!
+//! * `LFO` is the vibrato input described above, scaled by the LFO intensity.
+//! * `LinearFM` adds to the frequency, scaled by the FM depth in Hz. The
+//!   frequency may be pushed below zero, in which case the oscillator runs
+//!   backwards (through-zero FM).
+//! * `PhaseMod` offsets the phase of the waveform, scaled by the PM depth in
+//!   radians.
+//! * `PitchCV` transposes the frequency exponentially, scaled by the pitch CV
+//!   depth in steps per unit; the default depth of 12 steps follows one unit
+//!   per octave.
+//!
+//! Every input may run at audio rate, and each depth can be set through its
+//! own message.
+//!
+//! ## Smoothing
+//!
+//! Changes to the transposition, bend and modulation depths are smoothed to
+//! avoid clicks. By default they ramp to each new value over
+//! `DEFAULT_RAMP_TIME`; the builder methods set their values instantly.
+//!
+//! The frequency jumps straight to each new value, so that notes change
+//! cleanly. A glide between notes can be enabled by setting a glide time.
+//!
 //! ## Example
 //!
 //! The oscillator uses a builder pattern for initialization. The following will
@@ -35,12 +63,30 @@
 //! use oxcable::oscillator::*;
 //! let osc = Oscillator::new(Saw(PolyBlep)).freq(440.0).lfo_intensity(0.1);
 //! ```
+//!
+//! The following sets up a sine carrier, whose two inputs take an FM
+//! modulator and a pitch CV:
+//!
+//! ```
+//! use oxcable::oscillator::*;
+//! use oxcable::types::MessageReceiver;
+//! let mut osc = Oscillator::new(Sine).freq(220.0)
+//!     .inputs(&[LinearFM, PitchCV]);
+//! osc.handle_message(SetFMDepth(110.0));
+//! ```
 
-use std::f32::consts::PI;
+use std::f64::consts::PI;
 use num::traits::Float;
 use rand::random;
 
-use types::{SAMPLE_RATE, AudioDevice, MessageReceiver, Sample, Time};
+use parameters::{Hertz, Logarithmic, ParameterInfo, Parameters, Steps};
+use types::{SAMPLE_RATE, AudioDevice, MessageReceiver, Sample, SampleFloat,
+            Time};
+use utils::smoothed::{DEFAULT_RAMP_TIME, Exponential, Linear, Smoothed};
+
+
+/// The frequency of a new oscillator, in Hz.
+const DEFAULT_FREQ: f32 = 440.0;
 
 
 /// Defines the messages that the Oscillator supports.
@@ -56,10 +102,37 @@ pub enum Message {
     SetTranspose(f32),
     /// Sets the pitch bend, in steps.
     SetBend(f32),
+    /// Sets the time taken to ramp to a new transposition, bend or modulation
+    /// depth, in seconds.
+    SetRampTime(f32),
+    /// Sets the time taken to glide to a new frequency, in seconds. Defaults
+    /// to zero, which disables gliding.
+    SetGlideTime(f32),
+    /// Sets the linear FM depth, in Hz per unit of input.
+    SetFMDepth(f32),
+    /// Sets the phase modulation depth, in radians per unit of input.
+    SetPMDepth(f32),
+    /// Sets the pitch CV depth, in steps per unit of input.
+    SetPitchCVDepth(f32),
 }
 pub use self::Message::*;
 
 
+/// The role of an oscillator input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    /// An exponential vibrato, scaled by the LFO intensity.
+    LFO,
+    /// Linear, through-zero frequency modulation, scaled by the FM depth.
+    LinearFM,
+    /// Phase modulation, scaled by the PM depth.
+    PhaseMod,
+    /// An exponential pitch control, scaled by the pitch CV depth.
+    PitchCV,
+}
+pub use self::Input::*;
+
+
 /// Antialiasing method for certain waveforms.
 #[derive(Clone, Copy, Debug)]
 pub enum AntialiasType {
@@ -89,35 +162,102 @@ pub enum Waveform {
 }
 pub use self::Waveform::*;
 
+/// The names of each waveform, in the order of the waveform parameter.
+static WAVEFORM_NAMES: [&str; 9] = [
+    "sine", "saw", "aliased saw", "square", "aliased square", "triangle",
+    "aliased triangle", "white noise", "pulse train"
+];
+
+/// Returns the index of a waveform in `WAVEFORM_NAMES`.
+fn waveform_to_index(waveform: Waveform) -> usize {
+    match waveform {
+        Sine => 0,
+        Saw(PolyBlep) => 1,
+        Saw(Aliased) => 2,
+        Square(PolyBlep) => 3,
+        Square(Aliased) => 4,
+        Tri(PolyBlep) => 5,
+        Tri(Aliased) => 6,
+        WhiteNoise => 7,
+        PulseTrain => 8
+    }
+}
+
+/// Returns the waveform at an index of `WAVEFORM_NAMES`.
+fn waveform_from_index(i: usize) -> Waveform {
+    match i {
+        0 => Sine,
+        1 => Saw(PolyBlep),
+        2 => Saw(Aliased),
+        3 => Square(PolyBlep),
+        4 => Square(Aliased),
+        5 => Tri(PolyBlep),
+        6 => Tri(Aliased),
+        7 => WhiteNoise,
+        _ => PulseTrain
+    }
+}
+
 
 /// An oscillator that generates a periodic waveform.
-pub struct Oscillator {
+pub struct Oscillator<S: SampleFloat = Sample> {
+    sample_rate: u32,
+    freq: Smoothed<f32>,
     waveform: Waveform,
-    lfo_intensity: f32,
-    transpose: f32,
-    bend: f32,
-    phase: f32,
-    phase_delta: f32,
-    last_sample: Sample,
+    inputs: Vec<Input>,
+    lfo_intensity: Smoothed<S>,
+    fm_depth: Smoothed<S>,
+    pm_depth: Smoothed<S>,
+    pitch_cv_depth: Smoothed<S>,
+    transpose: Smoothed<S>,
+    bend: Smoothed<S>,
+    phase: S,
+    phase_delta: S,
+    last_sample: S,
 }
 
 impl Oscillator {
-    /// Returns an oscillator with the specified waveform.
+    /// Returns an oscillator with the specified waveform, at the default
+    /// frequency of 440 Hz.
     pub fn new(waveform: Waveform) -> Self {
-        Oscillator {
+        Oscillator::new_generic(waveform)
+    }
+}
+
+impl<S: SampleFloat> Oscillator<S> {
+    /// Returns an oscillator processing any sample type. `new` returns the same
+    /// device for the default `Sample` type.
+    pub fn new_generic(waveform: Waveform) -> Self {
+        let mut osc = Oscillator {
+            sample_rate: SAMPLE_RATE,
+            freq: Smoothed::new(DEFAULT_FREQ, Exponential)
+                .ramp_time(0.0, SAMPLE_RATE),
             waveform: waveform,
-            lfo_intensity: 0.0,
-            transpose: 1.0,
-            bend: 1.0,
-            phase: 0.0,
-            phase_delta: 0.0,
-            last_sample: 0.0
-        }
+            inputs: vec![LFO],
+            lfo_intensity: Smoothed::new(S::zero(), Linear)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
+            fm_depth: Smoothed::new(S::zero(), Linear)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
+            pm_depth: Smoothed::new(S::zero(), Linear)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
+            pitch_cv_depth: Smoothed::new(S::one(), Linear)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
+            transpose: Smoothed::new(S::one(), Exponential)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
+            bend: Smoothed::new(S::one(), Exponential)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
+            phase: S::zero(),
+            phase_delta: S::zero(),
+            last_sample: S::zero()
+        };
+        osc.update_phase_delta();
+        osc
     }
 
     /// Sets the frequency of the waveform, and return the same oscillator.
     pub fn freq(mut self, freq: f32) -> Self {
         self.handle_message(SetFreq(freq));
+        self.finish_ramps();
         self
     }
 
@@ -125,6 +265,7 @@ impl Oscillator {
     /// oscillator.
     pub fn transpose(mut self, steps: f32) -> Self {
         self.handle_message(SetTranspose(steps));
+        self.finish_ramps();
         self
     }
 
@@ -133,80 +274,285 @@ impl Oscillator {
     /// The intensity is provided in half steps (1/2ths of an octave).
     pub fn lfo_intensity(mut self, lfo_intensity: f32) -> Self {
         self.handle_message(SetLFOIntensity(lfo_intensity));
+        self.finish_ramps();
+        self
+    }
+
+    /// Sets the role of each input, and return the same oscillator. The
+    /// oscillator takes one input for each role provided.
+    ///
+    /// By default, the oscillator has a single `LFO` input.
+    pub fn inputs(mut self, inputs: &[Input]) -> Self {
+        self.inputs = inputs.to_vec();
+        self
+    }
+
+    /// Sets the time taken to ramp to new values (in seconds), and return the
+    /// same oscillator.
+    ///
+    /// A ramp time of zero disables smoothing.
+    pub fn ramp_time(mut self, seconds: f32) -> Self {
+        self.handle_message(SetRampTime(seconds));
         self
     }
+
+    /// Sets the time taken to glide to a new frequency (in seconds), and
+    /// return the same oscillator.
+    pub fn glide_time(mut self, seconds: f32) -> Self {
+        self.handle_message(SetGlideTime(seconds));
+        self
+    }
+
+    /// Jumps straight to the target of every ramp.
+    fn finish_ramps(&mut self) {
+        self.freq.finish();
+        self.lfo_intensity.finish();
+        self.fm_depth.finish();
+        self.pm_depth.finish();
+        self.pitch_cv_depth.finish();
+        self.transpose.finish();
+        self.bend.finish();
+        self.update_phase_delta();
+    }
+
+    /// Recomputes the phase delta from the current frequency.
+    fn update_phase_delta(&mut self) {
+        self.phase_delta = S::from_f64(
+            self.freq.get_value() as f64*2.0*PI/(self.sample_rate as f64));
+    }
+
+    /// Advances the phase by one time step and computes the next sample.
+    ///
+    /// `inputs` holds one frame of the oscillator's inputs, or is empty if no
+    /// inputs are provided.
+    fn next_sample(&mut self, inputs: &[S]) -> S {
+        let one = S::one();
+        let two = S::from_f32(2.0);
+        let pi = S::from_f64(PI);
+
+        // Advance any ramps
+        if self.freq.is_ramping() {
+            self.freq.tick();
+            self.update_phase_delta();
+        }
+        let lfo_intensity = self.lfo_intensity.tick();
+        let fm_depth = self.fm_depth.tick();
+        let pm_depth = self.pm_depth.tick();
+        let pitch_cv_depth = self.pitch_cv_depth.tick();
+        let bend = self.bend.tick();
+        let transpose = self.transpose.tick();
+
+        // Sum the modulation inputs by role
+        let (mut octaves, mut fm, mut pm) = (S::zero(), S::zero(), S::zero());
+        for (&input, &x) in self.inputs.iter().zip(inputs) {
+            match input {
+                LFO => octaves = octaves + x*lfo_intensity,
+                LinearFM => fm = fm + x*fm_depth,
+                PhaseMod => pm = pm + x*pm_depth,
+                PitchCV => octaves = octaves + x*pitch_cv_depth
+            }
+        }
+
+        // Tick the phase
+        let mut phase_delta = self.phase_delta * bend * transpose;
+        if octaves != S::zero() {
+            phase_delta = phase_delta * two.powf(octaves);
+        }
+        if fm != S::zero() {
+            phase_delta = phase_delta +
+                fm*two*pi/S::from_f32(self.sample_rate as f32);
+        }
+        // Modulation may step by more than a whole period in either direction
+        self.phase = self.phase + phase_delta;
+        self.phase = self.phase - two*pi*(self.phase/(two*pi)).floor();
+
+        // Read the waveform at the modulated phase. Running backwards only
+        // flips the direction of each step, so antialias using its size.
+        let phase = if pm != S::zero() {
+            let phase = (self.phase + pm) % (two*pi);
+            if phase < S::zero() { phase + two*pi } else { phase }
+        } else {
+            self.phase
+        };
+        let phase_delta = phase_delta.abs();
+
+        // Compute the next sample
+        self.last_sample = match self.waveform {
+            Sine => phase.sin(),
+            Saw(_) => {
+                phase/pi - one + poly_blep(self.waveform, phase, phase_delta)
+            },
+            Square(_) => {
+                (if phase < pi { one } else { -one }) +
+                    poly_blep(self.waveform, phase, phase_delta)
+            },
+            Tri(_) => {
+                // Compute a square wave signal
+                let out = (if phase < pi { one } else { -one }) +
+                    poly_blep(self.waveform, phase, phase_delta);
+
+                // Perform leaky integration
+                phase_delta*out + (one-phase_delta)*self.last_sample
+            },
+            WhiteNoise => S::from_f32(2.0*random::<f32>() - 1.0),
+            PulseTrain => {
+                // If we wrapped around...
+                if phase < phase_delta { one } else { S::zero() }
+            }
+        };
+        self.last_sample
+    }
 }
 
-impl MessageReceiver for Oscillator {
+impl<S: SampleFloat> MessageReceiver for Oscillator<S> {
     type Msg = Message;
     fn handle_message(&mut self, msg: Message) {
         match msg {
             SetFreq(freq) => {
-                self.phase_delta = freq*2.0*PI/(SAMPLE_RATE as f32);
+                self.freq.set_target(freq);
+                self.update_phase_delta();
             },
             SetWaveform(waveform) => {
                 self.waveform = waveform;
             },
             SetLFOIntensity(steps) => {
-                self.lfo_intensity = steps/12.0;
+                self.lfo_intensity.set_target(S::from_f32(steps/12.0));
             },
             SetTranspose(steps) => {
-                self.transpose = 2.0.powf(steps/12.0);
+                self.transpose.set_target(S::from_f32(2.0.powf(steps/12.0)));
             },
             SetBend(steps) => {
-                self.bend = 2.0.powf(steps/12.0);
+                self.bend.set_target(S::from_f32(2.0.powf(steps/12.0)));
+            },
+            SetFMDepth(depth) => {
+                self.fm_depth.set_target(S::from_f32(depth));
+            },
+            SetPMDepth(depth) => {
+                self.pm_depth.set_target(S::from_f32(depth));
+            },
+            SetPitchCVDepth(steps) => {
+                self.pitch_cv_depth.set_target(S::from_f32(steps/12.0));
+            },
+            SetRampTime(seconds) => {
+                self.lfo_intensity.set_ramp_time(seconds);
+                self.fm_depth.set_ramp_time(seconds);
+                self.pm_depth.set_ramp_time(seconds);
+                self.pitch_cv_depth.set_ramp_time(seconds);
+                self.transpose.set_ramp_time(seconds);
+                self.bend.set_ramp_time(seconds);
+            },
+            SetGlideTime(seconds) => {
+                self.freq.set_ramp_time(seconds);
             },
         }
     }
 }
 
-impl AudioDevice for Oscillator {
+impl<S: SampleFloat> Parameters for Oscillator<S> {
+    fn num_parameters(&self) -> usize {
+        8
+    }
+
+    fn get_parameter_info(&self, i: usize) -> ParameterInfo {
+        match i {
+            0 => ParameterInfo::new("frequency", 1.0, 20000.0, DEFAULT_FREQ)
+                .unit(Hertz).scale(Logarithmic),
+            1 => ParameterInfo::choice("waveform", &WAVEFORM_NAMES, 0),
+            2 => ParameterInfo::new("lfo intensity", 0.0, 12.0, 0.0)
+                .unit(Steps),
+            3 => ParameterInfo::new("transpose", -24.0, 24.0, 0.0).unit(Steps),
+            4 => ParameterInfo::new("bend", -12.0, 12.0, 0.0).unit(Steps),
+            5 => ParameterInfo::new("fm depth", 0.0, 20000.0, 0.0)
+                .unit(Hertz),
+            6 => ParameterInfo::new("pm depth", 0.0, 4.0*PI as f32, 0.0),
+            7 => ParameterInfo::new("pitch cv depth", -48.0, 48.0, 12.0)
+                .unit(Steps),
+            _ => panic!("Oscillator: parameter index {} out of range", i)
+        }
+    }
+
+    fn get_parameter(&self, i: usize) -> f32 {
+        match i {
+            0 => self.freq.get_target(),
+            1 => waveform_to_index(self.waveform) as f32,
+            2 => 12.0 * self.lfo_intensity.get_target().into_f32(),
+            3 => 12.0 * self.transpose.get_target().into_f32().log2(),
+            4 => 12.0 * self.bend.get_target().into_f32().log2(),
+            5 => self.fm_depth.get_target().into_f32(),
+            6 => self.pm_depth.get_target().into_f32(),
+            7 => 12.0 * self.pitch_cv_depth.get_target().into_f32(),
+            _ => panic!("Oscillator: parameter index {} out of range", i)
+        }
+    }
+
+    fn set_parameter(&mut self, i: usize, value: f32) {
+        let value = self.get_parameter_info(i).clamp(value);
+        let msg = match i {
+            0 => SetFreq(value),
+            1 => SetWaveform(waveform_from_index(value as usize)),
+            2 => SetLFOIntensity(value),
+            3 => SetTranspose(value),
+            4 => SetBend(value),
+            5 => SetFMDepth(value),
+            6 => SetPMDepth(value),
+            _ => SetPitchCVDepth(value)
+        };
+        self.handle_message(msg);
+    }
+}
+
+impl<S: SampleFloat> AudioDevice<S> for Oscillator<S> {
     fn num_inputs(&self) -> usize {
-        1
+        self.inputs.len()
     }
 
     fn num_outputs(&self) -> usize {
         1
     }
 
-    fn tick(&mut self, _: Time, inputs: &[Sample], outputs: &mut[Sample]) {
-        // Tick the phase
-        let phase_delta = if inputs.len() > 0 {
-            self.phase_delta*2.0.powf(inputs[0]*self.lfo_intensity)
-        } else {
-            self.phase_delta
-        } * self.bend * self.transpose;
-        self.phase += phase_delta;
-        if self.phase >= 2.0*PI {
-            self.phase -= 2.0*PI;
-        }
+    fn parameters(&self) -> Option<&Parameters> {
+        Some(self)
+    }
 
-        // Compute the next sample
-        self.last_sample = match self.waveform {
-            Sine => self.phase.sin(),
-            Saw(_) => {
-                self.phase/PI -1.0 +
-                    poly_blep(self.waveform, self.phase, phase_delta)
-            },
-            Square(_) => {
-                (if self.phase < PI { 1.0 } else { -1.0 }) +
-                    poly_blep(self.waveform, self.phase, phase_delta)
-            },
-            Tri(_) => {
-                // Compute a square wave signal
-                let out = (if self.phase < PI { 1.0 } else { -1.0 }) +
-                    poly_blep(self.waveform, self.phase, phase_delta);
+    fn parameters_mut(&mut self) -> Option<&mut Parameters> {
+        Some(self)
+    }
 
-                // Perform leaky integration
-                phase_delta*out + (1.0-phase_delta)*self.last_sample
-            },
-            WhiteNoise => 2.0*random::<f32>() - 1.0,
-            PulseTrain => {
-                // If we wrapped around...
-                if self.phase < self.phase_delta { 1.0 } else { 0.0 }
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.freq.set_sample_rate(sample_rate);
+        self.lfo_intensity.set_sample_rate(sample_rate);
+        self.fm_depth.set_sample_rate(sample_rate);
+        self.pm_depth.set_sample_rate(sample_rate);
+        self.pitch_cv_depth.set_sample_rate(sample_rate);
+        self.transpose.set_sample_rate(sample_rate);
+        self.bend.set_sample_rate(sample_rate);
+        self.update_phase_delta();
+    }
+
+    fn reset(&mut self) {
+        self.phase = S::zero();
+        self.last_sample = S::zero();
+        self.finish_ramps();
+    }
+
+    fn tick(&mut self, _: Time, inputs: &[S], outputs: &mut[S]) {
+        outputs[0] = self.next_sample(inputs);
+    }
+
+    fn tick_block(&mut self, _: Time, frames: usize, inputs: &[S],
+                  outputs: &mut[S]) {
+        let n = self.inputs.len();
+        if n == 0 || inputs.len() < frames*n {
+            for s in outputs[..frames].iter_mut() {
+                *s = self.next_sample(&[]);
             }
-        };
-        outputs[0] = self.last_sample;
+        } else {
+            for (s, frame) in outputs[..frames].iter_mut()
+                                               .zip(inputs.chunks(n)) {
+                *s = self.next_sample(frame);
+            }
+        }
     }
 }
 
@@ -217,17 +563,20 @@ impl AudioDevice for Oscillator {
 /// `waveform` should be the waveform we are antialiasing.
 /// `phase` should be the current phase, from 0 to 2pi.
 /// `phase_delta` should be the change in phase for one tick.
-fn poly_blep(waveform: Waveform, phase: f32, phase_delta: f32) -> Sample {
+fn poly_blep<S: SampleFloat>(waveform: Waveform, phase: S, phase_delta: S)
+        -> S {
+    let two_pi = S::from_f64(2.0*PI);
     match waveform {
         Saw(PolyBlep) => {
-            -1.0*poly_blep_offset(phase/(2.0*PI), phase_delta/(2.0*PI))
+            -poly_blep_offset(phase/two_pi, phase_delta/two_pi)
         },
         Square(PolyBlep) | Tri(PolyBlep) => {
-            let t = phase/(2.0*PI);
-            let dt = phase_delta/(2.0*PI);
-            poly_blep_offset(t, dt) - poly_blep_offset((t+0.5) % 1.0, dt)
+            let t = phase/two_pi;
+            let dt = phase_delta/two_pi;
+            let half = S::from_f32(0.5);
+            poly_blep_offset(t, dt) - poly_blep_offset((t+half) % S::one(), dt)
         },
-        _ => 0.0
+        _ => S::zero()
     }
 }
 
@@ -235,23 +584,28 @@ fn poly_blep(waveform: Waveform, phase: f32, phase_delta: f32) -> Sample {
 ///
 /// `t` should be the current waveform phase, normalized.
 /// `dt` should be the change in phase for one sample time, normalized.
-fn poly_blep_offset(t: f32, dt: f32) -> f32 {
+fn poly_blep_offset<S: SampleFloat>(t: S, dt: S) -> S {
+    let one = S::one();
+    let two = S::from_f32(2.0);
     if t < dt { // t ~= 0
         let t = t / dt;
-        -t*t + 2.0*t - 1.0
-    } else if t > 1.0-dt { // t ~= 1
-        let t = (t-1.0) / dt;
-        t*t + 2.0*t + 1.0
+        -t*t + two*t - one
+    } else if t > one-dt { // t ~= 1
+        let t = (t-one) / dt;
+        t*t + two*t + one
     } else {
-        0.0
+        S::zero()
     }
 }
 
 
 #[cfg(test)]
 mod test {
-    use testing::flt_eq;
-    use super::{Aliased, Oscillator, Waveform};
+    use testing::{flt_eq, flt_eq_eps};
+    use parameters::Parameters;
+    use types::MessageReceiver;
+    use super::{Aliased, Oscillator, SetFreq, SetGlideTime, Waveform};
+    use super::{LinearFM, PhaseMod, PitchCV, SetFMDepth, SetPMDepth};
     use types::{AudioDevice, Sample, Time};
 
     const FREQ: f32 = 4410.0;
@@ -286,10 +640,139 @@ mod test {
               &[-0.8, -0.6, -0.4, -0.2, 0.0, 0.2, 0.4, 0.6, 0.8, -1.0]);
     }
 
+    #[test]
+    fn test_block() {
+        let mut osc = Oscillator::new(Waveform::Saw(Aliased)).freq(FREQ);
+        let mut output = [0.0; 10];
+        osc.tick_block(0, 10, &[], &mut output);
+        check(&output, &[-0.8, -0.6, -0.4, -0.2, 0.0, 0.2, 0.4, 0.6, 0.8, -1.0]);
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let mut osc = Oscillator::new(Waveform::Saw(Aliased)).freq(FREQ/5.0);
+        osc.set_sample_rate(8820);
+        check(&get_one_cycle(&mut osc),
+              &[-0.8, -0.6, -0.4, -0.2, 0.0, 0.2, 0.4, 0.6, 0.8, -1.0]);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut osc = Oscillator::new(Waveform::Saw(Aliased)).freq(FREQ);
+        get_one_cycle(&mut osc);
+        osc.tick(10, &[], &mut [0.0]);
+        osc.reset();
+        check(&get_one_cycle(&mut osc),
+              &[-0.8, -0.6, -0.4, -0.2, 0.0, 0.2, 0.4, 0.6, 0.8, -1.0]);
+    }
+
+    #[test]
+    fn test_parameters() {
+        let mut osc = Oscillator::new(Waveform::Sine);
+        for i in 0..osc.num_parameters() {
+            let info = osc.get_parameter_info(i);
+            assert_eq!(osc.get_parameter(i), info.default);
+        }
+
+        let i = osc.find_parameter("waveform").unwrap();
+        osc.set_parameter(i, 2.0);
+        assert_eq!(osc.get_parameter(i), 2.0);
+        osc.set_parameter(osc.find_parameter("frequency").unwrap(), FREQ);
+        check(&get_one_cycle(&mut osc),
+              &[-0.8, -0.6, -0.4, -0.2, 0.0, 0.2, 0.4, 0.6, 0.8, -1.0]);
+
+        let i = osc.find_parameter("transpose").unwrap();
+        osc.set_parameter(i, 7.0);
+        assert!(flt_eq_eps(osc.get_parameter(i), 7.0, 1e-4));
+    }
+
+    #[test]
+    fn test_glide() {
+        // By default, the frequency jumps
+        let mut osc = Oscillator::new(Waveform::Saw(Aliased)).freq(FREQ);
+        get_one_cycle(&mut osc);
+        osc.handle_message(SetFreq(2.0*FREQ));
+        let mut output = [0.0];
+        osc.tick(10, &[], &mut output);
+        assert!(flt_eq(output[0], -0.6));
+
+        let mut osc = Oscillator::new(Waveform::Saw(Aliased)).freq(FREQ)
+            .glide_time(0.01);
+        get_one_cycle(&mut osc);
+        osc.handle_message(SetFreq(2.0*FREQ));
+        osc.tick(10, &[], &mut output);
+        assert!(output[0] > -0.8 && output[0] < -0.7);
+
+        osc.reset();
+        osc.handle_message(SetGlideTime(0.0));
+        osc.handle_message(SetFreq(FREQ));
+        check(&get_one_cycle(&mut osc),
+              &[-0.8, -0.6, -0.4, -0.2, 0.0, 0.2, 0.4, 0.6, 0.8, -1.0]);
+    }
+
     #[test]
     fn test_pulse() {
         let mut osc = Oscillator::new(Waveform::PulseTrain).freq(FREQ);
         check(&get_one_cycle(&mut osc),
               &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
     }
+
+    #[test]
+    fn test_inputs() {
+        let osc = Oscillator::new(Waveform::Sine);
+        assert_eq!(osc.num_inputs(), 1);
+        let osc = osc.inputs(&[LinearFM, PhaseMod, PitchCV]);
+        assert_eq!(osc.num_inputs(), 3);
+    }
+
+    #[test]
+    fn test_linear_fm() {
+        // Push the frequency through zero, so the saw runs backwards
+        let mut osc = Oscillator::new(Waveform::Saw(Aliased)).freq(FREQ)
+            .inputs(&[LinearFM]);
+        osc.handle_message(SetFMDepth(2.0*FREQ));
+        osc.reset();
+        let mut output = [0.0; 4];
+        osc.tick_block(0, 4, &[-1.0; 4], &mut output);
+        check(&output, &[0.8, 0.6, 0.4, 0.2]);
+    }
+
+    #[test]
+    fn test_wide_fm() {
+        // Each step covers more than one period, forwards then backwards
+        let mut osc = Oscillator::new(Waveform::Saw(Aliased)).freq(20000.0)
+            .inputs(&[LinearFM]);
+        osc.handle_message(SetFMDepth(20000.0));
+        osc.reset();
+        let mut output = [0.0; 64];
+        for &x in [3.0, -3.0].iter() {
+            osc.tick_block(0, 64, &[x; 64], &mut output);
+            assert!(output.iter().all(|s| (-1.0..=1.0).contains(s)));
+        }
+    }
+
+    #[test]
+    fn test_phase_mod() {
+        let mut osc = Oscillator::new(Waveform::Saw(Aliased)).freq(FREQ)
+            .inputs(&[PhaseMod]);
+        osc.handle_message(SetPMDepth(::std::f32::consts::PI));
+        osc.reset();
+        let mut output = [0.0; 10];
+        for t in 0..10 {
+            osc.tick(t as Time, &[1.0], &mut output[t..t+1]);
+        }
+        check(&output, &[0.2, 0.4, 0.6, 0.8, -1.0, -0.8, -0.6, -0.4, -0.2,
+                         0.0]);
+    }
+
+    #[test]
+    fn test_pitch_cv() {
+        // One unit of input raises the pitch by an octave
+        let mut osc = Oscillator::new(Waveform::Saw(Aliased)).freq(FREQ/2.0)
+            .inputs(&[PitchCV]);
+        let mut output = [0.0; 10];
+        osc.tick_block(0, 10, &[1.0; 10], &mut output);
+        check(&output, &[-0.8, -0.6, -0.4, -0.2, 0.0, 0.2, 0.4, 0.6, 0.8,
+                         -1.0]);
+    }
 }
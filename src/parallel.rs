@@ -0,0 +1,542 @@
+//! A device that runs several chains side by side.
+//!
+//! Many effect layouts split a signal into several paths and then mix them
+//! back together, such as send effects, or multiband processing. A `Parallel`
+//! block covers these layouts without building a full `DeviceGraph`: it feeds
+//! its input into every branch, each branch being a `DeviceChain`, and then
+//! merges the outputs of the branches.
+//!
+//! The outputs of the branches are either summed into a single set of
+//! channels, or concatenated, so that each branch gets its own channels. Each
+//! branch has its own gain, and a dry/wet mix blends the merged outputs with
+//! the unprocessed input.
+//!
+//! A parallel block is itself an `AudioDevice`, so it can be added to a chain
+//! or graph like any other device.
+//!
+//! # Example
+//!
+//! The following splits a signal into low and high bands, and turns the high
+//! band down:
+//!
+//! ```
+//! use oxcable::chain::DeviceChain;
+//! use oxcable::filters::second_order::{Filter, HighPass, LowPass};
+//! use oxcable::parallel::Parallel;
+//!
+//! let bands: Parallel = Parallel::sum(1, 1)
+//!     .branch(DeviceChain::from(Filter::new(LowPass(500.0), 1)))
+//!     .branch_with_gain(DeviceChain::from(Filter::new(HighPass(500.0), 1)),
+//!                       -6.0);
+//! assert_eq!(bands.num_branches(), 2);
+//! ```
+
+use chain::DeviceChain;
+use parameters::{Decibels, ParameterInfo, Parameters};
+use transport::TransportHandle;
+use types::{SAMPLE_RATE, AudioDevice, MessageReceiver, Sample, SampleFloat,
+            Time};
+use utils::helpers::decibel_to_ratio;
+use utils::ringbuffer::RingBuffer;
+use utils::smoothed::{DEFAULT_RAMP_TIME, Exponential, Linear, Smoothed};
+
+
+/// The most branches a parallel block can hold.
+pub const MAX_BRANCHES: usize = 16;
+
+/// The name of the gain parameter of each branch.
+static BRANCH_GAIN_NAMES: [&str; MAX_BRANCHES] = [
+    "branch 1 gain", "branch 2 gain", "branch 3 gain", "branch 4 gain",
+    "branch 5 gain", "branch 6 gain", "branch 7 gain", "branch 8 gain",
+    "branch 9 gain", "branch 10 gain", "branch 11 gain", "branch 12 gain",
+    "branch 13 gain", "branch 14 gain", "branch 15 gain", "branch 16 gain"
+];
+
+
+/// Defines the messages that the Parallel supports.
+#[derive(Clone, Copy, Debug)]
+pub enum Message {
+    /// Sets the gain of a branch, by the branch's index and the gain in
+    /// decibels. Messages for missing branches are ignored.
+    SetBranchGain(usize, f32),
+    /// Sets the dry/wet mix, from 0 for only the input, to 1 for only the
+    /// branches.
+    SetMix(f32),
+}
+pub use self::Message::*;
+
+
+/// Defines how the outputs of the branches are merged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Merge {
+    /// Every branch has the same outputs, which are summed together.
+    Sum,
+    /// The outputs of each branch follow those of the branch before it.
+    Concatenate,
+}
+pub use self::Merge::*;
+
+
+/// A block of chains that process the same input side by side.
+///
+/// Each output channel `j` of the block is mixed with input channel `j` as
+/// the dry signal. Output channels without a matching input have no dry
+/// signal.
+///
+/// The latency of the block is that of its slowest branch. The other
+/// branches and the dry signal are delayed to line up with it.
+///
+/// Parameter 0 is the dry/wet mix, and parameter `i+1` is the gain of the
+/// `i`th branch.
+pub struct Parallel<S: SampleFloat = Sample> {
+    num_inputs: usize,
+    num_outputs: usize,
+    merge: Merge,
+    branches: Vec<Branch<S>>,
+    mix: Smoothed<S>,
+    buffer: Vec<S>, // the outputs of a single branch
+    dry_delay: DelayLines<S>, // lines up the dry signal with the branches
+    sample_rate: u32,
+    block_size: usize
+}
+
+impl<S: SampleFloat> Parallel<S> {
+    /// Returns a new block with `num_inputs` inputs, that sums its branches
+    /// into `num_outputs` outputs.
+    pub fn sum(num_inputs: usize, num_outputs: usize) -> Self {
+        Parallel::new(num_inputs, num_outputs, Sum)
+    }
+
+    /// Returns a new block with `num_inputs` inputs, that concatenates the
+    /// outputs of its branches. The block starts with no outputs, and gains
+    /// the outputs of each branch as it is added.
+    pub fn concatenate(num_inputs: usize) -> Self {
+        Parallel::new(num_inputs, 0, Concatenate)
+    }
+
+    /// Returns a new block with no branches.
+    fn new(num_inputs: usize, num_outputs: usize, merge: Merge) -> Self {
+        Parallel {
+            num_inputs: num_inputs,
+            num_outputs: num_outputs,
+            merge: merge,
+            branches: Vec::new(),
+            mix: Smoothed::new(S::one(), Linear)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
+            buffer: Vec::new(),
+            dry_delay: DelayLines::new(num_inputs),
+            sample_rate: SAMPLE_RATE,
+            block_size: 1
+        }
+    }
+
+    /// Adds a branch to the block, then returns the same block.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block already has `MAX_BRANCHES` branches, or if the
+    /// chain does not have as many inputs as the block, or, if the branches
+    /// are summed, as many outputs.
+    pub fn branch(self, chain: DeviceChain<S>) -> Self {
+        self.branch_with_gain(chain, 0.0)
+    }
+
+    /// Adds a branch to the block with a gain in decibels, then returns the
+    /// same block.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block already has `MAX_BRANCHES` branches, or if the
+    /// chain does not have as many inputs as the block, or, if the branches
+    /// are summed, as many outputs.
+    pub fn branch_with_gain(mut self, mut chain: DeviceChain<S>, gain: f32)
+            -> Self {
+        if self.branches.len() == MAX_BRANCHES {
+            panic!("Parallel: block already has {} branches", MAX_BRANCHES);
+        }
+        if AudioDevice::<S>::num_inputs(&chain) != self.num_inputs {
+            panic!("Parallel: branch must have as many inputs as the block");
+        }
+        let num_out = AudioDevice::<S>::num_outputs(&chain);
+        match self.merge {
+            Sum if num_out != self.num_outputs =>
+                panic!("Parallel: branch must have as many outputs as the \
+                        block"),
+            Sum => (),
+            Concatenate => self.num_outputs += num_out
+        }
+
+        let sample_rate = self.sample_rate;
+        chain.prepare(sample_rate, self.block_size);
+        self.branches.push(Branch {
+            delay: DelayLines::new(num_out),
+            chain: chain,
+            gain_db: gain,
+            gain: Smoothed::new(S::from_f32(decibel_to_ratio(gain)),
+                                Exponential)
+                .ramp_time(DEFAULT_RAMP_TIME, sample_rate)
+        });
+        self.resize_buffer();
+        self.update_delays();
+        self
+    }
+
+    /// Returns the number of branches in the block.
+    pub fn num_branches(&self) -> usize {
+        self.branches.len()
+    }
+
+    /// Returns the chain of the `i`th branch, or `None` if it doesn't exist.
+    pub fn get_branch(&self, i: usize) -> Option<&DeviceChain<S>> {
+        self.branches.get(i).map(|branch| &branch.chain)
+    }
+
+    /// Returns the chain of the `i`th branch mutably, or `None` if it doesn't
+    /// exist.
+    ///
+    /// The number of channels of the chain must not be changed.
+    pub fn get_branch_mut(&mut self, i: usize)
+            -> Option<&mut DeviceChain<S>> {
+        self.branches.get_mut(i).map(|branch| &mut branch.chain)
+    }
+
+    /// Reallocates the branch buffer to hold a block of the widest branch.
+    fn resize_buffer(&mut self) {
+        let channels = self.branches.iter()
+            .map(|branch| AudioDevice::<S>::num_outputs(&branch.chain))
+            .max().unwrap_or(0);
+        self.buffer = vec![S::zero(); channels*self.block_size];
+    }
+
+    /// Sets the delay of every branch and of the dry signal, so that they all
+    /// line up with the slowest branch.
+    fn update_delays(&mut self) {
+        let latency = self.latency();
+        self.dry_delay.set_delay(latency);
+        for branch in self.branches.iter_mut() {
+            branch.delay.set_delay(latency - branch.chain.latency());
+        }
+    }
+}
+
+impl<S: SampleFloat> MessageReceiver for Parallel<S> {
+    type Msg = Message;
+    fn handle_message(&mut self, msg: Message) {
+        match msg {
+            SetBranchGain(i, gain) => {
+                if let Some(branch) = self.branches.get_mut(i) {
+                    branch.gain_db = gain;
+                    branch.gain.set_target(
+                        S::from_f32(decibel_to_ratio(gain)));
+                }
+            },
+            SetMix(mix) => {
+                self.mix.set_target(S::from_f32(mix));
+            }
+        }
+    }
+}
+
+impl<S: SampleFloat> Parameters for Parallel<S> {
+    fn num_parameters(&self) -> usize {
+        1 + self.branches.len()
+    }
+
+    fn get_parameter_info(&self, i: usize) -> ParameterInfo {
+        match i {
+            0 => ParameterInfo::new("mix", 0.0, 1.0, 1.0),
+            _ if i <= self.branches.len() =>
+                ParameterInfo::new(BRANCH_GAIN_NAMES[i-1], -60.0, 24.0, 0.0)
+                    .unit(Decibels),
+            _ => panic!("Parallel: parameter index {} out of range", i)
+        }
+    }
+
+    fn get_parameter(&self, i: usize) -> f32 {
+        match i {
+            0 => self.mix.get_target().into_f32(),
+            _ if i <= self.branches.len() => self.branches[i-1].gain_db,
+            _ => panic!("Parallel: parameter index {} out of range", i)
+        }
+    }
+
+    fn set_parameter(&mut self, i: usize, value: f32) {
+        let value = self.get_parameter_info(i).clamp(value);
+        let msg = match i {
+            0 => SetMix(value),
+            _ => SetBranchGain(i-1, value)
+        };
+        self.handle_message(msg);
+    }
+}
+
+impl<S: SampleFloat> AudioDevice<S> for Parallel<S> {
+    fn num_inputs(&self) -> usize {
+        self.num_inputs
+    }
+
+    fn num_outputs(&self) -> usize {
+        self.num_outputs
+    }
+
+    fn parameters(&self) -> Option<&Parameters> {
+        Some(self)
+    }
+
+    fn parameters_mut(&mut self) -> Option<&mut Parameters> {
+        Some(self)
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        let block_size = self.block_size;
+        self.prepare(sample_rate, block_size);
+    }
+
+    fn prepare(&mut self, sample_rate: u32, block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.block_size = block_size;
+        self.mix.set_sample_rate(sample_rate);
+        for branch in self.branches.iter_mut() {
+            branch.chain.prepare(sample_rate, block_size);
+            branch.gain.set_sample_rate(sample_rate);
+        }
+        self.resize_buffer();
+        self.update_delays();
+    }
+
+    fn reset(&mut self) {
+        self.mix.finish();
+        self.dry_delay.clear();
+        for branch in self.branches.iter_mut() {
+            branch.chain.reset();
+            branch.gain.finish();
+            branch.delay.clear();
+        }
+    }
+
+    fn latency(&self) -> usize {
+        self.branches.iter().map(|branch| branch.chain.latency())
+            .max().unwrap_or(0)
+    }
+
+    fn set_transport(&mut self, transport: TransportHandle) {
+        for branch in self.branches.iter_mut() {
+            AudioDevice::<S>::set_transport(&mut branch.chain,
+                                            transport.clone());
+        }
+    }
+
+    fn tick(&mut self, t: Time, inputs: &[S], outputs: &mut[S]) {
+        self.tick_block(t, 1, inputs, outputs);
+    }
+
+    fn tick_block(&mut self, t: Time, frames: usize, inputs: &[S],
+                  outputs: &mut[S]) {
+        let (num_in, num_out) = (self.num_inputs, self.num_outputs);
+        // The latency of a branch changes if its devices are changed
+        self.update_delays();
+        for y in outputs[..frames*num_out].iter_mut() {
+            *y = S::zero();
+        }
+
+        // Process each branch, then add it into its output channels
+        let mut start = 0;
+        for branch in self.branches.iter_mut() {
+            let n = AudioDevice::<S>::num_outputs(&branch.chain);
+            branch.chain.tick_block(t, frames, inputs,
+                                    &mut self.buffer[..frames*n]);
+            for f in 0..frames {
+                let gain = branch.gain.tick();
+                let out = &mut outputs[f*num_out+start..f*num_out+start+n];
+                let buf = &self.buffer[f*n..(f+1)*n];
+                for (ch, (y, &x)) in out.iter_mut().zip(buf).enumerate() {
+                    *y = *y + gain*branch.delay.read(ch, x);
+                }
+            }
+            if self.merge == Concatenate {
+                start += n;
+            }
+        }
+
+        // Blend in the dry signal
+        for f in 0..frames {
+            let mix = self.mix.tick();
+            for j in 0..num_out {
+                let dry = if j < num_in {
+                    self.dry_delay.read(j, inputs[f*num_in + j])
+                } else {
+                    S::zero()
+                };
+                let y = &mut outputs[f*num_out + j];
+                *y = dry + mix*(*y - dry);
+            }
+        }
+    }
+}
+
+
+/// A branch of a parallel block.
+struct Branch<S: SampleFloat> {
+    chain: DeviceChain<S>, // the devices of the branch
+    gain_db: f32, // the gain of the branch, in decibels
+    gain: Smoothed<S>, // the gain of the branch, as a ratio
+    delay: DelayLines<S> // lines up the branch with the slowest branch
+}
+
+
+/// Delays each of a set of channels by the same number of samples.
+struct DelayLines<S: SampleFloat> {
+    delay: usize,
+    lines: Vec<RingBuffer<S>>
+}
+
+impl<S: SampleFloat> DelayLines<S> {
+    /// Returns undelayed lines for `channels` channels.
+    fn new(channels: usize) -> Self {
+        DelayLines {
+            delay: 0,
+            lines: (0..channels).map(|_| RingBuffer::new(0)).collect()
+        }
+    }
+
+    /// Sets the delay in samples. The lines are zeroed if it changes.
+    fn set_delay(&mut self, delay: usize) {
+        if delay != self.delay {
+            self.delay = delay;
+            self.clear();
+        }
+    }
+
+    /// Zeroes the contents of every line.
+    fn clear(&mut self) {
+        let delay = self.delay;
+        for line in self.lines.iter_mut() {
+            *line = RingBuffer::from(&vec![S::zero(); delay][..]);
+        }
+    }
+
+    /// Passes the next sample of channel `ch` through its line.
+    fn read(&mut self, ch: usize, x: S) -> S {
+        if self.delay == 0 {
+            return x;
+        }
+        // The oldest sample is exactly `delay` samples old
+        let line = &mut self.lines[ch];
+        let (oldest, _) = line.times();
+        let y = line[oldest];
+        line.push(x);
+        y
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use chain::{DeviceChain, Tick};
+    use mixers::Gain;
+    use parameters::Parameters;
+    use testing::MockAudioDevice;
+    use types::{AudioDevice, MessageReceiver};
+    use super::{Parallel, SetBranchGain, SetMix};
+
+    #[test]
+    fn test_sum() {
+        let mut mock1 = MockAudioDevice::new("mock1", 1, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 1);
+        mock1.will_tick(&[1.0], &[2.0]).will_tick(&[1.0], &[2.0]);
+        mock2.will_tick(&[1.0], &[3.0]).will_tick(&[1.0], &[3.0]);
+
+        let mut parallel = Parallel::sum(1, 1)
+            .branch(DeviceChain::from(mock1))
+            .branch_with_gain(DeviceChain::from(mock2), -20.0);
+        parallel.reset();
+        let mut output = [0.0];
+        parallel.tick(0, &[1.0], &mut output);
+        assert!((output[0] - 2.03).abs() < 1e-6);
+
+        parallel.handle_message(SetBranchGain(1, 0.0));
+        parallel.reset();
+        parallel.tick(1, &[1.0], &mut output);
+        assert_eq!(output, [5.0]);
+    }
+
+    #[test]
+    fn test_concatenate() {
+        let mut mock1 = MockAudioDevice::new("mock1", 1, 1);
+        let mut mock2 = MockAudioDevice::new("mock2", 1, 2);
+        mock1.will_tick(&[1.0], &[2.0]).will_tick(&[3.0], &[4.0]);
+        mock2.will_tick(&[1.0], &[5.0, 6.0]).will_tick(&[3.0], &[7.0, 8.0]);
+
+        let mut parallel = Parallel::concatenate(1)
+            .branch(DeviceChain::from(mock1))
+            .branch(DeviceChain::from(mock2));
+        assert_eq!(AudioDevice::<f32>::num_outputs(&parallel), 3);
+        parallel.prepare(44100, 2);
+        let mut output = [0.0; 6];
+        parallel.tick_block(0, 2, &[1.0, 3.0], &mut output);
+        assert_eq!(output, [2.0, 5.0, 6.0, 4.0, 7.0, 8.0]);
+    }
+
+    #[test]
+    fn test_mix() {
+        let mut parallel = Parallel::sum(2, 2)
+            .branch(DeviceChain::from(Gain::new(-20.0, 2)));
+        parallel.handle_message(SetMix(0.5));
+        parallel.reset();
+        let mut output = [0.0f32; 2];
+        parallel.tick(0, &[1.0, 2.0], &mut output);
+        assert!((output[0] - 0.505).abs() < 1e-6);
+        assert!((output[1] - 1.01).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut slow = MockAudioDevice::new("slow", 1, 1);
+        slow.will_report_latency(2);
+        for &x in [1.0, 0.0, 0.0].iter() {
+            slow.will_tick(&[x], &[0.0]);
+        }
+
+        let mut parallel = Parallel::sum(1, 1)
+            .branch(DeviceChain::from(slow))
+            .branch_with_gain(DeviceChain::from(Gain::new(0.0, 1)), -20.0);
+        assert_eq!(AudioDevice::<f32>::latency(&parallel), 2);
+        parallel.prepare(44100, 3);
+        parallel.handle_message(SetMix(0.5));
+        parallel.reset();
+
+        // Both the dry signal and the faster branch line up with the slow one
+        let mut output = [0.0; 3];
+        parallel.tick_block(0, 3, &[1.0, 0.0, 0.0], &mut output);
+        assert_eq!(output[..2], [0.0, 0.0]);
+        assert!((output[2] - 0.505).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_parameter_names() {
+        let parallel = Parallel::<f32>::sum(1, 1)
+            .branch(DeviceChain::from(Gain::new(0.0, 1)))
+            .branch(DeviceChain::from(Gain::new(0.0, 1)));
+        assert_eq!(parallel.get_parameter_info(1).name, "branch 1 gain");
+        assert_eq!(parallel.get_parameter_info(2).name, "branch 2 gain");
+    }
+
+    #[test]
+    fn test_in_chain() {
+        let mut source = MockAudioDevice::new("source", 0, 1);
+        let mut mock = MockAudioDevice::new("mock", 1, 1);
+        source.will_tick(&[], &[1.0]);
+        mock.will_tick(&[1.0], &[2.0]);
+
+        let mut chain = DeviceChain::from(source).into(
+            Parallel::sum(1, 1).branch(DeviceChain::from(mock)));
+        Tick::tick(&mut chain);
+        assert_eq!(chain.get_output(), [2.0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_wrong_number_outputs() {
+        Parallel::<f32>::sum(1, 2).branch(DeviceChain::from(Gain::new(0.0, 1)));
+    }
+}
@@ -0,0 +1,235 @@
+//! Named parameters that can be inspected and set generically.
+//!
+//! Each device defines its own message type to change its settings, but those
+//! messages can only be sent by code that knows the device's type. The
+//! `Parameters` trait instead describes a device's settings as a numbered list
+//! of named parameters. Generic hosts and user interfaces can use it to list
+//! every parameter of a device, then build automation, MIDI learn or presets on
+//! top of them.
+//!
+//! Every parameter holds an `f32` value, and describes its range, unit and
+//! default value with a `ParameterInfo`. Parameters can also be read and set as
+//! a normalized value between 0 and 1, which is mapped onto the range
+//! according to the parameter's `Scale`.
+//!
+//! Devices behind an `AudioDevice` trait object expose their parameters through
+//! `AudioDevice::parameters` and `AudioDevice::parameters_mut`.
+//!
+//! # Example
+//!
+//! ```
+//! use oxcable::mixers::Gain;
+//! use oxcable::parameters::Parameters;
+//!
+//! let mut gain = Gain::new(0.0, 1);
+//! let i = gain.find_parameter("gain").unwrap();
+//! let info = gain.get_parameter_info(i);
+//! assert_eq!(gain.get_parameter(i), info.default);
+//!
+//! gain.set_parameter(i, -6.0);
+//! assert_eq!(gain.get_parameter(i), -6.0);
+//! gain.set_normalized(i, 1.0);
+//! assert_eq!(gain.get_parameter(i), info.max);
+//! ```
+
+
+/// The unit a parameter's value is measured in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    /// A plain number.
+    Unitless,
+    /// A frequency, in Hz.
+    Hertz,
+    /// A gain, in decibels.
+    Decibels,
+    /// A pitch interval, in half steps.
+    Steps,
+    /// A channel or other index.
+    Index,
+}
+pub use self::Unit::*;
+
+
+/// How a normalized value is mapped onto a parameter's range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scale {
+    /// The value changes linearly from the minimum to the maximum.
+    Linear,
+    /// The value changes exponentially from the minimum to the maximum. This
+    /// suits frequencies and other ratios. The minimum must be positive.
+    Logarithmic,
+    /// The value only takes whole numbers from the minimum to the maximum.
+    Discrete,
+}
+pub use self::Scale::*;
+
+
+/// Describes a single parameter.
+#[derive(Clone, Copy, Debug)]
+pub struct ParameterInfo {
+    /// The name of the parameter, unique within its device.
+    pub name: &'static str,
+    /// The unit of the parameter.
+    pub unit: Unit,
+    /// How normalized values map onto the range.
+    pub scale: Scale,
+    /// The smallest value of the parameter.
+    pub min: f32,
+    /// The largest value of the parameter.
+    pub max: f32,
+    /// The default value of the parameter.
+    pub default: f32,
+    /// Names for each value of a discrete parameter, starting from the
+    /// minimum. Empty if the values are not named.
+    pub labels: &'static [&'static str],
+}
+
+impl ParameterInfo {
+    /// Returns a unitless, linear parameter with the provided range and default
+    /// value.
+    pub fn new(name: &'static str, min: f32, max: f32, default: f32) -> Self {
+        ParameterInfo {
+            name: name,
+            unit: Unitless,
+            scale: Linear,
+            min: min,
+            max: max,
+            default: default,
+            labels: &[],
+        }
+    }
+
+    /// Returns a discrete parameter that chooses between the named `labels`.
+    ///
+    /// The value of the parameter is the index of the chosen label.
+    pub fn choice(name: &'static str, labels: &'static [&'static str],
+                  default: usize) -> Self {
+        let max = labels.len().saturating_sub(1) as f32;
+        ParameterInfo::new(name, 0.0, max, default as f32).scale(Discrete)
+            .labels(labels)
+    }
+
+    /// Sets the unit of the parameter, and return the same info.
+    pub fn unit(mut self, unit: Unit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    /// Sets the scale of the parameter, and return the same info.
+    pub fn scale(mut self, scale: Scale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Sets the labels of the parameter's values, and return the same info.
+    pub fn labels(mut self, labels: &'static [&'static str]) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Clamps `value` into the parameter's range, rounding it if the parameter
+    /// is discrete.
+    pub fn clamp(&self, value: f32) -> f32 {
+        let value = if self.scale == Discrete { value.round() } else { value };
+        value.max(self.min).min(self.max)
+    }
+
+    /// Converts a value of the parameter into a normalized value from 0 to 1.
+    pub fn normalize(&self, value: f32) -> f32 {
+        if self.max <= self.min {
+            return 0.0;
+        }
+        let normalized = match self.scale {
+            Linear | Discrete => (value-self.min) / (self.max-self.min),
+            Logarithmic => (value/self.min).ln() / (self.max/self.min).ln()
+        };
+        if normalized.is_nan() {
+            0.0
+        } else {
+            normalized.clamp(0.0, 1.0)
+        }
+    }
+
+    /// Converts a normalized value from 0 to 1 into a value of the parameter.
+    pub fn denormalize(&self, normalized: f32) -> f32 {
+        let normalized = normalized.clamp(0.0, 1.0);
+        let value = match self.scale {
+            Linear | Discrete => self.min + normalized*(self.max-self.min),
+            Logarithmic => self.min * (self.max/self.min).powf(normalized)
+        };
+        self.clamp(value)
+    }
+}
+
+
+/// A device with named parameters.
+///
+/// Parameters are numbered from zero up to `num_parameters`.
+///
+/// # Panics
+///
+/// The methods taking a parameter index panic if the index is not less than
+/// `num_parameters`.
+pub trait Parameters {
+    /// Returns the number of parameters the device has.
+    fn num_parameters(&self) -> usize;
+
+    /// Returns the description of the `i`th parameter.
+    fn get_parameter_info(&self, i: usize) -> ParameterInfo;
+
+    /// Returns the current value of the `i`th parameter.
+    fn get_parameter(&self, i: usize) -> f32;
+
+    /// Sets the value of the `i`th parameter. Values outside the parameter's
+    /// range are clamped into it.
+    fn set_parameter(&mut self, i: usize, value: f32);
+
+    /// Returns the index of the parameter named `name`, if there is one.
+    fn find_parameter(&self, name: &str) -> Option<usize> {
+        (0..self.num_parameters()).find(
+            |&i| self.get_parameter_info(i).name == name)
+    }
+
+    /// Returns the current value of the `i`th parameter, normalized to between
+    /// 0 and 1.
+    fn get_normalized(&self, i: usize) -> f32 {
+        self.get_parameter_info(i).normalize(self.get_parameter(i))
+    }
+
+    /// Sets the `i`th parameter from a value normalized to between 0 and 1.
+    fn set_normalized(&mut self, i: usize, normalized: f32) {
+        let value = self.get_parameter_info(i).denormalize(normalized);
+        self.set_parameter(i, value);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{Logarithmic, ParameterInfo};
+
+    #[test]
+    fn test_linear() {
+        let info = ParameterInfo::new("test", -10.0, 10.0, 0.0);
+        assert_eq!(info.normalize(5.0), 0.75);
+        assert_eq!(info.denormalize(0.25), -5.0);
+        assert_eq!(info.clamp(20.0), 10.0);
+    }
+
+    #[test]
+    fn test_logarithmic() {
+        let info = ParameterInfo::new("test", 10.0, 1000.0, 100.0)
+            .scale(Logarithmic);
+        assert_eq!(info.normalize(100.0), 0.5);
+        assert!((info.denormalize(0.5) - 100.0).abs() < 1e-3);
+        assert_eq!(info.normalize(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_choice() {
+        let info = ParameterInfo::choice("test", &["a", "b", "c"], 1);
+        assert_eq!(info.max, 2.0);
+        assert_eq!(info.denormalize(0.3), 1.0);
+        assert_eq!(info.clamp(1.4), 1.0);
+    }
+}
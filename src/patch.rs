@@ -0,0 +1,680 @@
+//! Saving and loading graphs as patches.
+//!
+//! A patch describes a `DeviceGraph` in text, so that it can be stored in a
+//! file rather than rebuilt in code. Each line of a patch is a node, an edge
+//! or a feedback edge. Blank lines are ignored, and a `#` starts a comment
+//! line:
+//!
+//! ```text
+//! # A low passed saw wave
+//! node osc oscillator frequency=220 waveform=1
+//! node lpf second_order channels=1 mode=0 cutoff=800
+//! edge osc 0 lpf 0
+//! ```
+//!
+//! A node line gives the node an id, names its device type, and lists any
+//! number of settings as `key=value` pairs. Settings either configure how the
+//! device is constructed, such as its number of channels, or set one of the
+//! device's parameters. Spaces in parameter names are written as underscores.
+//!
+//! An edge line connects an output channel of one node to an input channel of
+//! another, exactly as `DeviceGraph::add_edge` does. An edge may end with a
+//! gain, such as `edge osc 0 lpf 0 0.5`; otherwise its gain is 1.
+//!
+//! A feedback line connects two channels with a delay in samples, exactly as
+//! `DeviceGraph::add_feedback_edge` does, and may also end with a gain:
+//! `feedback lpf 0 lpf 0 64 0.5`.
+//!
+//! Device types are looked up in a `Registry`, which holds a factory for each
+//! type. The default registry knows every built-in device that can be
+//! configured from numbers alone; custom devices can be added with
+//! `Registry::register`.
+//!
+//! Errors found while loading a patch are wrapped in `Error::Patch`, along with
+//! the line they were found on.
+//!
+//! # Example
+//!
+//! ```
+//! use oxcable::graph::Tick;
+//! use oxcable::patch::{Patch, Registry};
+//!
+//! let patch: Patch = "
+//!     node osc oscillator frequency=220
+//!     node out gain channels=1 gain=-6
+//!     edge osc 0 out 0
+//! ".parse().unwrap();
+//!
+//! let (mut graph, ids) = patch.build(&Registry::new()).unwrap();
+//! graph.tick();
+//!
+//! // Export the graph again, including any changes to its parameters
+//! let exported = Patch::from_graph(&graph).unwrap();
+//! assert_eq!(exported.nodes.len(), 2);
+//! # let _ = ids;
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use error::{Error, Result};
+use filters::{first_order, second_order};
+use graph::{AudioNodeIdx, DeviceGraph};
+use mixers::{Adder, Gain, Multiplexer, Multiplier};
+use oscillator::{Oscillator, Sine};
+use types::{AudioDevice, Sample, SampleFloat};
+
+
+/// A description of a graph, that can be saved as text.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Patch {
+    /// The nodes of the graph, in the order they are added.
+    pub nodes: Vec<NodeDesc>,
+    /// The edges of the graph, in the order they are added.
+    pub edges: Vec<EdgeDesc>,
+}
+
+/// A description of a single node in a patch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeDesc {
+    /// The id edges use to refer to the node.
+    pub id: String,
+    /// The name of the device type, as registered in a `Registry`.
+    pub device_type: String,
+    /// The construction settings and parameters of the device.
+    pub settings: Vec<(String, f32)>,
+    /// The line the node was read from, or 0 if it wasn't read from text.
+    pub line: usize,
+}
+
+/// A description of a single edge in a patch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EdgeDesc {
+    /// The id of the source node.
+    pub src: String,
+    /// The output channel of the source node.
+    pub src_ch: usize,
+    /// The id of the destination node.
+    pub dest: String,
+    /// The input channel of the destination node.
+    pub dest_ch: usize,
+    /// The gain applied to the signal along the edge.
+    pub gain: f32,
+    /// The delay of a feedback edge, in samples, or 0 for a normal edge.
+    pub delay: usize,
+    /// The line the edge was read from, or 0 if it wasn't read from text.
+    pub line: usize,
+}
+
+impl Patch {
+    /// Reads a patch from the file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Patch> {
+        let mut text = String::new();
+        let mut file = try!(File::open(path));
+        try!(file.read_to_string(&mut text));
+        text.parse()
+    }
+
+    /// Writes the patch to a file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = try!(File::create(path));
+        try!(write!(file, "{}", self));
+        Ok(())
+    }
+
+    /// Describes an existing graph as a patch.
+    ///
+    /// Named nodes use their name as their id. Other nodes, and nodes whose
+    /// name is already taken or can't be written as an id, are given unique
+    /// ids from their device type and position in the graph. Every parameter
+    /// is saved with its current value.
+    ///
+    /// Returns an Err if any node has no device type recorded; see
+    /// `DeviceGraph::set_device_type`. This includes the port nodes added by
+    /// `DeviceGraph::set_inputs` and `DeviceGraph::set_outputs`.
+    pub fn from_graph<S: SampleFloat>(graph: &DeviceGraph<S>)
+            -> Result<Patch> {
+        let mut patch = Patch::default();
+
+        // Names are kept first, so that generated ids can avoid all of them
+        let mut ids: Vec<Option<String>> = Vec::new();
+        for &node in graph.get_nodes().iter() {
+            let id = match graph.get_name(node) {
+                Some(name) if is_valid_id(name) && !is_taken(&ids, name) =>
+                    Some(String::from(name)),
+                _ => None
+            };
+            ids.push(id);
+        }
+
+        for (i, &node) in graph.get_nodes().iter().enumerate() {
+            let device_type = match graph.get_device_type(node) {
+                Some(device_type) => device_type,
+                None => return Err(Error::Unsupported(
+                    "exporting nodes without a device type"))
+            };
+            let mut settings = device_type.settings.clone();
+            if let Some(params) = graph.get_parameters(node) {
+                for j in 0..params.num_parameters() {
+                    let name = params.get_parameter_info(j).name;
+                    settings.push((name.replace(" ", "_"),
+                                   params.get_parameter(j)));
+                }
+            }
+            if ids[i].is_none() {
+                let mut n = i;
+                let mut id = format!("{}{}", device_type.name, n);
+                while is_taken(&ids, &id) {
+                    n += 1;
+                    id = format!("{}{}", device_type.name, n);
+                }
+                ids[i] = Some(id);
+            }
+            patch.nodes.push(NodeDesc {
+                id: ids[i].clone().unwrap(),
+                device_type: device_type.name.clone(),
+                settings: settings,
+                line: 0
+            });
+        }
+        let ids: Vec<String> = ids.into_iter().map(|id| id.unwrap()).collect();
+
+        let nodes = graph.get_nodes();
+        for edge in graph.get_edges() {
+            let src = nodes.iter().position(|&n| n == edge.src).unwrap();
+            let dest = nodes.iter().position(|&n| n == edge.dest).unwrap();
+            patch.edges.push(EdgeDesc {
+                src: ids[src].clone(),
+                src_ch: edge.src_ch,
+                dest: ids[dest].clone(),
+                dest_ch: edge.dest_ch,
+                gain: edge.gain,
+                delay: edge.delay,
+                line: 0
+            });
+        }
+        Ok(patch)
+    }
+
+    /// Builds a new graph from the patch, creating each device using
+    /// `registry`. Each node is named after its id.
+    ///
+    /// Returns the graph, and a map from each node id to its identifier in the
+    /// graph.
+    pub fn build<S: SampleFloat>(&self, registry: &Registry<S>)
+            -> Result<(DeviceGraph<S>, HashMap<String, AudioNodeIdx>)> {
+        let mut graph = DeviceGraph::default();
+        let mut ids = HashMap::new();
+        for node in self.nodes.iter() {
+            if ids.contains_key(&node.id) {
+                return Err(at_line(node.line, Error::Parse("a unique id")));
+            }
+            let (device, device_type) = try!(
+                registry.create(&node.device_type, &node.settings)
+                    .map_err(|e| at_line(node.line, e)));
+            let idx = graph.add_node(device);
+            try!(graph.set_device_type(idx, device_type));
+            try!(graph.set_name(idx, &node.id));
+            ids.insert(node.id.clone(), idx);
+        }
+
+        for edge in self.edges.iter() {
+            let src = try!(ids.get(&edge.src)
+                .ok_or(at_line(edge.line, Error::OutOfRange("src"))));
+            let dest = try!(ids.get(&edge.dest)
+                .ok_or(at_line(edge.line, Error::OutOfRange("dest"))));
+            let result = if edge.delay > 0 {
+                graph.add_feedback_edge(*src, edge.src_ch, *dest, edge.dest_ch,
+                                        edge.delay, edge.gain)
+            } else {
+                graph.add_edge_with_gain(*src, edge.src_ch, *dest,
+                                         edge.dest_ch, edge.gain)
+            };
+            try!(result.map_err(|e| at_line(edge.line, e)));
+        }
+        Ok((graph, ids))
+    }
+}
+
+impl FromStr for Patch {
+    type Err = Error;
+
+    /// Parses a patch from text. Unknown device types are not detected until
+    /// the patch is built.
+    fn from_str(text: &str) -> Result<Patch> {
+        let mut patch = Patch::default();
+        for (i, line) in text.lines().enumerate() {
+            let line_num = i + 1;
+            let tokens: Vec<_> = line.split_whitespace().collect();
+            if tokens.is_empty() || tokens[0].starts_with("#") {
+                continue;
+            }
+            match tokens[0] {
+                "node" => {
+                    let node = try!(parse_node(&tokens, line_num)
+                                    .map_err(|e| at_line(line_num, e)));
+                    patch.nodes.push(node);
+                },
+                "edge" | "feedback" => {
+                    let edge = try!(parse_edge(&tokens, line_num)
+                                    .map_err(|e| at_line(line_num, e)));
+                    patch.edges.push(edge);
+                },
+                _ => return Err(at_line(line_num,
+                                        Error::Parse("node, edge or feedback")))
+            }
+        }
+        Ok(patch)
+    }
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for node in self.nodes.iter() {
+            try!(write!(f, "node {} {}", node.id, node.device_type));
+            for &(ref key, value) in node.settings.iter() {
+                try!(write!(f, " {}={}", key, value));
+            }
+            try!(writeln!(f, ""));
+        }
+        for edge in self.edges.iter() {
+            if edge.delay > 0 {
+                try!(write!(f, "feedback {} {} {} {} {}", edge.src,
+                            edge.src_ch, edge.dest, edge.dest_ch, edge.delay));
+            } else {
+                try!(write!(f, "edge {} {} {} {}", edge.src, edge.src_ch,
+                            edge.dest, edge.dest_ch));
+            }
+            if edge.gain != 1.0 {
+                try!(write!(f, " {}", edge.gain));
+            }
+            try!(writeln!(f, ""));
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether `id` can be written as a node id, and parsed back.
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() &&
+        !id.chars().any(|c| c.is_whitespace() || c == '#' || c == '=')
+}
+
+/// Returns whether `id` is already one of `ids`.
+fn is_taken(ids: &[Option<String>], id: &str) -> bool {
+    ids.iter().any(|taken| taken.as_deref() == Some(id))
+}
+
+/// Parses the tokens of a node line.
+fn parse_node(tokens: &[&str], line: usize) -> Result<NodeDesc> {
+    if tokens.len() < 3 {
+        return Err(Error::Parse("node <id> <type>"));
+    }
+    let mut settings = Vec::new();
+    for token in tokens[3..].iter() {
+        let mut parts = token.splitn(2, '=');
+        let key = parts.next().unwrap();
+        let value = match parts.next() {
+            Some(value) => value,
+            None => return Err(Error::Parse("key=value"))
+        };
+        let value = try!(value.parse().map_err(|_| Error::Parse("a number")));
+        settings.push((String::from(key), value));
+    }
+    Ok(NodeDesc {
+        id: String::from(tokens[1]),
+        device_type: String::from(tokens[2]),
+        settings: settings,
+        line: line
+    })
+}
+
+/// Parses the tokens of an edge or feedback line.
+fn parse_edge(tokens: &[&str], line: usize) -> Result<EdgeDesc> {
+    let feedback = tokens[0] == "feedback";
+    let required = if feedback { 6 } else { 5 };
+    if tokens.len() != required && tokens.len() != required + 1 {
+        return Err(Error::Parse(if feedback {
+            "feedback <src> <src_ch> <dest> <dest_ch> <delay> [gain]"
+        } else {
+            "edge <src> <src_ch> <dest> <dest_ch> [gain]"
+        }));
+    }
+    let src_ch = try!(tokens[2].parse().map_err(|_| Error::Parse("a channel")));
+    let dest_ch = try!(tokens[4].parse()
+                       .map_err(|_| Error::Parse("a channel")));
+    let delay = if feedback {
+        try!(tokens[5].parse().map_err(|_| Error::Parse("a delay")))
+    } else {
+        0
+    };
+    let gain = match tokens.get(required) {
+        Some(gain) => try!(gain.parse().map_err(|_| Error::Parse("a number"))),
+        None => 1.0
+    };
+    Ok(EdgeDesc {
+        src: String::from(tokens[1]),
+        src_ch: src_ch,
+        dest: String::from(tokens[3]),
+        dest_ch: dest_ch,
+        gain: gain,
+        delay: delay,
+        line: line
+    })
+}
+
+/// Attaches a line number to an error, unless the line is unknown.
+fn at_line(line: usize, error: Error) -> Error {
+    if line == 0 {
+        error
+    } else {
+        Error::Patch(line, Box::new(error))
+    }
+}
+
+
+/// The type a device was created from, and the settings used to construct
+/// it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceType {
+    /// The name of the type, as registered in a `Registry`.
+    pub name: String,
+    /// The settings used to construct the device. These don't include the
+    /// device's parameters.
+    pub settings: Vec<(String, f32)>,
+}
+
+
+/// The settings passed to a device factory.
+///
+/// Factories take the settings they need to construct the device. Any
+/// settings left over are then applied to the device's parameters.
+pub struct Settings {
+    remaining: Vec<(String, f32)>,
+    taken: Vec<(String, f32)>,
+}
+
+impl Settings {
+    /// Removes the setting named `key`, and returns its value, or `default`
+    /// if it wasn't set.
+    pub fn take(&mut self, key: &str, default: f32) -> f32 {
+        let value = match self.remaining.iter().position(|s| s.0 == key) {
+            Some(i) => self.remaining.remove(i).1,
+            None => default
+        };
+        self.taken.push((String::from(key), value));
+        value
+    }
+
+    /// Removes the setting named `key`, and returns its value as a count, or
+    /// `default` if it wasn't set.
+    ///
+    /// Returns an Err if the value is negative or not a whole number.
+    pub fn take_count(&mut self, key: &str, default: usize) -> Result<usize> {
+        let value = self.take(key, default as f32);
+        if value >= 0.0 && value.fract() == 0.0 {
+            Ok(value as usize)
+        } else {
+            Err(Error::Parse("a whole number"))
+        }
+    }
+}
+
+
+/// A factory that constructs a device from its settings.
+pub type Factory<S> = Box<Fn(&mut Settings) -> Result<Box<AudioDevice<S>>>>;
+
+/// A collection of device types that patches can create.
+///
+/// The following types are registered by default. Each takes its number of
+/// channels as a `channels` setting, except for the multiplexer, which takes
+/// `inputs`; both default to 1:
+///
+/// * `adder`, `multiplier`, `multiplexer` and `gain` from `mixers`.
+/// * `oscillator`, starting as a sine wave.
+/// * `first_order` and `second_order` filters, starting as low pass filters.
+///
+/// `Registry::new` creates a registry of the default `Sample` type; registries
+/// of other precisions can be created with `Registry::default`.
+pub struct Registry<S: SampleFloat = Sample> {
+    factories: HashMap<String, Factory<S>>,
+}
+
+impl Registry {
+    /// Returns a registry holding the built-in device types.
+    pub fn new() -> Self {
+        Registry::default()
+    }
+}
+
+impl<S: SampleFloat> Default for Registry<S> {
+    fn default() -> Self {
+        let mut registry = Registry::empty();
+        registry.register("adder", |s| {
+            let channels = try!(s.take_count("channels", 1));
+            Ok(Box::new(Adder::new_generic(channels)) as Box<AudioDevice<S>>)
+        });
+        registry.register("multiplier", |s| {
+            let channels = try!(s.take_count("channels", 1));
+            Ok(Box::new(Multiplier::new_generic(channels))
+               as Box<AudioDevice<S>>)
+        });
+        registry.register("multiplexer", |s| {
+            let inputs = try!(s.take_count("inputs", 1));
+            Ok(Box::new(Multiplexer::new_generic(inputs))
+               as Box<AudioDevice<S>>)
+        });
+        registry.register("gain", |s| {
+            let channels = try!(s.take_count("channels", 1));
+            Ok(Box::new(Gain::new_generic(0.0, channels))
+               as Box<AudioDevice<S>>)
+        });
+        registry.register("oscillator", |_| {
+            Ok(Box::new(Oscillator::new_generic(Sine)) as Box<AudioDevice<S>>)
+        });
+        registry.register("first_order", |s| {
+            let channels = try!(s.take_count("channels", 1));
+            let mode = first_order::LowPass(1000.0);
+            Ok(Box::new(first_order::Filter::new_generic(mode, channels))
+               as Box<AudioDevice<S>>)
+        });
+        registry.register("second_order", |s| {
+            let channels = try!(s.take_count("channels", 1));
+            let mode = second_order::LowPass(1000.0);
+            Ok(Box::new(second_order::Filter::new_generic(mode, channels))
+               as Box<AudioDevice<S>>)
+        });
+        registry
+    }
+}
+
+impl<S: SampleFloat> Registry<S> {
+    /// Returns a registry without any device types.
+    pub fn empty() -> Self {
+        Registry { factories: HashMap::new() }
+    }
+
+    /// Registers a device type under `name`, replacing any type already
+    /// registered with that name.
+    pub fn register<F>(&mut self, name: &str, factory: F)
+            where F: 'static+Fn(&mut Settings) -> Result<Box<AudioDevice<S>>> {
+        self.factories.insert(String::from(name), Box::new(factory));
+    }
+
+    /// Returns true if a device type is registered under `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.contains_key(name)
+    }
+
+    /// Creates a device of the type registered under `name`, then applies any
+    /// settings the factory didn't take to the device's parameters.
+    ///
+    /// Returns the device and its type, or an Err if the type isn't registered
+    /// or a setting doesn't match any of the device's parameters.
+    pub fn create(&self, name: &str, settings: &[(String, f32)])
+            -> Result<(Box<AudioDevice<S>>, DeviceType)> {
+        let factory = match self.factories.get(name) {
+            Some(factory) => factory,
+            None => return Err(Error::OutOfRange("device type"))
+        };
+        let mut settings = Settings {
+            remaining: settings.to_vec(),
+            taken: Vec::new()
+        };
+        let mut device = try!(factory(&mut settings));
+
+        for &(ref key, value) in settings.remaining.iter() {
+            let params = match device.parameters_mut() {
+                Some(params) => params,
+                None => return Err(Error::OutOfRange("parameter"))
+            };
+            match params.find_parameter(&key.replace("_", " ")) {
+                Some(i) => params.set_parameter(i, value),
+                None => return Err(Error::OutOfRange("parameter"))
+            }
+        }
+        // Jump straight to the loaded parameters instead of ramping to them
+        device.reset();
+
+        Ok((device, DeviceType {
+            name: String::from(name),
+            settings: settings.taken
+        }))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use error::Error;
+    use graph::Tick;
+    use mixers::Adder;
+    use types::AudioDevice;
+    use super::{Patch, Registry};
+
+    static PATCH: &str = "
+        # Two oscillators summed together
+        node a oscillator frequency=220
+        node b oscillator frequency=330 lfo_intensity=2
+
+        node sum adder channels=2
+        edge a 0 sum 0
+        edge b 0 sum 1 0.5
+        feedback sum 0 sum 0 4 0.25
+    ";
+
+    #[test]
+    fn test_parse() {
+        let patch: Patch = PATCH.parse().unwrap();
+        assert_eq!(patch.nodes.len(), 3);
+        assert_eq!(patch.nodes[1].id, "b");
+        assert_eq!(patch.nodes[1].device_type, "oscillator");
+        assert_eq!(patch.nodes[1].settings[1], ("lfo_intensity".to_string(),
+                                                2.0));
+        assert_eq!(patch.nodes[2].line, 6);
+        assert_eq!(patch.edges[1].src, "b");
+        assert_eq!(patch.edges[1].dest_ch, 1);
+        assert_eq!(patch.edges[0].gain, 1.0);
+        assert_eq!(patch.edges[1].gain, 0.5);
+        assert_eq!(patch.edges[1].delay, 0);
+        assert_eq!(patch.edges[2].delay, 4);
+        assert_eq!(patch.edges[2].gain, 0.25);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let patch: Patch = PATCH.parse().unwrap();
+        let (mut graph, ids) = patch.build(&Registry::new()).unwrap();
+        Tick::tick(&mut graph);
+
+        let params = graph.get_parameters(ids["b"]).unwrap();
+        assert_eq!(params.get_parameter(0), 330.0);
+        assert_eq!(params.get_parameter(2), 2.0);
+
+        let exported = Patch::from_graph(&graph).unwrap();
+        assert_eq!(exported.nodes[1].id, "b");
+        let text = exported.to_string();
+        let reparsed: Patch = text.parse().unwrap();
+        assert_eq!(reparsed.nodes.len(), 3);
+        assert_eq!(reparsed.edges.len(), 3);
+        for (a, b) in reparsed.nodes.iter().zip(exported.nodes.iter()) {
+            assert_eq!(a.settings, b.settings);
+        }
+
+        let (graph, _) = reparsed.build(&Registry::new()).unwrap();
+        assert_eq!(Patch::from_graph(&graph).unwrap().to_string(), text);
+    }
+
+    #[test]
+    fn test_export_ids() {
+        let patch: Patch = "
+            node a gain channels=1
+            node b gain channels=1
+            node c gain channels=1
+            edge a 0 b 0
+            edge b 0 c 0
+        ".parse().unwrap();
+        let (mut graph, ids) = patch.build(&Registry::new()).unwrap();
+        graph.set_name(ids["a"], "my gain").unwrap();
+        graph.set_name(ids["b"], "gain2").unwrap();
+        graph.set_name(ids["c"], "").unwrap();
+
+        let exported = Patch::from_graph(&graph).unwrap();
+        assert_eq!(exported.nodes[0].id, "gain0");
+        assert_eq!(exported.nodes[1].id, "gain2");
+        assert_eq!(exported.nodes[2].id, "gain3");
+
+        let reparsed: Patch = exported.to_string().parse().unwrap();
+        let (graph, ids) = reparsed.build(&Registry::new()).unwrap();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(graph.get_outputs(ids["gain0"]).unwrap().len(), 1);
+        assert_eq!(graph.get_outputs(ids["gain2"]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_errors() {
+        match "node a oscillator\nnode b reverb".parse::<Patch>().unwrap()
+                .build(&Registry::new()) {
+            Err(Error::Patch(2, ref e)) => match **e {
+                Error::OutOfRange("device type") => (),
+                _ => panic!("wrong error: {:?}", e)
+            },
+            _ => panic!("unknown device type was not reported")
+        }
+
+        match "node a oscillator\nnode b gain\n\nedge a 0 b 1"
+                .parse::<Patch>().unwrap().build(&Registry::new()) {
+            Err(Error::Patch(4, ref e)) => match **e {
+                Error::OutOfRange("dest_ch") => (),
+                _ => panic!("wrong error: {:?}", e)
+            },
+            _ => panic!("bad channel was not reported")
+        }
+
+        match "node a oscillator frequency=high".parse::<Patch>() {
+            Err(Error::Patch(1, _)) => (),
+            _ => panic!("bad number was not reported")
+        }
+    }
+
+    #[test]
+    fn test_register() {
+        let mut registry = Registry::empty();
+        assert!(!registry.contains("sum"));
+        registry.register("sum", |s| {
+            let channels = try!(s.take_count("inputs", 2));
+            Ok(Box::new(Adder::new(channels)) as Box<AudioDevice>)
+        });
+
+        let patch: Patch = "node s sum inputs=3".parse().unwrap();
+        let (graph, ids) = patch.build(&registry).unwrap();
+        let exported = Patch::from_graph(&graph).unwrap();
+        assert_eq!(exported.nodes[0].settings, vec![("inputs".to_string(),
+                                                     3.0)]);
+        assert!(graph.contains_node(ids["s"]));
+    }
+}
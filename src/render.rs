@@ -0,0 +1,440 @@
+//! Offline rendering of chains and graphs.
+//!
+//! Real-time processing is paced by an audio device, one block at a time. A
+//! `Renderer` instead processes as fast as it can, collecting the output into
+//! buffers or writing it straight to a wav file. This is useful for bouncing a
+//! patch to disk, or for checking the output of a patch in tests.
+//!
+//! Anything that implements `Render` can be rendered. `DeviceChain` renders
+//! the output of its last device, and a `GraphOutput` renders the outputs of a
+//! single node of a `DeviceGraph`.
+//!
+//! # Example
+//!
+//! The following renders one second of a sine wave into a wav file:
+//!
+//! ```no_run
+//! use oxcable::chain::DeviceChain;
+//! use oxcable::oscillator::{Oscillator, Sine};
+//! use oxcable::render::{After, Renderer};
+//!
+//! let mut chain: DeviceChain = DeviceChain::from(
+//!     Oscillator::new(Sine).freq(440.0));
+//! chain.set_block_size(256);
+//! Renderer::new(After(44100)).render_to_file(&mut chain, "sine.wav")
+//!     .unwrap();
+//! ```
+
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::path::Path;
+
+use chain::{DeviceChain, Tick};
+use error::{Error, Result};
+use graph::{AudioNodeIdx, DeviceGraph};
+use io::wav::WavWriter;
+use tick::StopHandle;
+use types::{AudioDevice, SampleFloat, Time};
+
+
+/// Decides when rendering stops.
+#[derive(Clone, Copy, Debug)]
+pub enum Stop {
+    /// Stops after the provided number of frames.
+    After(Time),
+    /// Stops once every channel has stayed below `threshold` for `hold`
+    /// frames, or after `max` frames, whichever comes first.
+    ///
+    /// The threshold is a linear amplitude, not decibels.
+    Silence {
+        /// The level a sample must not exceed to count as silent.
+        threshold: f32,
+        /// The number of consecutive silent frames to wait for.
+        hold: Time,
+        /// The most frames to render.
+        max: Time
+    },
+}
+pub use self::Stop::*;
+
+
+/// A source of audio that can be rendered offline.
+pub trait Render<S: SampleFloat> {
+    /// Returns the number of channels the source outputs.
+    fn num_channels(&self) -> usize;
+
+    /// Returns the sample rate of the source, in Hz.
+    fn get_sample_rate(&self) -> u32;
+
+    /// Processes the next block of audio, and returns the number of frames
+    /// processed.
+    ///
+    /// `output` holds one buffer per channel. Each buffer is cleared, then
+    /// filled with the frames of the block.
+    fn render_block(&mut self, output: &mut [Vec<S>]) -> usize;
+}
+
+impl<S: SampleFloat> Render<S> for DeviceChain<S> {
+    fn num_channels(&self) -> usize {
+        self.get_output().len() / self.get_block_size()
+    }
+
+    fn get_sample_rate(&self) -> u32 {
+        DeviceChain::get_sample_rate(self)
+    }
+
+    fn render_block(&mut self, output: &mut [Vec<S>]) -> usize {
+        Tick::tick(self);
+        let frames = self.get_block_size();
+        let samples = self.get_output();
+        let channels = samples.len() / frames;
+        for (ch, buffer) in output.iter_mut().enumerate() {
+            buffer.clear();
+            buffer.extend((0..frames).map(|f| samples[f*channels + ch]));
+        }
+        frames
+    }
+}
+
+
+/// Renders the outputs of a single node in a `DeviceGraph`.
+///
+/// Each block ticks the whole graph, then collects the outputs of the node.
+pub struct GraphOutput<'a, S: 'a+SampleFloat> {
+    graph: &'a mut DeviceGraph<S>,
+    node: AudioNodeIdx
+}
+
+impl<'a, S: SampleFloat> GraphOutput<'a, S> {
+    /// Returns a source rendering the outputs of `node`, or an Err if the node
+    /// doesn't exist.
+    pub fn new(graph: &'a mut DeviceGraph<S>, node: AudioNodeIdx)
+            -> Result<Self> {
+        try!(graph.get_output(node));
+        Ok(GraphOutput { graph: graph, node: node })
+    }
+}
+
+impl<'a, S: SampleFloat> Render<S> for GraphOutput<'a, S> {
+    fn num_channels(&self) -> usize {
+        self.graph.get_output(self.node).unwrap().len() /
+            self.graph.get_block_size()
+    }
+
+    fn get_sample_rate(&self) -> u32 {
+        self.graph.get_sample_rate()
+    }
+
+    fn render_block(&mut self, output: &mut [Vec<S>]) -> usize {
+        Tick::tick(self.graph);
+        let frames = self.graph.get_block_size();
+        let samples = self.graph.get_output(self.node).unwrap();
+        for (ch, buffer) in output.iter_mut().enumerate() {
+            buffer.clear();
+            buffer.extend_from_slice(&samples[ch*frames..(ch+1)*frames]);
+        }
+        frames
+    }
+}
+
+
+/// A callback passed the frames rendered so far and the total, if known.
+type Progress = Box<FnMut(Time, Option<Time>) -> bool>;
+
+/// Renders a source offline, as fast as possible.
+pub struct Renderer {
+    stop: Stop,
+    tail: Time,
+    progress: Option<Progress>,
+    handle: Option<StopHandle>
+}
+
+impl Renderer {
+    /// Returns a renderer that stops according to `stop`.
+    pub fn new(stop: Stop) -> Self {
+        Renderer {
+            stop: stop,
+            tail: 0,
+            progress: None,
+            handle: None
+        }
+    }
+
+    /// Sets a number of frames to keep rendering after the stop condition is
+    /// met, such as to let a reverb ring out, and return the same renderer.
+    pub fn tail(mut self, frames: Time) -> Self {
+        self.tail = frames;
+        self
+    }
+
+    /// Sets a callback to report progress after each block, and return the
+    /// same renderer.
+    ///
+    /// The callback is passed the number of frames rendered so far, and the
+    /// total number of frames if it is known yet. If the callback returns
+    /// false, rendering is cancelled.
+    pub fn progress<F>(mut self, callback: F) -> Self
+            where F: 'static+FnMut(Time, Option<Time>) -> bool {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a handle to control rendering from another thread, and return the
+    /// same renderer.
+    ///
+    /// Rendering waits between blocks while the handle is paused, and is
+    /// cancelled if the handle is stopped.
+    pub fn stop_handle(mut self, handle: StopHandle) -> Self {
+        self.handle = Some(handle);
+        self
+    }
+
+    /// Renders `source` into one buffer per channel.
+    ///
+    /// Returns `Error::Cancelled` if the progress callback or stop handle
+    /// cancelled the render.
+    pub fn render_to_buffers<S, R>(&mut self, source: &mut R)
+            -> Result<Vec<Vec<S>>> where S: SampleFloat, R: Render<S> {
+        let mut buffers = vec![Vec::new(); source.num_channels()];
+        try!(self.render(source, |block, frames| {
+            for (buffer, channel) in buffers.iter_mut().zip(block.iter()) {
+                buffer.extend_from_slice(&channel[..frames]);
+            }
+        }));
+        Ok(buffers)
+    }
+
+    /// Renders `source` into a new wav file at `path`, and returns the number
+    /// of frames written.
+    ///
+    /// Returns `Error::Cancelled` if the progress callback or stop handle
+    /// cancelled the render. The frames rendered before cancelling are still
+    /// written.
+    pub fn render_to_file<S, R, P>(&mut self, source: &mut R, path: P)
+            -> Result<Time>
+            where S: SampleFloat, R: Render<S>, P: AsRef<Path> {
+        let file = try!(File::create(path));
+        let writer = try!(WavWriter::new_generic(file, source.num_channels()));
+        self.render_to_wav(source, writer)
+    }
+
+    /// Renders `source` as a wav file into `writer`, and returns the number of
+    /// frames written.
+    ///
+    /// Returns `Error::Cancelled` if the progress callback or stop handle
+    /// cancelled the render. The frames rendered before cancelling are still
+    /// written.
+    pub fn render_to_writer<S, R, W>(&mut self, source: &mut R, writer: W)
+            -> Result<Time>
+            where S: SampleFloat, R: Render<S>, W: Write+Seek {
+        let writer = try!(WavWriter::new_generic(writer,
+                                                 source.num_channels()));
+        self.render_to_wav(source, writer)
+    }
+
+    /// Interleaves each rendered block into the provided wav writer.
+    fn render_to_wav<S, R, W>(&mut self, source: &mut R,
+                              mut writer: WavWriter<W, S>) -> Result<Time>
+            where S: SampleFloat, R: Render<S>, W: Write+Seek {
+        writer.set_sample_rate(source.get_sample_rate());
+        let mut interleaved = Vec::new();
+        let mut time = 0;
+        try!(self.render(source, |block, frames| {
+            interleaved.clear();
+            for f in 0..frames {
+                interleaved.extend(block.iter().map(|channel| channel[f]));
+            }
+            writer.tick_block(time, frames, &interleaved, &mut []);
+            time += frames as Time;
+        }));
+        Ok(time)
+    }
+
+    /// Renders blocks from `source` until the stop condition is met, passing
+    /// each block and the number of frames to keep from it to `sink`.
+    fn render<S, R, F>(&mut self, source: &mut R, mut sink: F) -> Result<Time>
+            where S: SampleFloat, R: Render<S>, F: FnMut(&[Vec<S>], usize) {
+        let mut block = vec![Vec::new(); source.num_channels()];
+        let mut time: Time = 0;
+        let mut end = match self.stop {
+            After(frames) => Some(frames + self.tail),
+            Silence { .. } => None
+        };
+        let mut loud_until = 0; // the frame after the last loud frame
+
+        loop {
+            let limit = match (end, self.stop) {
+                (Some(end), _) => end,
+                (None, Silence { max, .. }) => max,
+                (None, After(frames)) => frames
+            };
+            if time >= limit {
+                break;
+            }
+            if let Some(ref handle) = self.handle {
+                if !handle.wait() {
+                    return Err(Error::Cancelled);
+                }
+            }
+
+            let frames = source.render_block(&mut block);
+            if frames == 0 {
+                break;
+            }
+
+            // Look for the end of the render in this block
+            if let (None, Silence { threshold, hold, max }) = (end, self.stop) {
+                for f in 0..frames {
+                    let t = time + f as Time;
+                    if block.iter().any(|ch| ch[f].into_f32().abs() > threshold)
+                    {
+                        loud_until = t + 1;
+                    } else if t + 1 - loud_until >= hold {
+                        end = Some((t + 1 + self.tail).min(max));
+                        break;
+                    }
+                }
+            }
+
+            // Never keep frames past the end, or past max if none was found
+            let keep = (end.unwrap_or(limit) - time).min(frames as Time);
+            sink(&block, keep as usize);
+            time += keep;
+
+            if let Some(ref mut progress) = self.progress {
+                if !progress(time, end) {
+                    return Err(Error::Cancelled);
+                }
+            }
+        }
+        Ok(time)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use chain::DeviceChain;
+    use error::Error;
+    use graph::DeviceGraph;
+    use io::wav::WavReader;
+    use testing::MockAudioDevice;
+    use tick::StopHandle;
+    use types::AudioDevice;
+    use super::{After, GraphOutput, Renderer, Silence};
+
+    #[test]
+    fn test_after() {
+        let mut mock = MockAudioDevice::new("mock", 0, 1);
+        for i in 0..6 {
+            mock.will_tick(&[], &[i as f32]);
+        }
+        let mut chain = DeviceChain::from(mock);
+        chain.set_block_size(2);
+
+        let output = Renderer::new(After(3)).tail(2)
+            .render_to_buffers(&mut chain).unwrap();
+        assert_eq!(output, vec![vec![0.0, 1.0, 2.0, 3.0, 4.0]]);
+    }
+
+    #[test]
+    fn test_silence() {
+        let mut mock = MockAudioDevice::new("mock", 0, 1);
+        mock.will_tick(&[], &[1.0]);
+        for _ in 0..3 {
+            mock.will_tick(&[], &[0.0]);
+        }
+        let mut chain = DeviceChain::from(mock);
+
+        let stop = Silence { threshold: 0.5, hold: 2, max: 100 };
+        let output = Renderer::new(stop).tail(1)
+            .render_to_buffers(&mut chain).unwrap();
+        assert_eq!(output, vec![vec![1.0, 0.0, 0.0, 0.0]]);
+    }
+
+    #[test]
+    fn test_silence_max() {
+        let mut mock = MockAudioDevice::new("mock", 0, 1);
+        for _ in 0..6 {
+            mock.will_tick(&[], &[1.0]);
+        }
+        let mut chain = DeviceChain::from(mock);
+        chain.set_block_size(2);
+
+        let stop = Silence { threshold: 0.5, hold: 2, max: 5 };
+        let output = Renderer::new(stop).render_to_buffers(&mut chain)
+            .unwrap();
+        assert_eq!(output, vec![vec![1.0; 5]]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut mock = MockAudioDevice::new("mock", 0, 1);
+        mock.will_tick(&[], &[0.0]);
+        let mut chain = DeviceChain::from(mock);
+
+        let result = Renderer::new(After(4)).progress(|t, total| {
+            assert_eq!(total, Some(4));
+            t < 1
+        }).render_to_buffers(&mut chain);
+        match result {
+            Err(Error::Cancelled) => (),
+            _ => panic!("render was not cancelled")
+        }
+    }
+
+    #[test]
+    fn test_stop_handle() {
+        let mut mock = MockAudioDevice::new("mock", 0, 1);
+        mock.will_tick(&[], &[0.0]);
+        let mut chain = DeviceChain::from(mock);
+
+        let handle = StopHandle::new();
+        let progress_handle = handle.clone();
+        let result = Renderer::new(After(4)).stop_handle(handle)
+            .progress(move |_, _| { progress_handle.stop(); true })
+            .render_to_buffers(&mut chain);
+        match result {
+            Err(Error::Cancelled) => (),
+            _ => panic!("render was not cancelled")
+        }
+    }
+
+    #[test]
+    fn test_graph_output() {
+        let mut graph = DeviceGraph::new();
+        graph.set_block_size(2);
+        let mut mock = MockAudioDevice::new("mock", 0, 2);
+        mock.will_tick(&[], &[1.0, 2.0]).will_tick(&[], &[3.0, 4.0]);
+        let node = graph.add_node(mock);
+
+        let mut output = GraphOutput::new(&mut graph, node).unwrap();
+        let buffers = Renderer::new(After(2)).render_to_buffers(&mut output)
+            .unwrap();
+        assert_eq!(buffers, vec![vec![1.0, 3.0], vec![2.0, 4.0]]);
+    }
+
+    #[test]
+    fn test_wav() {
+        let mut mock = MockAudioDevice::new("mock", 0, 2);
+        mock.will_tick(&[], &[0.5, -0.5]).will_tick(&[], &[0.25, -0.25]);
+        let mut chain = DeviceChain::from(mock);
+        chain.set_sample_rate(22050);
+
+        let mut cursor = Cursor::new(Vec::new());
+        let frames = Renderer::new(After(2))
+            .render_to_writer(&mut chain, &mut cursor).unwrap();
+        assert_eq!(frames, 2);
+
+        cursor.set_position(0);
+        let mut reader: WavReader<_> = WavReader::new(cursor).unwrap();
+        assert_eq!(reader.get_sample_rate(), 22050);
+        assert_eq!(reader.get_num_samples(), 2);
+        let mut frame = [0.0; 2];
+        reader.tick(0, &[], &mut frame);
+        assert!((frame[0] - 0.5).abs() < 1e-3);
+        assert!((frame[1] + 0.5).abs() < 1e-3);
+    }
+}
@@ -12,6 +12,7 @@ pub struct MockAudioDevice {
     name: String,
     num_inputs: usize,
     num_outputs: usize,
+    latency: usize,
     times_ticked: usize,
     inputs: Vec<Vec<Sample>>,
     outputs: Vec<Vec<Sample>>,
@@ -27,6 +28,7 @@ impl MockAudioDevice {
             name: String::from(name),
             num_inputs: inputs,
             num_outputs: outputs,
+            latency: 0,
             times_ticked: 0,
             inputs: Vec::new(),
             outputs: Vec::new(),
@@ -41,6 +43,12 @@ impl MockAudioDevice {
         self.outputs.push(Vec::from(outputs));
         self
     }
+
+    /// Sets the latency the device reports, in samples.
+    pub fn will_report_latency(&mut self, latency: usize) -> &mut Self {
+        self.latency = latency;
+        self
+    }
 }
 
 impl Drop for MockAudioDevice {
@@ -57,6 +65,7 @@ impl Drop for MockAudioDevice {
 impl AudioDevice for MockAudioDevice {
     fn num_inputs(&self) -> usize { self.num_inputs }
     fn num_outputs(&self) -> usize { self.num_outputs }
+    fn latency(&self) -> usize { self.latency }
 
     fn tick(&mut self, _: Time, inputs: &[Sample], outputs: &mut[Sample]) {
         if self.times_ticked >= self.inputs.len() {
@@ -1,10 +1,45 @@
 //! A trait for objects that process in discrete time steps.
+//!
+//! Besides ticking a fixed number of times, the `Tick` trait can tick until a
+//! condition is met, for a length of wall clock time, or until it is stopped
+//! from another thread using a `StopHandle`.
+//!
+//! # Example
+//!
+//! The following plays a sine wave from a seperate thread, and stops it after
+//! one second:
+//!
+//! ```no_run
+//! use std::thread;
+//! use std::time::Duration;
+//! use oxcable::chain::{DeviceChain, Tick};
+//! use oxcable::io::audio::AudioEngine;
+//! use oxcable::oscillator::{Oscillator, Sine};
+//! use oxcable::tick::StopHandle;
+//!
+//! let handle = StopHandle::new();
+//! let thread_handle = handle.clone();
+//! let thread = thread::spawn(move || {
+//!     let engine = AudioEngine::with_buffer_size(256).unwrap();
+//!     let mut chain: DeviceChain = DeviceChain::from(
+//!         Oscillator::new(Sine).freq(440.0)
+//!     ).into(
+//!         engine.default_output(1).unwrap()
+//!     );
+//!     chain.tick_until_stopped(&thread_handle);
+//! });
+//!
+//! thread::sleep(Duration::from_secs(1));
+//! handle.stop();
+//! thread.join().unwrap();
+//! ```
 
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use types::{SAMPLE_RATE, Time};
+use types::Time;
 
 
 /// Methods for processing something in discrete time steps. By defining how to
@@ -12,6 +47,10 @@ use types::{SAMPLE_RATE, Time};
 ///
 /// A single tick could correspond to one frame, but it doesn't have to. For
 /// example, if an object does buffering it may process many samples per tick.
+///
+/// The methods that tick until some condition check it between every tick. A
+/// tick may cover anywhere from a single frame to a large block, so they can't
+/// assume how much time passes between checks.
 pub trait Tick {
     /// Handles one time step.
     fn tick(&mut self);
@@ -30,27 +69,171 @@ pub trait Tick {
         }
     }
 
+    /// Ticks until `done` returns true. `done` is called before every tick.
+    fn tick_until<F>(&mut self, mut done: F) where F: FnMut() -> bool {
+        while !done() {
+            self.tick();
+        }
+    }
+
+    /// Ticks until at least `duration` has passed on the wall clock.
+    fn tick_for(&mut self, duration: Duration) {
+        let start = Instant::now();
+        self.tick_until(|| start.elapsed() >= duration);
+    }
+
+    /// Ticks until `handle` is stopped.
+    ///
+    /// While the handle is paused, the thread sleeps instead of ticking.
+    fn tick_until_stopped(&mut self, handle: &StopHandle) {
+        while handle.wait() {
+            self.tick();
+        }
+    }
+
+    /// Ticks, then calls `callback` after every tick, until the callback
+    /// returns false.
+    ///
+    /// The callback is passed the object being ticked, so that it can inspect
+    /// the outputs of each block or send messages to its devices.
+    fn tick_with<F>(&mut self, mut callback: F)
+            where Self: Sized, F: FnMut(&mut Self) -> bool {
+        loop {
+            self.tick();
+            if !callback(self) {
+                break;
+            }
+        }
+    }
+
     /// Ticks while waiting for the user to press `Enter`. When enter is
     /// pressed, ticking stops and the method returns.
     fn tick_until_enter(&mut self) {
-        let stopped = Arc::new(AtomicBool::new(false));
-        let signal_stop = stopped.clone();
+        let handle = StopHandle::new();
+        let signal_stop = handle.clone();
         thread::spawn(move || {
             use std::io::{Read, stdin};
             let mut buf = [0];
             let _ = stdin().read(&mut buf);
-            signal_stop.store(true, Ordering::Relaxed);
+            signal_stop.stop();
         });
+        self.tick_until_stopped(&handle);
+    }
+}
 
-        let ticks = SAMPLE_RATE / 10;
-        loop {
-            // Tick for 100ms, then check for exit command
-            for _ in 0..ticks {
-                self.tick();
-            }
-            if stopped.load(Ordering::Relaxed) {
-                break;
-            }
+
+/// A handle to stop or pause ticking from another thread.
+///
+/// Clones of a handle share the same state, so one clone can be passed to the
+/// thread doing the processing, while another is kept to control it.
+#[derive(Clone, Debug, Default)]
+pub struct StopHandle {
+    stopped: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>
+}
+
+impl StopHandle {
+    /// Returns a new handle, that is neither stopped nor paused.
+    pub fn new() -> Self {
+        StopHandle::default()
+    }
+
+    /// Stops processing. A stopped handle can't be restarted.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if the handle has been stopped.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Pauses processing until `resume` is called.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Resumes processing after a pause.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns true if the handle is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Blocks while the handle is paused, then returns true if processing
+    /// should continue, or false if the handle has been stopped.
+    pub fn wait(&self) -> bool {
+        while self.is_paused() && !self.is_stopped() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        !self.is_stopped()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{StopHandle, Tick};
+
+    struct Counter(usize);
+
+    impl Tick for Counter {
+        fn tick(&mut self) {
+            self.0 += 1;
         }
     }
+
+    #[test]
+    fn test_tick_until() {
+        let mut counter = Counter(0);
+        let mut checks = 0;
+        counter.tick_until(|| { checks += 1; checks > 3 });
+        assert_eq!(counter.0, 3);
+    }
+
+    #[test]
+    fn test_tick_with() {
+        let mut counter = Counter(0);
+        counter.tick_with(|c| c.0 < 5);
+        assert_eq!(counter.0, 5);
+    }
+
+    #[test]
+    fn test_stop_handle() {
+        let handle = StopHandle::new();
+        let mut counter = Counter(0);
+        counter.tick_with(|c| {
+            if c.0 == 4 {
+                handle.stop();
+            }
+            !handle.is_stopped()
+        });
+        assert_eq!(counter.0, 4);
+
+        // A stopped handle never ticks
+        counter.tick_until_stopped(&handle);
+        assert_eq!(counter.0, 4);
+    }
+
+    #[test]
+    fn test_pause() {
+        let handle = StopHandle::new();
+        handle.pause();
+        let thread_handle = handle.clone();
+        let thread = thread::spawn(move || {
+            let mut counter = Counter(0);
+            counter.tick_until_stopped(&thread_handle);
+            counter.0
+        });
+
+        thread::sleep(Duration::from_millis(20));
+        handle.stop();
+        assert_eq!(thread.join().unwrap(), 0);
+    }
 }
@@ -0,0 +1,343 @@
+//! A musical transport, shared by the devices in a container.
+//!
+//! Devices are only passed a raw sample counter when they tick. The transport
+//! adds a musical clock on top of it: a tempo, a time signature, whether
+//! playback is running, and an optional loop. From these it converts a
+//! position in frames into beats and bars, so that tempo-synced devices, such
+//! as LFOs, delays and sequencers, don't each need to reimplement the math.
+//!
+//! Each `DeviceChain` and `DeviceGraph` owns a transport, and hands a shared
+//! `TransportHandle` to every device through `AudioDevice::set_transport`. The
+//! container advances the transport after every block, while it is playing.
+//!
+//! The tempo can change over time. Tempo changes are scheduled at a position
+//! of the transport in frames, and take effect on exactly that frame.
+//!
+//! # Example
+//!
+//! ```
+//! use oxcable::transport::Transport;
+//!
+//! // At 120 bpm and 4 Hz, there are two frames per beat...
+//! let mut transport = Transport::new(4);
+//! transport.set_tempo(120.0);
+//! transport.schedule_tempo(8, 240.0);
+//! assert_eq!(transport.beats_at(8), 4.0);
+//! // ...until frame 8, then just one
+//! assert_eq!(transport.beats_at(10), 6.0);
+//!
+//! let bar_beat = transport.bar_beat_at(10);
+//! assert_eq!((bar_beat.bar, bar_beat.beat), (1, 2.0));
+//! ```
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use types::Time;
+
+
+/// The tempo of a new transport, in beats per minute.
+pub const DEFAULT_TEMPO: f64 = 120.0;
+
+
+/// A transport shared between a container and its devices.
+pub type TransportHandle = Rc<RefCell<Transport>>;
+
+
+/// A time signature.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeSignature {
+    /// The number of beats in each bar.
+    pub beats_per_bar: u32,
+    /// The note value of each beat, such as 4 for quarter notes.
+    pub beat_value: u32
+}
+
+impl Default for TimeSignature {
+    /// Returns 4/4 time.
+    fn default() -> Self {
+        TimeSignature { beats_per_bar: 4, beat_value: 4 }
+    }
+}
+
+
+/// A position in bars and beats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BarBeat {
+    /// The bar, counting from zero.
+    pub bar: u64,
+    /// The beat within the bar, counting from zero. The fractional part is
+    /// the position within the beat.
+    pub beat: f64
+}
+
+
+/// A musical clock with a tempo, a time signature and a play state.
+#[derive(Clone, Debug)]
+pub struct Transport {
+    sample_rate: u32,
+    time_signature: TimeSignature,
+    tempo_map: Vec<(Time, f64)>, // sorted by time, the first starting at 0
+    position: Time,
+    playing: bool,
+    looping: Option<(Time, Time)>
+}
+
+impl Transport {
+    /// Returns a stopped transport at the start of the song, running at
+    /// `sample_rate` Hz with the default tempo and time signature.
+    pub fn new(sample_rate: u32) -> Self {
+        Transport {
+            sample_rate: sample_rate,
+            time_signature: TimeSignature::default(),
+            tempo_map: vec![(0, DEFAULT_TEMPO)],
+            position: 0,
+            playing: false,
+            looping: None
+        }
+    }
+
+    /// Returns a new shared handle to a transport.
+    pub fn new_handle(sample_rate: u32) -> TransportHandle {
+        Rc::new(RefCell::new(Transport::new(sample_rate)))
+    }
+
+    /// Returns the sample rate of the transport, in Hz.
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Sets the sample rate of the transport, in Hz.
+    ///
+    /// Positions are kept in frames, so any scheduled tempo changes and loop
+    /// points are not rescaled.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Returns the time signature.
+    pub fn get_time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+
+    /// Sets the time signature.
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+    }
+
+    /// Starts the transport from its current position.
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    /// Stops the transport, leaving it at its current position.
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    /// Returns true if the transport is playing.
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Returns the position of the transport, in frames.
+    pub fn get_position(&self) -> Time {
+        self.position
+    }
+
+    /// Moves the transport to `position`, in frames.
+    pub fn seek(&mut self, position: Time) {
+        self.position = position;
+    }
+
+    /// Returns the loop range, if looping is enabled.
+    pub fn get_loop(&self) -> Option<(Time, Time)> {
+        self.looping
+    }
+
+    /// Loops playback from the frame `end` back to the frame `start`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `end` is not after `start`.
+    pub fn set_loop(&mut self, start: Time, end: Time) {
+        if end <= start {
+            panic!("Transport: loop must end after it starts");
+        }
+        self.looping = Some((start, end));
+    }
+
+    /// Disables looping.
+    pub fn clear_loop(&mut self) {
+        self.looping = None;
+    }
+
+    /// Returns the position the transport will be at after `frames` more
+    /// frames, wrapping around the loop if it is enabled.
+    ///
+    /// Devices can use this to find the position of each frame within a
+    /// block. If the transport is stopped, the position doesn't change.
+    pub fn position_after(&self, frames: usize) -> Time {
+        if !self.playing {
+            return self.position;
+        }
+        let next = self.position + frames as Time;
+        match self.looping {
+            Some((start, end)) if self.position < end && next >= end =>
+                start + (next - end) % (end - start),
+            _ => next
+        }
+    }
+
+    /// Moves the transport forward by `frames` frames, if it is playing.
+    ///
+    /// Containers call this after processing each block.
+    pub fn advance(&mut self, frames: usize) {
+        self.position = self.position_after(frames);
+    }
+
+    /// Returns the tempo at the current position, in beats per minute.
+    pub fn get_tempo(&self) -> f64 {
+        self.tempo_at(self.position)
+    }
+
+    /// Returns the tempo at the frame `t`, in beats per minute.
+    pub fn tempo_at(&self, t: Time) -> f64 {
+        self.tempo_map.iter().rev().find(|&&(start, _)| start <= t)
+            .map(|&(_, tempo)| tempo).unwrap_or(DEFAULT_TEMPO)
+    }
+
+    /// Sets a constant tempo, in beats per minute, removing any scheduled
+    /// tempo changes.
+    pub fn set_tempo(&mut self, tempo: f64) {
+        self.tempo_map = vec![(0, tempo)];
+    }
+
+    /// Changes the tempo to `tempo` beats per minute, starting at the frame
+    /// `t`. The tempo holds until the next scheduled change.
+    ///
+    /// A change already scheduled at `t` is replaced.
+    pub fn schedule_tempo(&mut self, t: Time, tempo: f64) {
+        match self.tempo_map.binary_search_by(|&(start, _)| start.cmp(&t)) {
+            Ok(i) => self.tempo_map[i].1 = tempo,
+            Err(i) => self.tempo_map.insert(i, (t, tempo))
+        }
+    }
+
+    /// Returns the number of frames in a beat at the current tempo.
+    pub fn frames_per_beat(&self) -> f64 {
+        60.0 * self.sample_rate as f64 / self.get_tempo()
+    }
+
+    /// Returns the number of beats from the start of the song to the frame
+    /// `t`, following the tempo map.
+    pub fn beats_at(&self, t: Time) -> f64 {
+        let rate = self.sample_rate as f64 * 60.0;
+        let mut beats = 0.0;
+        for (i, &(start, tempo)) in self.tempo_map.iter().enumerate() {
+            if start >= t {
+                break;
+            }
+            let end = match self.tempo_map.get(i+1) {
+                Some(&(next, _)) if next < t => next,
+                _ => t
+            };
+            beats += (end - start) as f64 * tempo / rate;
+        }
+        beats
+    }
+
+    /// Returns the frame the song reaches `beats` beats at, following the
+    /// tempo map. The result is rounded to the nearest frame.
+    pub fn time_at_beats(&self, beats: f64) -> Time {
+        let rate = self.sample_rate as f64 * 60.0;
+        let mut remaining = beats;
+        for (i, &(start, tempo)) in self.tempo_map.iter().enumerate() {
+            let length = match self.tempo_map.get(i+1) {
+                Some(&(next, _)) => (next - start) as f64 * tempo / rate,
+                None => f64::MAX
+            };
+            if remaining <= length {
+                return start + (remaining * rate / tempo).round() as Time;
+            }
+            remaining -= length;
+        }
+        0
+    }
+
+    /// Returns the number of beats from the start of the song to the current
+    /// position.
+    pub fn get_beats(&self) -> f64 {
+        self.beats_at(self.position)
+    }
+
+    /// Returns the bar and beat of the frame `t`.
+    pub fn bar_beat_at(&self, t: Time) -> BarBeat {
+        let beats = self.beats_at(t);
+        let per_bar = self.time_signature.beats_per_bar as f64;
+        let bar = (beats / per_bar).floor();
+        BarBeat { bar: bar as u64, beat: beats - bar*per_bar }
+    }
+
+    /// Returns the bar and beat of the current position.
+    pub fn get_bar_beat(&self) -> BarBeat {
+        self.bar_beat_at(self.position)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{TimeSignature, Transport};
+
+    #[test]
+    fn test_beats() {
+        let transport = Transport::new(60);
+        assert_eq!(transport.frames_per_beat(), 30.0);
+        assert_eq!(transport.beats_at(45), 1.5);
+        assert_eq!(transport.time_at_beats(1.5), 45);
+    }
+
+    #[test]
+    fn test_tempo_map() {
+        let mut transport = Transport::new(60);
+        transport.schedule_tempo(60, 60.0);
+        transport.schedule_tempo(30, 30.0);
+        assert_eq!(transport.tempo_at(29), 120.0);
+        assert_eq!(transport.tempo_at(30), 30.0);
+        assert_eq!(transport.beats_at(60), 1.25);
+        assert_eq!(transport.beats_at(120), 2.25);
+        assert_eq!(transport.time_at_beats(2.25), 120);
+
+        transport.set_tempo(60.0);
+        assert_eq!(transport.beats_at(120), 2.0);
+    }
+
+    #[test]
+    fn test_bar_beat() {
+        let mut transport = Transport::new(60);
+        transport.set_time_signature(
+            TimeSignature { beats_per_bar: 3, beat_value: 4 });
+        let bar_beat = transport.bar_beat_at(225);
+        assert_eq!(bar_beat.bar, 2);
+        assert_eq!(bar_beat.beat, 1.5);
+    }
+
+    #[test]
+    fn test_play_and_loop() {
+        let mut transport = Transport::new(60);
+        transport.advance(10);
+        assert_eq!(transport.get_position(), 0);
+
+        transport.play();
+        transport.set_loop(4, 8);
+        transport.advance(6);
+        assert_eq!(transport.get_position(), 6);
+        transport.advance(3);
+        assert_eq!(transport.get_position(), 5);
+
+        transport.stop();
+        transport.advance(3);
+        assert_eq!(transport.get_position(), 5);
+    }
+}
@@ -1,12 +1,72 @@
 //! Global types and constants.
 
+use std::any::Any;
+use std::fmt::Debug;
 
-/// The global sample rate, in Hz.
+use num::traits::Float;
+
+use parameters::Parameters;
+use transport::TransportHandle;
+
+
+/// The default sample rate, in Hz.
+///
+/// Devices process at this rate until they are told otherwise using
+/// `AudioDevice::set_sample_rate`.
 pub static SAMPLE_RATE: u32 = 44100;
 
 /// The datatype of a single audio sample.
+///
+/// This is the default sample precision. Devices and containers are generic
+/// over any `SampleFloat`, but default to `Sample` when no other precision is
+/// specified.
 pub type Sample = f32;
 
+/// A floating point type that can be used as an audio sample.
+///
+/// This is implemented for `f32` and `f64`. Using `f64` samples is slower, but
+/// avoids the loss of precision in long feedback networks and filters with
+/// very low cutoff frequencies.
+///
+/// Devices created with `new` use the default `Sample` precision. Other
+/// precisions are created with `new_generic`:
+///
+/// ```
+/// use oxcable::filters::second_order::{Filter, LowPass};
+/// use oxcable::types::AudioDevice;
+///
+/// let mut filter = Filter::<f64>::new_generic(LowPass(20.0), 1);
+/// let mut output = [0.0];
+/// filter.tick(0, &[1.0], &mut output);
+/// ```
+pub trait SampleFloat: Float + Default + Debug + Send + Sync + 'static {
+    /// Converts an `f32` to this sample type.
+    fn from_f32(x: f32) -> Self;
+
+    /// Converts an `f64` to this sample type.
+    fn from_f64(x: f64) -> Self;
+
+    /// Converts this sample to an `f32`.
+    fn into_f32(self) -> f32;
+
+    /// Converts this sample to an `f64`.
+    fn into_f64(self) -> f64;
+}
+
+impl SampleFloat for f32 {
+    fn from_f32(x: f32) -> Self { x }
+    fn from_f64(x: f64) -> Self { x as f32 }
+    fn into_f32(self) -> f32 { self }
+    fn into_f64(self) -> f64 { self as f64 }
+}
+
+impl SampleFloat for f64 {
+    fn from_f32(x: f32) -> Self { x as f64 }
+    fn from_f64(x: f64) -> Self { x }
+    fn into_f32(self) -> f32 { self as f32 }
+    fn into_f64(self) -> f64 { self }
+}
+
 /// The datatype of a single time tick.
 pub type Time = u64;
 
@@ -69,7 +129,10 @@ pub enum MidiMessage {
 
 
 /// A device that processes and/or generates audio.
-pub trait AudioDevice {
+///
+/// Devices are generic over their sample precision `S`, which defaults to
+/// `Sample`.
+pub trait AudioDevice<S: SampleFloat = Sample> {
     /// Returns the number of input channels the device accepts.
     fn num_inputs(&self) -> usize;
 
@@ -81,7 +144,159 @@ pub trait AudioDevice {
     ///
     /// If a device accepts no inputs, or generates no outputs, then zero length
     /// slices may be passed in.
-    fn tick(&mut self, t: Time, inputs: &[Sample], outputs: &mut[Sample]);
+    fn tick(&mut self, t: Time, inputs: &[S], outputs: &mut[S]);
+
+    /// Sets the sample rate the device processes at, in Hz.
+    ///
+    /// Devices that depend on the sample rate should recompute any derived
+    /// values, such as filter coefficients. The default implementation does
+    /// nothing.
+    fn set_sample_rate(&mut self, _: u32) {}
+
+    /// Prepares the device for playback at `sample_rate` Hz, processing at
+    /// most `block_size` frames per call to `tick_block`.
+    ///
+    /// Containers call this before processing begins, and again whenever their
+    /// sample rate or block size changes. Devices may use it to allocate any
+    /// buffers they need. The default implementation calls `set_sample_rate`.
+    fn prepare(&mut self, sample_rate: u32, _block_size: usize) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    /// Clears any internal state, such as filter history or oscillator phase,
+    /// so that the device behaves as if it were newly created. Parameters are
+    /// left unchanged.
+    ///
+    /// The default implementation does nothing.
+    fn reset(&mut self) {}
+
+    /// Returns the processing latency of the device, in samples.
+    ///
+    /// This is the number of samples an input takes to reach the output, such
+    /// as from lookahead or block-based processing. The default is no latency.
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Gives the device a handle to the transport of its container.
+    ///
+    /// Containers call this when a device is added. Tempo-synced devices
+    /// should keep the handle, and read the tempo and position from it while
+    /// processing. The default implementation ignores the transport.
+    fn set_transport(&mut self, _: TransportHandle) {}
+
+    /// Returns the device's parameters, if it has any.
+    ///
+    /// This allows the parameters of a device to be found through an
+    /// `AudioDevice` trait object. Devices that implement `Parameters` should
+    /// return `Some(self)`. The default implementation returns `None`.
+    fn parameters(&self) -> Option<&Parameters> {
+        None
+    }
+
+    /// Returns the device's parameters mutably, if it has any.
+    ///
+    /// Devices that implement `Parameters` should return `Some(self)`. The
+    /// default implementation returns `None`.
+    fn parameters_mut(&mut self) -> Option<&mut Parameters> {
+        None
+    }
+
+    /// Processes a block of `frames` frames worth of audio data, starting at
+    /// time `t`.
+    ///
+    /// The inputs and outputs are interleaved by frame: the `i`th channel of
+    /// frame `f` is found at index `f*num_channels + i`. Therefore `inputs`
+    /// holds `frames*num_inputs()` samples, and `outputs` holds
+    /// `frames*num_outputs()` samples.
+    ///
+    /// The default implementation simply calls `tick` once per frame. Devices
+    /// should override this method when they can process a whole block more
+    /// efficiently.
+    fn tick_block(&mut self, t: Time, frames: usize, inputs: &[S],
+                  outputs: &mut[S]) {
+        let num_in = self.num_inputs();
+        let num_out = self.num_outputs();
+        for f in 0..frames {
+            self.tick(t + f as Time, &inputs[f*num_in..(f+1)*num_in],
+                      &mut outputs[f*num_out..(f+1)*num_out]);
+        }
+    }
+}
+
+impl<S, D: ?Sized> AudioDevice<S> for Box<D>
+        where S: SampleFloat, D: AudioDevice<S> {
+    fn num_inputs(&self) -> usize {
+        (**self).num_inputs()
+    }
+
+    fn num_outputs(&self) -> usize {
+        (**self).num_outputs()
+    }
+
+    fn tick(&mut self, t: Time, inputs: &[S], outputs: &mut[S]) {
+        (**self).tick(t, inputs, outputs);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        (**self).set_sample_rate(sample_rate);
+    }
+
+    fn prepare(&mut self, sample_rate: u32, block_size: usize) {
+        (**self).prepare(sample_rate, block_size);
+    }
+
+    fn reset(&mut self) {
+        (**self).reset();
+    }
+
+    fn latency(&self) -> usize {
+        (**self).latency()
+    }
+
+    fn set_transport(&mut self, transport: TransportHandle) {
+        (**self).set_transport(transport);
+    }
+
+    fn parameters(&self) -> Option<&Parameters> {
+        (**self).parameters()
+    }
+
+    fn parameters_mut(&mut self) -> Option<&mut Parameters> {
+        (**self).parameters_mut()
+    }
+
+    fn tick_block(&mut self, t: Time, frames: usize, inputs: &[S],
+                  outputs: &mut[S]) {
+        (**self).tick_block(t, frames, inputs, outputs);
+    }
+}
+
+
+/// An `AudioDevice` that can be downcast to its concrete type.
+///
+/// This is implemented for every `'static` device. Containers store their
+/// devices as `AnyDevice` trait objects, so that they can hand back typed
+/// references to them.
+///
+/// Note that a `Box<AnyDevice>` is itself a device; to downcast the device
+/// inside the box, call these methods on `*device`, not on the box.
+pub trait AnyDevice<S: SampleFloat = Sample>: AudioDevice<S> {
+    /// Returns the device as `Any`.
+    fn as_any(&self) -> &Any;
+
+    /// Returns the device as `Any`, mutably.
+    fn as_any_mut(&mut self) -> &mut Any;
+}
+
+impl<S, D> AnyDevice<S> for D where S: SampleFloat, D: 'static+AudioDevice<S> {
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut Any {
+        self
+    }
 }
 
 
@@ -5,13 +5,14 @@ use num::traits::Float;
 use types::Sample;
 
 /// Converts a decibel ratio to an amplitude multiplier.
-pub fn decibel_to_ratio(db: f32) -> f32 {
-    10.0.powf(db/10.0)
+pub fn decibel_to_ratio<F: Float>(db: F) -> F {
+    let ten = F::from(10.0).unwrap();
+    ten.powf(db/ten)
 }
 
 /// Converts an amplitude multiplier to decibels.
-pub fn ratio_to_decibel(ratio: f32) -> f32 {
-    10.0*ratio.log10()
+pub fn ratio_to_decibel<F: Float>(ratio: F) -> F {
+    F::from(10.0).unwrap()*ratio.log10()
 }
 
 /// Converts a MIDI note number to frequency in Hz.
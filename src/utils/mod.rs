@@ -3,4 +3,5 @@
 pub mod helpers;
 pub mod fft;
 pub mod ringbuffer;
+pub mod smoothed;
 pub mod windows;
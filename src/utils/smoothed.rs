@@ -0,0 +1,222 @@
+//! A value that ramps smoothly towards a target.
+//!
+//! Changing a parameter instantly, such as the gain of an amplifier, produces
+//! a discontinuity in the output that is heard as a click. When a parameter is
+//! swept, the many small jumps produce "zipper" noise. A `Smoothed` value
+//! instead ramps to each new target over a fixed amount of time.
+//!
+//! # Example
+//!
+//! ```
+//! use oxcable::utils::smoothed::{Smoothed, Linear};
+//!
+//! // Ramp linearly over 4 samples, at a sample rate of 4 Hz...
+//! let mut gain = Smoothed::new(0.0f32, Linear).ramp_time(1.0, 4);
+//! gain.set_target(1.0);
+//! assert_eq!(gain.tick(), 0.25);
+//! assert_eq!(gain.tick(), 0.5);
+//! assert_eq!(gain.tick(), 0.75);
+//! assert_eq!(gain.tick(), 1.0);
+//! assert!(!gain.is_ramping());
+//! ```
+
+use num::traits::Float;
+
+use types::SAMPLE_RATE;
+
+
+/// The ramp time used by the built-in devices, in seconds.
+pub const DEFAULT_RAMP_TIME: f32 = 0.01;
+
+
+/// The shape of a ramp between two values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ramp {
+    /// Changes by a constant amount each sample. This suits values such as
+    /// decibels, that are already perceptually linear.
+    Linear,
+    /// Changes by a constant ratio each sample. This suits values such as
+    /// frequencies and amplitudes, that are perceived logarithmically.
+    ///
+    /// Exponential ramps can't pass through zero; a ramp to or from zero, or
+    /// between values of different signs, falls back to a linear ramp.
+    Exponential,
+}
+pub use self::Ramp::*;
+
+
+/// A value that ramps smoothly towards a target.
+#[derive(Clone, Copy, Debug)]
+pub struct Smoothed<F: Float> {
+    value: F,
+    target: F,
+    step: F, // the amount added or ratio multiplied each sample
+    multiply: bool, // whether the current ramp multiplies by step
+    remaining: usize, // the samples left in the current ramp
+    ramp: Ramp,
+    ramp_time: f32,
+    sample_rate: u32,
+}
+
+impl<F: Float> Smoothed<F> {
+    /// Returns a value that starts at `value`, and ramps to new targets with
+    /// the provided shape.
+    ///
+    /// The ramp time defaults to zero, so that targets are reached instantly,
+    /// and the sample rate defaults to `SAMPLE_RATE`.
+    pub fn new(value: F, ramp: Ramp) -> Self {
+        Smoothed {
+            value: value,
+            target: value,
+            step: F::zero(),
+            multiply: false,
+            remaining: 0,
+            ramp: ramp,
+            ramp_time: 0.0,
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    /// Sets the time taken to reach a new target, in seconds, and the sample
+    /// rate the value is updated at, then return the same value.
+    pub fn ramp_time(mut self, seconds: f32, sample_rate: u32) -> Self {
+        self.ramp_time = seconds;
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Returns the time taken to reach a new target, in seconds.
+    pub fn get_ramp_time(&self) -> f32 {
+        self.ramp_time
+    }
+
+    /// Sets the time taken to reach a new target, in seconds.
+    ///
+    /// Any ramp already in progress will finish at its original speed.
+    pub fn set_ramp_time(&mut self, seconds: f32) {
+        self.ramp_time = seconds;
+    }
+
+    /// Sets the sample rate the value is updated at, in Hz.
+    ///
+    /// Any ramp already in progress will finish at its original speed.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Returns the current value.
+    pub fn get_value(&self) -> F {
+        self.value
+    }
+
+    /// Returns the value being ramped towards.
+    pub fn get_target(&self) -> F {
+        self.target
+    }
+
+    /// Returns true while the value is still ramping towards its target.
+    pub fn is_ramping(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// Starts ramping from the current value towards `target`.
+    pub fn set_target(&mut self, target: F) {
+        let samples = (self.ramp_time * self.sample_rate as f32).round();
+        self.target = target;
+        if samples < 1.0 || target == self.value {
+            self.finish();
+            return;
+        }
+
+        let n = F::from(samples).unwrap();
+        let ratio = target / self.value;
+        self.remaining = samples as usize;
+        self.multiply = self.ramp == Exponential && ratio > F::zero() &&
+            ratio.is_finite();
+        self.step = if self.multiply {
+            ratio.powf(n.recip())
+        } else {
+            (target - self.value) / n
+        };
+    }
+
+    /// Sets the value instantly, stopping any ramp in progress.
+    pub fn set_value(&mut self, value: F) {
+        self.target = value;
+        self.finish();
+    }
+
+    /// Jumps straight to the target, stopping any ramp in progress.
+    pub fn finish(&mut self) {
+        self.value = self.target;
+        self.remaining = 0;
+    }
+
+    /// Advances the value by one sample, and returns the new value.
+    pub fn tick(&mut self) -> F {
+        if self.remaining > 1 {
+            self.remaining -= 1;
+            self.value = if self.multiply {
+                self.value * self.step
+            } else {
+                self.value + self.step
+            };
+        } else if self.remaining == 1 {
+            self.finish();
+        }
+        self.value
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{Exponential, Linear, Smoothed};
+
+    #[test]
+    fn test_instant() {
+        let mut value = Smoothed::new(1.0f32, Linear);
+        value.set_target(2.0);
+        assert!(!value.is_ramping());
+        assert_eq!(value.tick(), 2.0);
+    }
+
+    #[test]
+    fn test_exponential() {
+        let mut value = Smoothed::new(1.0f32, Exponential).ramp_time(1.0, 3);
+        value.set_target(8.0);
+        assert!((value.tick() - 2.0).abs() < 1e-6);
+        assert!((value.tick() - 4.0).abs() < 1e-6);
+        assert_eq!(value.tick(), 8.0);
+        assert_eq!(value.tick(), 8.0);
+    }
+
+    #[test]
+    fn test_exponential_through_zero() {
+        let mut value = Smoothed::new(-1.0f32, Exponential).ramp_time(1.0, 2);
+        value.set_target(1.0);
+        assert_eq!(value.tick(), 0.0);
+        assert_eq!(value.tick(), 1.0);
+    }
+
+    #[test]
+    fn test_exponential_from_zero() {
+        let mut value = Smoothed::new(0.0f32, Exponential).ramp_time(1.0, 2);
+        value.set_target(1.0);
+        assert_eq!(value.tick(), 0.5);
+        assert_eq!(value.tick(), 1.0);
+    }
+
+    #[test]
+    fn test_retarget() {
+        let mut value = Smoothed::new(0.0f32, Linear).ramp_time(1.0, 4);
+        value.set_target(4.0);
+        value.tick();
+        value.tick();
+        value.set_target(0.0);
+        assert_eq!(value.get_target(), 0.0);
+        assert_eq!(value.tick(), 1.5);
+        value.finish();
+        assert_eq!(value.get_value(), 0.0);
+    }
+}
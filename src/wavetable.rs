@@ -0,0 +1,508 @@
+//! A band-limited wavetable oscillator.
+//!
+//! ## Wavetables
+//!
+//! A `Wavetable` holds one or more frames, each a single cycle of a waveform.
+//! Tables can be loaded from a slice of samples, or from a WAV file. A single
+//! cycle gives a fixed waveform, while a multi-frame table lets the oscillator
+//! sweep between waveforms.
+//!
+//! ## Antialiasing
+//!
+//! Playing a stored cycle back at a high pitch folds its upper harmonics back
+//! below the Nyquist frequency, producing aliasing. When a table is loaded,
+//! the FFT of each frame is used to build a band-limited copy of the frame for
+//! each octave, every copy keeping half the harmonics of the one before. The
+//! oscillator plays the copy with the most harmonics that still fit below the
+//! Nyquist frequency.
+//!
+//! ## Position
+//!
+//! The position selects the frame that is played, from 0 for the first frame
+//! to 1 for the last, crossfading between neighbouring frames. The position
+//! can be set using a message, and is modulated by the oscillator's input,
+//! which is added to it.
+//!
+//! ## Example
+//!
+//! The following builds a single-cycle saw wave, and plays it at 440 Hz:
+//!
+//! ```
+//! use oxcable::wavetable::{Wavetable, WavetableOscillator};
+//!
+//! let cycle: Vec<f32> = (0..256).map(|i| i as f32 / 128.0 - 1.0).collect();
+//! let table = Wavetable::from_samples(&cycle, 256).unwrap();
+//! let osc = WavetableOscillator::new(table).freq(440.0);
+//! ```
+
+use std::io::Read;
+use std::marker::PhantomData;
+use std::path::Path;
+use num::traits::Zero;
+
+use error::{Error, Result};
+use io::wav::WavReader;
+use parameters::{Hertz, Logarithmic, ParameterInfo, Parameters};
+use types::{SAMPLE_RATE, AudioDevice, MessageReceiver, Sample, SampleFloat,
+            Time};
+use utils::fft::{Complex32, Transformer};
+use utils::smoothed::{DEFAULT_RAMP_TIME, Exponential, Linear, Smoothed};
+
+
+/// The frequency of a new oscillator, in Hz.
+const DEFAULT_FREQ: f32 = 440.0;
+
+
+/// Defines the messages that the WavetableOscillator supports.
+#[derive(Clone, Copy, Debug)]
+pub enum Message {
+    /// Sets the frequency in Hz.
+    SetFreq(f32),
+    /// Sets the position in the wavetable, from 0 to 1.
+    SetPosition(f32),
+    /// Sets the time taken to ramp to a new position, in seconds.
+    SetRampTime(f32),
+    /// Sets the time taken to glide to a new frequency, in seconds. Defaults
+    /// to zero, which disables gliding.
+    SetGlideTime(f32),
+}
+pub use self::Message::*;
+
+
+/// A set of single-cycle waveforms, with band-limited copies of each.
+#[derive(Clone, Debug)]
+pub struct Wavetable {
+    size: usize, // the number of samples in each cycle
+    frames: Vec<Vec<Vec<f32>>>, // the band-limited copies of each frame
+}
+
+impl Wavetable {
+    /// Returns a wavetable holding the provided samples, split into frames of
+    /// `frame_size` samples. Any samples left over after the last full frame
+    /// are ignored.
+    ///
+    /// Frames are resampled up to the next power of two, if needed.
+    ///
+    /// Returns an Err if there are fewer than two samples in a frame, or not
+    /// enough samples to fill a single frame.
+    pub fn from_samples(samples: &[f32], frame_size: usize) -> Result<Self> {
+        if frame_size < 2 || samples.len() < frame_size {
+            return Err(Error::OutOfRange("frame size"));
+        }
+        let size = frame_size.next_power_of_two();
+        let transformer = Transformer::new(size);
+        let frames = samples.chunks(frame_size)
+            .filter(|cycle| cycle.len() == frame_size)
+            .map(|cycle| band_limit(&transformer, &resample(cycle, size)))
+            .collect();
+        Ok(Wavetable { size: size, frames: frames })
+    }
+
+    /// Returns a wavetable holding the rest of the samples from a WAV reader,
+    /// split into frames of `frame_size` samples. The channels of the file are
+    /// averaged together.
+    ///
+    /// See `from_samples`.
+    pub fn from_wav<R: Read>(reader: &mut WavReader<R>, frame_size: usize)
+            -> Result<Self> {
+        let channels = reader.num_outputs();
+        let mut frame = vec![0.0; channels];
+        let mut samples = Vec::new();
+        let mut t = 0;
+        while !reader.is_done() {
+            reader.tick(t, &[], &mut frame);
+            samples.push(frame.iter().sum::<f32>() / channels as f32);
+            t += 1;
+        }
+        Wavetable::from_samples(&samples, frame_size)
+    }
+
+    /// Returns a wavetable holding the samples of a WAV file, split into
+    /// frames of `frame_size` samples.
+    ///
+    /// See `from_wav`.
+    pub fn open<P: AsRef<Path>>(filename: P, frame_size: usize)
+            -> Result<Self> {
+        let mut reader = try!(WavReader::open(filename));
+        Wavetable::from_wav(&mut reader, frame_size)
+    }
+
+    /// Returns the number of frames in the table.
+    pub fn get_num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns the number of samples in each cycle, after resampling.
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the number of band-limited copies of each frame.
+    pub fn get_num_levels(&self) -> usize {
+        self.frames[0].len()
+    }
+
+    /// Returns the band-limited copy to play for a frequency of `delta`
+    /// cycles per sample.
+    fn level(&self, delta: f32) -> usize {
+        let octaves = (self.size as f32 * delta.abs()).log2().ceil();
+        if octaves > 0.0 {
+            (octaves as usize).min(self.get_num_levels() - 1)
+        } else {
+            0
+        }
+    }
+
+    /// Returns the sample at `phase`, from 0 to 1, of a band-limited copy, at
+    /// `position` between the first and last frame.
+    fn sample(&self, position: f32, level: usize, phase: f32) -> f32 {
+        let last = self.frames.len() - 1;
+        let x = position.clamp(0.0, 1.0) * last as f32;
+        let i = (x.floor() as usize).min(last);
+        let frac = x - i as f32;
+        let a = interpolate(&self.frames[i][level], phase);
+        if frac > 0.0 {
+            let b = interpolate(&self.frames[i+1][level], phase);
+            a + frac*(b - a)
+        } else {
+            a
+        }
+    }
+}
+
+
+/// An oscillator that plays a wavetable.
+pub struct WavetableOscillator<S: SampleFloat = Sample> {
+    table: Wavetable,
+    sample_rate: u32,
+    freq: Smoothed<f32>,
+    position: Smoothed<f32>,
+    phase: f32, // the position in the cycle, from 0 to 1
+    sample: PhantomData<S>
+}
+
+impl WavetableOscillator {
+    /// Returns an oscillator playing the provided wavetable, at the default
+    /// frequency of 440 Hz.
+    pub fn new(table: Wavetable) -> Self {
+        WavetableOscillator::new_generic(table)
+    }
+}
+
+impl<S: SampleFloat> WavetableOscillator<S> {
+    /// Returns an oscillator processing any sample type. `new` returns the
+    /// same device for the default `Sample` type.
+    pub fn new_generic(table: Wavetable) -> Self {
+        WavetableOscillator {
+            table: table,
+            sample_rate: SAMPLE_RATE,
+            freq: Smoothed::new(DEFAULT_FREQ, Exponential)
+                .ramp_time(0.0, SAMPLE_RATE),
+            position: Smoothed::new(0.0, Linear)
+                .ramp_time(DEFAULT_RAMP_TIME, SAMPLE_RATE),
+            phase: 0.0,
+            sample: PhantomData
+        }
+    }
+
+    /// Sets the frequency of the waveform, and return the same oscillator.
+    pub fn freq(mut self, freq: f32) -> Self {
+        self.handle_message(SetFreq(freq));
+        self.freq.finish();
+        self
+    }
+
+    /// Sets the position in the wavetable, and return the same oscillator.
+    pub fn position(mut self, position: f32) -> Self {
+        self.handle_message(SetPosition(position));
+        self.position.finish();
+        self
+    }
+
+    /// Sets the time taken to ramp to new values (in seconds), and return the
+    /// same oscillator.
+    pub fn ramp_time(mut self, seconds: f32) -> Self {
+        self.handle_message(SetRampTime(seconds));
+        self
+    }
+
+    /// Sets the time taken to glide to a new frequency (in seconds), and
+    /// return the same oscillator.
+    pub fn glide_time(mut self, seconds: f32) -> Self {
+        self.handle_message(SetGlideTime(seconds));
+        self
+    }
+
+    /// Returns the wavetable being played.
+    pub fn get_table(&self) -> &Wavetable {
+        &self.table
+    }
+
+    /// Replaces the wavetable being played. The phase of the oscillator is
+    /// kept.
+    pub fn set_table(&mut self, table: Wavetable) {
+        self.table = table;
+    }
+
+    /// Computes the next sample, then advances the phase by one time step.
+    ///
+    /// `modulation` is added to the position.
+    fn next_sample(&mut self, modulation: S) -> S {
+        let delta = self.freq.tick() / self.sample_rate as f32;
+        let position = self.position.tick() + modulation.into_f32();
+        let level = self.table.level(delta);
+        let sample = self.table.sample(position, level, self.phase);
+
+        self.phase += delta;
+        self.phase = self.phase - self.phase.floor();
+        S::from_f32(sample)
+    }
+}
+
+impl<S: SampleFloat> MessageReceiver for WavetableOscillator<S> {
+    type Msg = Message;
+    fn handle_message(&mut self, msg: Message) {
+        match msg {
+            SetFreq(freq) => {
+                self.freq.set_target(freq);
+            },
+            SetPosition(position) => {
+                self.position.set_target(position.clamp(0.0, 1.0));
+            },
+            SetRampTime(seconds) => {
+                self.position.set_ramp_time(seconds);
+            },
+            SetGlideTime(seconds) => {
+                self.freq.set_ramp_time(seconds);
+            }
+        }
+    }
+}
+
+impl<S: SampleFloat> Parameters for WavetableOscillator<S> {
+    fn num_parameters(&self) -> usize {
+        2
+    }
+
+    fn get_parameter_info(&self, i: usize) -> ParameterInfo {
+        match i {
+            0 => ParameterInfo::new("frequency", 1.0, 20000.0, DEFAULT_FREQ)
+                .unit(Hertz).scale(Logarithmic),
+            1 => ParameterInfo::new("position", 0.0, 1.0, 0.0),
+            _ => panic!("WavetableOscillator: parameter index {} out of range",
+                        i)
+        }
+    }
+
+    fn get_parameter(&self, i: usize) -> f32 {
+        match i {
+            0 => self.freq.get_target(),
+            1 => self.position.get_target(),
+            _ => panic!("WavetableOscillator: parameter index {} out of range",
+                        i)
+        }
+    }
+
+    fn set_parameter(&mut self, i: usize, value: f32) {
+        let value = self.get_parameter_info(i).clamp(value);
+        let msg = match i {
+            0 => SetFreq(value),
+            _ => SetPosition(value)
+        };
+        self.handle_message(msg);
+    }
+}
+
+impl<S: SampleFloat> AudioDevice<S> for WavetableOscillator<S> {
+    fn num_inputs(&self) -> usize {
+        1
+    }
+
+    fn num_outputs(&self) -> usize {
+        1
+    }
+
+    fn parameters(&self) -> Option<&Parameters> {
+        Some(self)
+    }
+
+    fn parameters_mut(&mut self) -> Option<&mut Parameters> {
+        Some(self)
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.freq.set_sample_rate(sample_rate);
+        self.position.set_sample_rate(sample_rate);
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0.0;
+        self.freq.finish();
+        self.position.finish();
+    }
+
+    fn tick(&mut self, _: Time, inputs: &[S], outputs: &mut[S]) {
+        outputs[0] = self.next_sample(inputs.first().map_or(S::zero(), |&s| s));
+    }
+
+    fn tick_block(&mut self, _: Time, frames: usize, inputs: &[S],
+                  outputs: &mut[S]) {
+        if inputs.len() < frames {
+            for s in outputs[..frames].iter_mut() {
+                *s = self.next_sample(S::zero());
+            }
+        } else {
+            for (s, &modulation) in outputs[..frames].iter_mut().zip(inputs) {
+                *s = self.next_sample(modulation);
+            }
+        }
+    }
+}
+
+
+/// Resamples a single cycle to `size` samples, using linear interpolation.
+fn resample(cycle: &[f32], size: usize) -> Vec<f32> {
+    if cycle.len() == size {
+        return cycle.to_vec();
+    }
+    (0..size).map(|i| interpolate(cycle, i as f32 / size as f32)).collect()
+}
+
+/// Returns the band-limited copies of a cycle, whose length must be a power
+/// of two. The first copy keeps every harmonic, and each following copy keeps
+/// half the harmonics of the one before, down to just the fundamental.
+fn band_limit(transformer: &Transformer, cycle: &[f32]) -> Vec<Vec<f32>> {
+    let size = cycle.len();
+    let input: Vec<_> = cycle.iter().map(|&s| Complex32::new(s, 0.0))
+        .collect();
+    let mut spectrum = vec![Complex32::zero(); size];
+    transformer.fft(&input, &mut spectrum);
+
+    let mut levels = Vec::new();
+    let mut bins = vec![Complex32::zero(); size];
+    let mut output = vec![Complex32::zero(); size];
+    let mut harmonics = size / 2;
+    while harmonics > 0 {
+        // Keep each harmonic along with its negative frequency
+        for (i, bin) in bins.iter_mut().enumerate() {
+            *bin = if i <= harmonics || i >= size - harmonics {
+                spectrum[i]
+            } else {
+                Complex32::zero()
+            };
+        }
+        transformer.ifft(&bins, &mut output);
+        levels.push(output.iter().map(|s| s.re).collect());
+        harmonics /= 2;
+    }
+    levels
+}
+
+/// Returns the sample at `phase`, from 0 to 1, of a single cycle, using linear
+/// interpolation.
+fn interpolate(cycle: &[f32], phase: f32) -> f32 {
+    let x = phase * cycle.len() as f32;
+    let i = x.floor() as usize % cycle.len();
+    let frac = x - x.floor();
+    let a = cycle[i];
+    let b = cycle[(i+1) % cycle.len()];
+    a + frac*(b - a)
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+    use std::io::Cursor;
+
+    use io::wav::{WavReader, WavWriter};
+    use parameters::Parameters;
+    use testing::flt_eq_eps;
+    use types::{AudioDevice, MessageReceiver};
+    use super::{SetPosition, Wavetable, WavetableOscillator};
+
+    fn sine(size: usize) -> Vec<f32> {
+        (0..size).map(|i| (2.0*PI*i as f32/size as f32).sin()).collect()
+    }
+
+    #[test]
+    fn test_sine() {
+        let table = Wavetable::from_samples(&sine(64), 64).unwrap();
+        assert_eq!(table.get_num_levels(), 6);
+        let mut osc = WavetableOscillator::new(table).freq(44100.0/16.0);
+        let mut output = [0.0; 16];
+        osc.tick_block(0, 16, &[], &mut output);
+        for (i, &s) in output.iter().enumerate() {
+            assert!(flt_eq_eps(s, (2.0*PI*i as f32/16.0).sin(), 1e-5));
+        }
+    }
+
+    #[test]
+    fn test_band_limit() {
+        let square: Vec<f32> = (0..64).map(|i| if i < 32 { 1.0 } else { -1.0 })
+            .collect();
+        let table = Wavetable::from_samples(&square, 64).unwrap();
+        assert_eq!(table.level(1.0/64.0), 0);
+        assert_eq!(table.level(1.0/32.0), 1);
+        assert_eq!(table.level(0.4), 5);
+
+        // The first copy keeps the whole waveform
+        let levels = &table.frames[0];
+        for (&a, &b) in levels[0].iter().zip(square.iter()) {
+            assert!(flt_eq_eps(a, b, 1e-5));
+        }
+
+        // The last copy is a smooth sine
+        let last = &levels[levels.len()-1];
+        for i in 0..64 {
+            assert!((last[(i+1) % 64] - last[i]).abs() < 0.15);
+        }
+    }
+
+    #[test]
+    fn test_position() {
+        let mut samples = vec![0.0; 8];
+        samples.extend(vec![1.0; 8]);
+        let table = Wavetable::from_samples(&samples, 8).unwrap();
+        assert_eq!(table.get_num_frames(), 2);
+
+        let mut osc =
+            WavetableOscillator::new(table).freq(100.0).ramp_time(0.0);
+        let mut output = [0.0];
+        osc.tick(0, &[0.25], &mut output);
+        assert!(flt_eq_eps(output[0], 0.25, 1e-6));
+        osc.handle_message(SetPosition(0.5));
+        osc.tick(1, &[0.25], &mut output);
+        assert!(flt_eq_eps(output[0], 0.75, 1e-6));
+        osc.tick(2, &[1.0], &mut output);
+        assert!(flt_eq_eps(output[0], 1.0, 1e-6));
+    }
+
+    #[test]
+    fn test_wav() {
+        let mut buffer = [0u8; 44 + 8*4];
+        {
+            let cursor = Cursor::new(&mut buffer[..]);
+            let mut writer = WavWriter::new(cursor, 2).unwrap();
+            for t in 0..8 {
+                writer.tick(t, &[0.25, 0.75], &mut[]);
+            }
+        }
+        let mut reader = WavReader::new(Cursor::new(&buffer[..])).unwrap();
+        let table = Wavetable::from_wav(&mut reader, 8).unwrap();
+        assert_eq!(table.get_size(), 8);
+        assert!(flt_eq_eps(table.sample(0.0, 0, 0.5), 0.5, 1e-3));
+        assert!(Wavetable::from_samples(&[0.0; 4], 8).is_err());
+    }
+
+    #[test]
+    fn test_parameters() {
+        let table = Wavetable::from_samples(&sine(64), 64).unwrap();
+        let osc = WavetableOscillator::new(table);
+        for i in 0..osc.num_parameters() {
+            let info = osc.get_parameter_info(i);
+            assert_eq!(osc.get_parameter(i), info.default);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::ops::{Deref, DerefMut};
 
-use types::{AudioDevice, Sample, Time};
+use types::{AudioDevice, Sample, SampleFloat, Time};
 
 
 /// Bundles an `AudioDevice` with allocated input and output buffers.
@@ -31,42 +31,43 @@ use types::{AudioDevice, Sample, Time};
 ///     assert_eq!(i as f32, filter.outputs[0]);
 /// }
 /// ```
-pub struct Buffered<D> where D: AudioDevice {
+pub struct Buffered<D, S=Sample> where D: AudioDevice<S>, S: SampleFloat {
     /// The AudioDevice being wrapped.
     pub device: D,
     /// The input buffer.
-    pub inputs: Vec<Sample>,
+    pub inputs: Vec<S>,
     /// The output buffer.
-    pub outputs: Vec<Sample>,
+    pub outputs: Vec<S>,
 }
 
-impl<D> Buffered<D> where D: AudioDevice {
+impl<D, S> Buffered<D, S> where D: AudioDevice<S>, S: SampleFloat {
     /// Calls the device's tick method using the wrapper's buffers.
     pub fn tick(&mut self, t: Time) {
         self.device.tick(t, &self.inputs, &mut self.outputs);
     }
 }
 
-impl<D> From<D> for Buffered<D> where D: AudioDevice {
+impl<D, S> From<D> for Buffered<D, S> where D: AudioDevice<S>, S: SampleFloat {
     fn from(device: D) -> Self {
         let inputs = device.num_inputs();
         let outputs = device.num_outputs();
         Buffered {
             device: device,
-            inputs: vec![0.0; inputs],
-            outputs: vec![0.0; outputs],
+            inputs: vec![S::zero(); inputs],
+            outputs: vec![S::zero(); outputs],
         }
     }
 }
 
-impl<D> Deref for Buffered<D> where D: AudioDevice {
+impl<D, S> Deref for Buffered<D, S> where D: AudioDevice<S>, S: SampleFloat {
     type Target = D;
     fn deref(&self) -> &D {
         &self.device
     }
 }
 
-impl<D> DerefMut for Buffered<D> where D: AudioDevice {
+impl<D, S> DerefMut for Buffered<D, S>
+        where D: AudioDevice<S>, S: SampleFloat {
     fn deref_mut(&mut self) -> &mut D {
         &mut self.device
     }
@@ -1,6 +1,6 @@
 use std::ops::{Deref, DerefMut};
 
-use types::{AudioDevice, Sample, Time};
+use types::{AudioDevice, Sample, SampleFloat, Time};
 
 
 /// Bundles an `AudioDevice` with an allocated output buffer.
@@ -30,38 +30,41 @@ use types::{AudioDevice, Sample, Time};
 ///     assert_eq!(i as f32, filter.outputs[0]);
 /// }
 /// ```
-pub struct BufferedOutput<D> where D: AudioDevice {
+pub struct BufferedOutput<D, S=Sample> where D: AudioDevice<S>, S: SampleFloat {
     /// The AudioDevice being wrapped.
     pub device: D,
     /// The output buffer.
-    pub outputs: Vec<Sample>,
+    pub outputs: Vec<S>,
 }
 
-impl<D> BufferedOutput<D> where D: AudioDevice {
+impl<D, S> BufferedOutput<D, S> where D: AudioDevice<S>, S: SampleFloat {
     /// Calls the device's tick method using the wrapper's buffers.
-    pub fn tick(&mut self, t: Time, inputs: &[Sample]) {
+    pub fn tick(&mut self, t: Time, inputs: &[S]) {
         self.device.tick(t, inputs, &mut self.outputs);
     }
 }
 
-impl<D> From<D> for BufferedOutput<D> where D: AudioDevice {
+impl<D, S> From<D> for BufferedOutput<D, S>
+        where D: AudioDevice<S>, S: SampleFloat {
     fn from(device: D) -> Self {
         let outputs = device.num_outputs();
         BufferedOutput {
             device: device,
-            outputs: vec![0.0; outputs],
+            outputs: vec![S::zero(); outputs],
         }
     }
 }
 
-impl<D> Deref for BufferedOutput<D> where D: AudioDevice {
+impl<D, S> Deref for BufferedOutput<D, S>
+        where D: AudioDevice<S>, S: SampleFloat {
     type Target = D;
     fn deref(&self) -> &D {
         &self.device
     }
 }
 
-impl<D> DerefMut for BufferedOutput<D> where D: AudioDevice {
+impl<D, S> DerefMut for BufferedOutput<D, S>
+        where D: AudioDevice<S>, S: SampleFloat {
     fn deref_mut(&mut self) -> &mut D {
         &mut self.device
     }
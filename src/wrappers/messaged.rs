@@ -1,7 +1,10 @@
+use std::collections::VecDeque;
 use std::ops::{Deref, DerefMut};
 use std::sync::mpsc::{channel, Sender, Receiver};
 
-use types::{AudioDevice, MessageReceiver, Sample, Time};
+use parameters::{ParameterInfo, Parameters};
+use transport::TransportHandle;
+use types::{AudioDevice, MessageReceiver, SampleFloat, Time};
 
 
 /// Bundles a `MessageReceiver` with threaded message passing.
@@ -24,6 +27,22 @@ use types::{AudioDevice, MessageReceiver, Sample, Time};
 /// `DeviceChain` or `DeviceGraph`. It will automatically receive and handle its
 /// own messages while processing.
 ///
+/// # Timed messages
+///
+/// Messages from `get_sender` are applied at the start of whichever tick
+/// follows their arrival, so their timing depends on when the sending thread
+/// runs. For sample accurate timing, a message can instead be sent with the
+/// time it should take effect, using `get_timed_sender` or `schedule`.
+///
+/// Timed messages are held in a queue ordered by time, and each is applied
+/// right before the device processes that time step. Messages for the same
+/// time are applied in the order they were received, and messages for a time
+/// that has already passed are applied at the next tick. When processing a
+/// block, the wrapper splits the block at each message, so messages take
+/// effect on the exact frame regardless of the block size.
+///
+/// The wrapper also passes through the `Parameters` of the contained device.
+///
 /// # Example
 ///
 /// ```no_run
@@ -61,6 +80,9 @@ pub struct Messaged<D> where D: MessageReceiver {
     device: D,
     tx: Sender<D::Msg>,
     rx: Receiver<D::Msg>,
+    timed_tx: Sender<(Time, D::Msg)>,
+    timed_rx: Receiver<(Time, D::Msg)>,
+    queue: VecDeque<(Time, D::Msg)>, // timed messages, ordered by time
 }
 
 impl<D> Messaged<D> where D: MessageReceiver {
@@ -68,20 +90,59 @@ impl<D> Messaged<D> where D: MessageReceiver {
     pub fn get_sender(&self) -> Sender<D::Msg> {
         self.tx.clone()
     }
+
+    /// Return the sending half of our channel for timed messages.
+    ///
+    /// Each message is sent along with the time step it should be applied at.
+    pub fn get_timed_sender(&self) -> Sender<(Time, D::Msg)> {
+        self.timed_tx.clone()
+    }
+
+    /// Schedules a message to be applied at time `t`.
+    pub fn schedule(&mut self, t: Time, msg: D::Msg) {
+        // Insert after any messages for the same time, so they stay in order
+        let i = self.queue.iter().position(|&(qt, _)| qt > t)
+            .unwrap_or(self.queue.len());
+        self.queue.insert(i, (t, msg));
+    }
+
+    /// Handles all untimed messages, and moves any timed messages into the
+    /// queue.
+    fn receive_messages(&mut self) {
+        while let Ok(msg) = self.rx.try_recv() {
+            self.device.handle_message(msg);
+        }
+        while let Ok((t, msg)) = self.timed_rx.try_recv() {
+            self.schedule(t, msg);
+        }
+    }
+
+    /// Handles every queued message scheduled at or before time `t`.
+    fn handle_messages_until(&mut self, t: Time) {
+        while self.queue.front().is_some_and(|&(qt, _)| qt <= t) {
+            let (_, msg) = self.queue.pop_front().unwrap();
+            self.device.handle_message(msg);
+        }
+    }
 }
 
 impl<D> From<D> for Messaged<D> where D: MessageReceiver {
     fn from(device: D) -> Self {
         let (tx, rx) = channel();
+        let (timed_tx, timed_rx) = channel();
         Messaged {
             device: device,
             tx: tx,
             rx: rx,
+            timed_tx: timed_tx,
+            timed_rx: timed_rx,
+            queue: VecDeque::new(),
         }
     }
 }
 
-impl<D> AudioDevice for Messaged<D> where D: AudioDevice+MessageReceiver {
+impl<D, S> AudioDevice<S> for Messaged<D>
+        where D: AudioDevice<S>+MessageReceiver, S: SampleFloat {
     fn num_inputs(&self) -> usize {
         self.device.num_inputs()
     }
@@ -90,26 +151,121 @@ impl<D> AudioDevice for Messaged<D> where D: AudioDevice+MessageReceiver {
         self.device.num_outputs()
     }
 
-    fn tick(&mut self, t: Time, inputs: &[Sample], outputs: &mut[Sample]) {
-        loop {
-            match self.rx.try_recv() {
-                Ok(msg) => self.device.handle_message(msg),
-                _ => break
-            }
-        }
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.device.set_sample_rate(sample_rate);
+    }
+
+    fn prepare(&mut self, sample_rate: u32, block_size: usize) {
+        self.device.prepare(sample_rate, block_size);
+    }
+
+    fn reset(&mut self) {
+        self.device.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.device.latency()
+    }
+
+    fn set_transport(&mut self, transport: TransportHandle) {
+        self.device.set_transport(transport);
+    }
+
+    fn parameters(&self) -> Option<&Parameters> {
+        self.device.parameters()
+    }
+
+    fn parameters_mut(&mut self) -> Option<&mut Parameters> {
+        self.device.parameters_mut()
+    }
+
+    fn tick(&mut self, t: Time, inputs: &[S], outputs: &mut[S]) {
+        self.receive_messages();
+        self.handle_messages_until(t);
         self.device.tick(t, inputs, outputs);
     }
+
+    fn tick_block(&mut self, t: Time, frames: usize, inputs: &[S],
+                  outputs: &mut[S]) {
+        self.receive_messages();
+        let num_in = self.device.num_inputs();
+        let num_out = self.device.num_outputs();
+        let end = t + frames as Time;
+
+        // Process up to each message in the block, then apply it
+        let mut start = t;
+        while start < end {
+            self.handle_messages_until(start);
+            let stop = match self.queue.front() {
+                Some(&(qt, _)) if qt < end => qt,
+                _ => end
+            };
+            let (i, j) = ((start-t) as usize, (stop-t) as usize);
+            self.device.tick_block(start, j-i, &inputs[i*num_in..j*num_in],
+                                   &mut outputs[i*num_out..j*num_out]);
+            start = stop;
+        }
+    }
+}
+
+impl<D> Parameters for Messaged<D> where D: Parameters+MessageReceiver {
+    fn num_parameters(&self) -> usize {
+        self.device.num_parameters()
+    }
+
+    fn get_parameter_info(&self, i: usize) -> ParameterInfo {
+        self.device.get_parameter_info(i)
+    }
+
+    fn get_parameter(&self, i: usize) -> f32 {
+        self.device.get_parameter(i)
+    }
+
+    fn set_parameter(&mut self, i: usize, value: f32) {
+        self.device.set_parameter(i, value);
+    }
 }
 
-impl<D> Deref for Messaged<D> where D: AudioDevice+MessageReceiver {
+impl<D> Deref for Messaged<D> where D: MessageReceiver {
     type Target = D;
     fn deref(&self) -> &D {
         &self.device
     }
 }
 
-impl<D> DerefMut for Messaged<D> where D: AudioDevice+MessageReceiver {
+impl<D> DerefMut for Messaged<D> where D: MessageReceiver {
     fn deref_mut(&mut self) -> &mut D {
         &mut self.device
     }
 }
+
+
+#[cfg(test)]
+mod test {
+    use mixers::multiplexer::{Multiplexer, SelectChannel};
+    use types::AudioDevice;
+    use super::Messaged;
+
+    #[test]
+    fn test_timed_messages() {
+        let mut mux = Messaged::from(Multiplexer::new(2));
+        let tx = mux.get_timed_sender();
+        tx.send((3, SelectChannel(0))).unwrap();
+        tx.send((1, SelectChannel(1))).unwrap();
+
+        let inputs = [1.0, 2.0, 1.0, 2.0, 1.0, 2.0, 1.0, 2.0];
+        let mut outputs = [0.0; 4];
+        mux.tick_block(0, 4, &inputs, &mut outputs);
+        assert_eq!(outputs, [1.0, 2.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn test_late_message() {
+        let mut mux = Messaged::from(Multiplexer::new(2));
+        mux.schedule(0, SelectChannel(1));
+
+        let mut outputs = [0.0; 2];
+        mux.tick_block(2, 2, &[1.0, 2.0, 1.0, 2.0], &mut outputs);
+        assert_eq!(outputs, [2.0, 2.0]);
+    }
+}